@@ -0,0 +1,317 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::linalg::{center_rows, gram_matrix, orthogonal_procrustes, symmetric_eigen};
+use crate::nnet::NeuralNet;
+use crate::vocab::Vocabulary;
+
+impl NeuralNet {
+    /// @return the input vectors (`syn0` rows) of all words as one row-major matrix
+    fn input_matrix(&self) -> Vec<f32> {
+        (0..self.vocab_size())
+            .flat_map(|idx| self.word_vector(idx).iter().copied())
+            .collect()
+    }
+
+    /// Estimate how many dimensions the word vectors actually use, as the effective
+    /// rank (Roy & Vetterli) of the `syn0` matrix: the exponential of the entropy of
+    /// its normalized singular values. The result is between 1 and `layer1_size`, or 0
+    /// if all vectors are zero vectors.
+    ///
+    /// The singular values are obtained from the eigenvalues of the
+    /// `layer1_size` x `layer1_size` Gram matrix, which takes
+    /// O(vocab_size * layer1_size^2) time to compute.
+    pub fn effective_rank(&self) -> f64 {
+        let dim = self.layer1_size();
+        let gram = gram_matrix(&self.input_matrix(), dim);
+        let (eigenvalues, _) = symmetric_eigen(gram, dim);
+
+        let singular_values: Vec<f64> = eigenvalues.iter().map(|x| x.max(0.0).sqrt()).collect();
+        let total: f64 = singular_values.iter().sum();
+        if total == 0.0 {
+            return 0.0;
+        }
+
+        let entropy: f64 = singular_values
+            .iter()
+            .map(|s| s / total)
+            .filter(|&p| p > 0.0)
+            .map(|p| -p * p.ln())
+            .sum();
+        entropy.exp()
+    }
+
+    /// Compute the fraction of the variance of the input vectors captured by their top
+    /// `k` principal components. A value near 1 for a small `k` means the vectors are
+    /// concentrated in a few directions, a sign of a collapsed model. The principal
+    /// components are the eigenvectors of the covariance matrix of the mean-centered
+    /// vectors, which takes O(vocab_size * layer1_size^2) time to compute.
+    /// @return the ratio, between 0 and 1 (1 if `k` >= `layer1_size`), 0 if all
+    /// vectors are equal
+    pub fn top_k_variance_ratio(&self, k: usize) -> f32 {
+        let dim = self.layer1_size();
+        let mut rows = self.input_matrix();
+        center_rows(&mut rows, dim);
+
+        let (eigenvalues, _) = symmetric_eigen(gram_matrix(&rows, dim), dim);
+        let total: f64 = eigenvalues.iter().map(|x| x.max(0.0)).sum();
+        if total == 0.0 {
+            return 0.0;
+        }
+        let top: f64 = eigenvalues.iter().take(k).map(|x| x.max(0.0)).sum();
+        (top / total).min(1.0) as f32
+    }
+
+    /// Project the input vectors of `word_indices` onto their top `dims` principal
+    /// components, e.g. to plot them in 2D. The vectors are centered on their mean,
+    /// and each component is oriented so that the first word has a non-negative
+    /// coordinate, which keeps the projections of successive training snapshots from
+    /// flipping.
+    /// @return the row-major N x `dims` matrix of the coordinates of the N words,
+    /// coordinates beyond `layer1_size` components being 0
+    pub fn pca_projection(&self, word_indices: &[usize], dims: usize) -> Vec<f32> {
+        let dim = self.layer1_size();
+        let mut rows: Vec<f32> = word_indices
+            .iter()
+            .flat_map(|&idx| self.word_vector(idx).iter().copied())
+            .collect();
+        let mut result: Vec<f32> = vec![0.0; word_indices.len() * dims];
+        if rows.is_empty() {
+            return result;
+        }
+        center_rows(&mut rows, dim);
+
+        let (_, eigenvectors) = symmetric_eigen(gram_matrix(&rows, dim), dim);
+        for (c, component) in eigenvectors.chunks_exact(dim).take(dims).enumerate() {
+            let project = |row: &[f32]| -> f64 {
+                row.iter().zip(component).map(|(&x, v)| x as f64 * v).sum()
+            };
+            let sign = if project(&rows[..dim]) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            for (i, row) in rows.chunks_exact(dim).enumerate() {
+                result[i * dims + c] = (sign * project(row)) as f32;
+            }
+        }
+        result
+    }
+
+    /// Save the 2D `pca_projection` of the `k` most frequent words besides "</s>" to
+    /// `file_name`, one "word x y" line per word, most frequent first, as written
+    /// during training for `TrainigParams::snapshot_prefix`
+    pub fn save_pca_snapshot(
+        &self,
+        vocab: &Vocabulary,
+        k: usize,
+        file_name: &str,
+    ) -> std::io::Result<()> {
+        let indices: Vec<usize> = (1..self.vocab_size().min(vocab.len())).take(k).collect();
+        let coords = self.pca_projection(&indices, 2);
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(file_name)?);
+        for (&idx, xy) in indices.iter().zip(coords.chunks_exact(2)) {
+            writeln!(
+                buf_writer,
+                "{} {:.06} {:.06}",
+                vocab.word(idx),
+                xy[0],
+                xy[1]
+            )?;
+        }
+        buf_writer.flush()
+    }
+
+    /// Rotate the vectors into the space of `other`, e.g. a model trained on another
+    /// corpus, so that the vectors of both models can be compared directly. The
+    /// rotation is the orthogonal matrix that best maps the vectors of the words found
+    /// in both vocabularies (except "</s>") onto their vectors in `other`, in the
+    /// least squares sense (orthogonal Procrustes). Rotating doesn't change the
+    /// similarities within this model.
+    /// @return a net holding the rotated input vectors of all words of this model,
+    /// without an output layer
+    pub fn align_to(
+        &self,
+        vocab: &Vocabulary,
+        other: &NeuralNet,
+        other_vocab: &Vocabulary,
+    ) -> NeuralNet {
+        let dim = self.layer1_size();
+        assert!(
+            other.layer1_size() == dim,
+            "the models must have the same vector size"
+        );
+
+        // m = X^T Y over the shared words
+        let mut m: Vec<f64> = vec![0.0; dim * dim];
+        for idx in 1..vocab.len().min(self.vocab_size()) {
+            let other_idx = other_vocab.search_word(vocab.word(idx));
+            if other_idx <= 0 || other_idx as usize >= other.vocab_size() {
+                continue;
+            }
+            let (x, y) = (self.word_vector(idx), other.word_vector(other_idx as usize));
+            for i in 0..dim {
+                for j in 0..dim {
+                    m[i * dim + j] += x[i] as f64 * y[j] as f64;
+                }
+            }
+        }
+        let rotation = orthogonal_procrustes(&m, dim);
+
+        let mut syn0: Vec<f32> = Vec::with_capacity(self.vocab_size() * dim);
+        for idx in 0..self.vocab_size() {
+            let x = self.word_vector(idx);
+            syn0.extend((0..dim).map(|j| {
+                (0..dim)
+                    .map(|i| x[i] as f64 * rotation[i * dim + j])
+                    .sum::<f64>() as f32
+            }));
+        }
+        NeuralNet::from_input_vectors(dim, syn0)
+    }
+
+    /// Compute the mean input vector of the words of each frequency decile, to see
+    /// whether frequent and rare words occupy different regions of the vector space.
+    /// The words besides "</s>" are split by their rank in `vocab`, which is sorted
+    /// by decreasing count, into 10 groups of (nearly) equal size.
+    /// @return 10 centroids, the first one of the most frequent words; the centroid of
+    /// an empty decile (for less than 10 words) is a zero vector
+    pub fn frequency_decile_centroids(&self, vocab: &Vocabulary) -> Vec<Vec<f32>> {
+        const DECILES: usize = 10;
+        let mut centroids: Vec<Vec<f32>> = vec![vec![0.0; self.layer1_size()]; DECILES];
+        let mut counts: [usize; DECILES] = [0; DECILES];
+
+        // skip word 0, the "</s>" sentence separator
+        let words = vocab.len().min(self.vocab_size()).saturating_sub(1);
+        for rank in 0..words {
+            let decile = rank * DECILES / words;
+            for (c, v) in centroids[decile].iter_mut().zip(self.word_vector(rank + 1)) {
+                *c += v;
+            }
+            counts[decile] += 1;
+        }
+
+        for (centroid, count) in centroids.iter_mut().zip(counts) {
+            if count > 0 {
+                centroid.iter_mut().for_each(|c| *c /= count as f32);
+            }
+        }
+        centroids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{net_of, vocab_of};
+
+    #[test]
+    fn effective_rank_of_a_rank_deficient_matrix() {
+        // two orthogonal directions with equal singular values in 4 dimensions
+        let net = net_of(&[
+            &[1.0, 0.0, 0.0, 0.0],
+            &[0.0, 2.0, 0.0, 0.0],
+            &[-2.0, 0.0, 0.0, 0.0],
+            &[0.0, -1.0, 0.0, 0.0],
+        ]);
+        assert!((net.effective_rank() - 2.0).abs() < 1e-9);
+
+        let net = net_of(&[&[1.0, 2.0, 3.0, 4.0], &[2.0, 4.0, 6.0, 8.0]]);
+        assert!((net.effective_rank() - 1.0).abs() < 1e-6);
+
+        let net = net_of(&[&[0.0, 0.0], &[0.0, 0.0]]);
+        assert_eq!(net.effective_rank(), 0.0);
+    }
+
+    #[test]
+    fn ten_frequency_decile_centroids() {
+        let words: Vec<String> = (0..21).map(|i| format!("w{i}")).collect();
+        let counts: Vec<(&str, u32)> = words
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (w.as_str(), 100 - i as u32))
+            .collect();
+        let vocab = vocab_of(&counts);
+        let vectors: Vec<[f32; 3]> = (0..21).map(|i| [i as f32, 1.0, 0.0]).collect();
+        let rows: Vec<&[f32]> = vectors.iter().map(|v| &v[..]).collect();
+        let net = net_of(&rows);
+
+        let centroids = net.frequency_decile_centroids(&vocab);
+        assert_eq!(centroids.len(), 10);
+        for (decile, centroid) in centroids.iter().enumerate() {
+            // the two words of each decile
+            assert_eq!(centroid, &[2.0 * decile as f32 + 1.5, 1.0, 0.0]);
+        }
+    }
+
+    #[test]
+    fn alignment_undoes_a_rotation() {
+        let vocab = vocab_of(&[
+            ("</s>", 0),
+            ("a", 5),
+            ("b", 4),
+            ("c", 3),
+            ("d", 2),
+            ("e", 1),
+        ]);
+        let vectors: [[f32; 3]; 6] = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.2, -0.5],
+            [0.3, -1.0, 0.4],
+            [-0.7, 0.5, 0.9],
+            [0.1, 0.8, -0.2],
+            [-0.4, -0.3, -1.0],
+        ];
+        // a rotation by 30 degrees around z followed by one by 60 degrees around x
+        let (s, c) = (0.5f32, 0.75f32.sqrt());
+        let rotate = |[x, y, z]: [f32; 3]| {
+            let (x, y) = (c * x - s * y, s * x + c * y);
+            [x, s * y - c * z, c * y + s * z]
+        };
+        let rows: Vec<&[f32]> = vectors.iter().map(|v| &v[..]).collect();
+        let original = net_of(&rows);
+        let rotated_vectors: Vec<[f32; 3]> = vectors.iter().map(|&v| rotate(v)).collect();
+        let rotated_rows: Vec<&[f32]> = rotated_vectors.iter().map(|v| &v[..]).collect();
+        let rotated = net_of(&rotated_rows);
+
+        let aligned = rotated.align_to(&vocab, &original, &vocab);
+        for (idx, vector) in vectors.iter().enumerate() {
+            for (a, b) in aligned.word_vector(idx).iter().zip(vector) {
+                assert!((a - b).abs() < 1e-4, "{idx}: {a} {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn top_component_explains_rank_one_data() {
+        // multiples of one direction, plus a little noise off it
+        let vectors: Vec<[f32; 3]> = (0..10)
+            .map(|i| {
+                let t = i as f32 - 4.5;
+                let noise = if i % 2 == 0 { 0.01 } else { -0.01 };
+                [t, 2.0 * t, -t + noise]
+            })
+            .collect();
+        let rows: Vec<&[f32]> = vectors.iter().map(|v| &v[..]).collect();
+        let net = net_of(&rows);
+        let ratio = net.top_k_variance_ratio(1);
+        assert!(ratio > 0.999 && ratio <= 1.0, "{ratio}");
+        assert_eq!(net.top_k_variance_ratio(3), 1.0);
+
+        let equal = net_of(&[&[1.0, 2.0], &[1.0, 2.0]]);
+        assert_eq!(equal.top_k_variance_ratio(1), 0.0);
+    }
+}
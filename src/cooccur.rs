@@ -0,0 +1,178 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::tokenizer::{FileTokenIterator, TokenizerParams};
+use crate::vocab::Vocabulary;
+
+/// Word co-occurrence counts, as used by count based methods like GloVe
+pub struct Cooccurrences {
+    /// (word_index, context_word_index) -> count
+    counts: HashMap<(u32, u32), u64>,
+}
+
+impl Cooccurrences {
+    /// Count how often each pair of vocabulary words occurs within `window` words of
+    /// each other in the same sentence of `file_name`. Pairs are counted in both
+    /// directions, so the counts are symmetric. Out-of-vocabulary words are skipped
+    /// before the window is applied, like during training. The file is split into
+    /// tokens with `tokenizer`, which should be the one the vocabulary was learned with.
+    pub fn collect(
+        vocab: &Vocabulary,
+        file_name: &str,
+        window: usize,
+        tokenizer: &TokenizerParams,
+    ) -> std::io::Result<Cooccurrences> {
+        Cooccurrences::collect_with_min_count(vocab, file_name, window, 1, tokenizer)
+    }
+
+    /// Same as `collect`, dropping the pairs that co-occurred less than `min_count`
+    /// times, like GloVe does to keep the matrix of a large corpus tractable
+    pub fn collect_with_min_count(
+        vocab: &Vocabulary,
+        file_name: &str,
+        window: usize,
+        min_count: u64,
+        tokenizer: &TokenizerParams,
+    ) -> std::io::Result<Cooccurrences> {
+        let mut result = Cooccurrences {
+            counts: HashMap::new(),
+        };
+        let mut fi = FileTokenIterator::with_params(file_name, 0, tokenizer)?;
+        // the last `window` words of the current sentence
+        let mut history: Vec<u32> = Vec::with_capacity(window + 1);
+
+        while let Some(token) = fi.read_token() {
+            let idx = vocab.search_word(&token);
+            if idx < 0 {
+                continue;
+            }
+            // word 0 is the "</s>" sentence separator
+            if idx == 0 {
+                history.clear();
+                continue;
+            }
+
+            let idx = idx as u32;
+            for &prev in &history {
+                *result.counts.entry((idx, prev)).or_insert(0) += 1;
+                *result.counts.entry((prev, idx)).or_insert(0) += 1;
+            }
+            if window > 0 {
+                if history.len() == window {
+                    history.remove(0);
+                }
+                history.push(idx);
+            }
+        }
+
+        if min_count > 1 {
+            result.counts.retain(|_, count| *count >= min_count);
+        }
+        Ok(result)
+    }
+
+    /// @return the number of distinct (word, context word) pairs
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// @return how often the words with indices `word_idx` and `context_idx` co-occurred
+    pub fn count(&self, word_idx: usize, context_idx: usize) -> u64 {
+        self.counts
+            .get(&(word_idx as u32, context_idx as u32))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Save the counts as a sparse matrix, one "word_index context_index count" line
+    /// per pair, sorted by word index and then by context index
+    pub fn save_sparse(&self, file_name: &str) -> std::io::Result<()> {
+        let mut entries: Vec<(&(u32, u32), &u64)> = self.counts.iter().collect();
+        entries.sort_unstable_by_key(|(pair, _)| **pair);
+
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(file_name)?);
+        for ((word_idx, context_idx), count) in entries {
+            writeln!(buf_writer, "{word_idx} {context_idx} {count}")?;
+        }
+        buf_writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{temp_path, vocab_of, write_temp_file};
+
+    #[test]
+    fn sparse_export_matches_the_counts() {
+        let vocab = vocab_of(&[("</s>", 0), ("a", 2), ("b", 2), ("c", 1)]);
+        let file = write_temp_file("cooccur-sparse.txt", "a b c\nb a\n");
+        let cooccurrences =
+            Cooccurrences::collect(&vocab, &file, 2, &TokenizerParams::default()).unwrap();
+        let sparse_file = temp_path("cooccur-sparse.mtx");
+        cooccurrences.save_sparse(&sparse_file).unwrap();
+
+        let sparse = std::fs::read_to_string(&sparse_file).unwrap();
+        assert_eq!(sparse, "1 2 2\n1 3 1\n2 1 2\n2 3 1\n3 1 1\n3 2 1\n");
+        for line in sparse.lines() {
+            let entry: Vec<usize> = line.split(' ').map(|x| x.parse().unwrap()).collect();
+            assert_eq!(cooccurrences.count(entry[0], entry[1]), entry[2] as u64);
+        }
+        assert_eq!(cooccurrences.len(), sparse.lines().count());
+    }
+
+    #[test]
+    fn min_count_drops_rare_pairs() {
+        let vocab = vocab_of(&[("</s>", 0), ("a", 3), ("b", 3), ("c", 1)]);
+        let file = write_temp_file("cooccur-min-count.txt", "a b\na b c\nb a\n");
+        let all = Cooccurrences::collect(&vocab, &file, 2, &TokenizerParams::default()).unwrap();
+        assert_eq!((all.count(1, 2), all.count(1, 3)), (3, 1));
+
+        let frequent =
+            Cooccurrences::collect_with_min_count(&vocab, &file, 2, 2, &TokenizerParams::default())
+                .unwrap();
+        assert_eq!(frequent.len(), 2);
+        assert_eq!((frequent.count(1, 2), frequent.count(2, 1)), (3, 3));
+        assert_eq!((frequent.count(1, 3), frequent.count(3, 2)), (0, 0));
+    }
+
+    #[test]
+    fn tokens_are_split_like_the_tokenizer() {
+        let vocab = vocab_of(&[("</s>", 0), ("a", 3), ("b", 3)]);
+        let file = write_temp_file("cooccur-tokenizer.txt", "A:0.5 b:1\nB:0.25 a\n");
+        let default = Cooccurrences::collect(&vocab, &file, 2, &TokenizerParams::default());
+        assert!(default.unwrap().is_empty());
+
+        let tokenizer = TokenizerParams {
+            token_weights: true,
+            lowercase_sentence_start: true,
+            ..Default::default()
+        };
+        let cooccurrences = Cooccurrences::collect(&vocab, &file, 2, &tokenizer).unwrap();
+        assert_eq!(cooccurrences.len(), 2);
+        assert_eq!(
+            (cooccurrences.count(1, 2), cooccurrences.count(2, 1)),
+            (2, 2)
+        );
+    }
+}
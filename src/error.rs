@@ -0,0 +1,48 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::fmt;
+
+/// The errors of loading a model whose format is detected, see `NeuralNet::load_auto`
+#[derive(Debug)]
+pub enum W2vError {
+    /// the file couldn't be read, or it is an invalid file of the detected format
+    Io(std::io::Error),
+    /// the file starts neither with a model header nor with a known magic number
+    UnknownFormat,
+}
+
+impl fmt::Display for W2vError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            W2vError::Io(err) => err.fmt(f),
+            W2vError::UnknownFormat => f.write_str("Unknown model file format"),
+        }
+    }
+}
+
+impl std::error::Error for W2vError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            W2vError::Io(err) => Some(err),
+            W2vError::UnknownFormat => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for W2vError {
+    fn from(err: std::io::Error) -> Self {
+        W2vError::Io(err)
+    }
+}
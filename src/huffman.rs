@@ -0,0 +1,141 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use crate::vocab::Vocabulary;
+
+/// The binary Huffman tree over the word counts of a vocabulary, used by hierarchical
+/// softmax: each word is a leaf, each of the `len() - 1` inner nodes has a row in the
+/// output layer, and the probability of a word is the product of the binary decisions
+/// on the path from the root to its leaf. Frequent words get short paths.
+pub struct HuffmanTree {
+    /// the branch taken at each inner node of the path of each word, 0 or 1
+    codes: Vec<u8>,
+    /// the inner nodes on the path of each word, starting with the root
+    points: Vec<u32>,
+    /// the path of word i is at offsets[i]..offsets[i + 1] of `codes` and `points`
+    offsets: Vec<usize>,
+}
+
+impl HuffmanTree {
+    /// Build the tree of the words of `vocab` like the original word2vec does, by
+    /// repeatedly joining the two nodes of the lowest counts. For a vocabulary sorted by
+    /// decreasing count, as learned from a training file, the codes are the ones of the
+    /// original. The root is inner node `len() - 2`.
+    pub fn new(vocab: &Vocabulary) -> HuffmanTree {
+        let n = vocab.len();
+        // the leaves by increasing count, equal counts by decreasing index
+        let mut leaves: Vec<usize> = (0..n).rev().collect();
+        leaves.sort_by_key(|&idx| vocab.word_count(idx));
+
+        // nodes 0..n are the leaves, node n + i is inner node i
+        let mut count: Vec<u64> = leaves
+            .iter()
+            .map(|&idx| vocab.word_count(idx) as u64)
+            .collect();
+        count.resize(n * 2, u64::MAX);
+        let mut parent: Vec<usize> = vec![0; n * 2];
+        let mut binary: Vec<u8> = vec![0; n * 2];
+        // the next leaf and the next inner node not joined yet, both queues are sorted
+        let (mut pos1, mut pos2) = (0, n);
+        for inner in n..(n * 2).saturating_sub(1) {
+            let mut min = [0; 2];
+            for m in &mut min {
+                *m = if pos1 < n && count[pos1] < count[pos2] {
+                    pos1 += 1;
+                    pos1 - 1
+                } else {
+                    pos2 += 1;
+                    pos2 - 1
+                };
+            }
+            let [min1, min2] = min;
+            count[inner] = count[min1] + count[min2];
+            parent[min1] = inner;
+            parent[min2] = inner;
+            binary[min2] = 1;
+        }
+
+        let mut leaf_of_word: Vec<usize> = vec![0; n];
+        for (leaf, &idx) in leaves.iter().enumerate() {
+            leaf_of_word[idx] = leaf;
+        }
+        let root = (n * 2).saturating_sub(2);
+        let mut tree = HuffmanTree {
+            codes: Vec::new(),
+            points: Vec::new(),
+            offsets: vec![0],
+        };
+        for &leaf in &leaf_of_word {
+            let start = tree.codes.len();
+            // walk up from the leaf, then reverse the path
+            let mut node = leaf;
+            while node != root {
+                tree.codes.push(binary[node]);
+                tree.points.push((parent[node] - n) as u32);
+                node = parent[node];
+            }
+            tree.codes[start..].reverse();
+            tree.points[start..].reverse();
+            tree.offsets.push(tree.codes.len());
+        }
+        tree
+    }
+
+    /// @return the number of leaves, the words of the vocabulary
+    pub fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// @return the Huffman code of the word with index `word_idx`, one 0 or 1 per
+    /// inner node on its path
+    pub fn code(&self, word_idx: usize) -> &[u8] {
+        &self.codes[self.offsets[word_idx]..self.offsets[word_idx + 1]]
+    }
+
+    /// @return the inner nodes on the path of the word with index `word_idx`, from the
+    /// root down to the parent of its leaf
+    pub fn points(&self, word_idx: usize) -> &[u32] {
+        &self.points[self.offsets[word_idx]..self.offsets[word_idx + 1]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HuffmanTree;
+    use crate::test_util::vocab_of;
+
+    #[test]
+    fn codes_and_points_match_the_hand_built_tree() {
+        // c and d are joined first into inner node 0, then b with it into inner node 1,
+        // then a with that into the root, inner node 2
+        let vocab = vocab_of(&[("a", 5), ("b", 3), ("c", 1), ("d", 1)]);
+        let tree = HuffmanTree::new(&vocab);
+        assert_eq!(tree.len(), 4);
+        let paths: Vec<(&[u8], &[u32])> = (0..4).map(|i| (tree.code(i), tree.points(i))).collect();
+        assert_eq!(
+            paths,
+            [
+                (&[1][..], &[2][..]),
+                (&[0, 1][..], &[2, 1][..]),
+                (&[0, 0, 1][..], &[2, 1, 0][..]),
+                (&[0, 0, 0][..], &[2, 1, 0][..]),
+            ]
+        );
+    }
+}
@@ -1,4 +1,16 @@
 pub mod nnet;
 pub mod vocab;
 pub mod tokenizer;
+pub mod query;
+pub mod analysis;
+pub mod cooccur;
+pub mod sketch;
+pub mod shuffle;
+pub mod pq;
+pub mod huffman;
+pub mod error;
+mod linalg;
 pub mod mem_block_lock;
+/// helpers shared by the unit tests
+#[cfg(test)]
+mod test_util;
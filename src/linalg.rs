@@ -0,0 +1,313 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+/// @return the row-major `dim` x `dim` matrix X^T X, where X is the matrix having
+/// `rows` as its rows, each of length `dim`
+pub(crate) fn gram_matrix(rows: &[f32], dim: usize) -> Vec<f64> {
+    let mut gram: Vec<f64> = vec![0.0; dim * dim];
+    for row in rows.chunks_exact(dim) {
+        for i in 0..dim {
+            let x = row[i] as f64;
+            for j in i..dim {
+                gram[i * dim + j] += x * row[j] as f64;
+            }
+        }
+    }
+    // mirror the upper triangle
+    for i in 0..dim {
+        for j in 0..i {
+            gram[i * dim + j] = gram[j * dim + i];
+        }
+    }
+    gram
+}
+
+/// Subtract the mean row from each row of length `dim` of `rows`
+pub(crate) fn center_rows(rows: &mut [f32], dim: usize) {
+    let count = rows.len() / dim;
+    let mut mean: Vec<f64> = vec![0.0; dim];
+    for row in rows.chunks_exact(dim) {
+        for (m, &x) in mean.iter_mut().zip(row) {
+            *m += x as f64;
+        }
+    }
+    for row in rows.chunks_exact_mut(dim) {
+        for (x, m) in row.iter_mut().zip(&mean) {
+            *x -= (m / count as f64) as f32;
+        }
+    }
+}
+
+/// Eigen-decomposition of the symmetric row-major `n` x `n` matrix `a`, using the
+/// cyclic Jacobi method.
+/// @return (eigenvalues, eigenvectors) sorted by decreasing eigenvalue, eigenvector
+/// `i` is stored in row `i` of the second matrix
+pub(crate) fn symmetric_eigen(mut a: Vec<f64>, n: usize) -> (Vec<f64>, Vec<f64>) {
+    debug_assert!(a.len() == n * n);
+    let mut v: Vec<f64> = vec![0.0; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.0;
+    }
+
+    let total: f64 = a.iter().map(|x| x * x).sum();
+    for _sweep in 0..100 {
+        let mut off_diagonal: f64 = 0.0;
+        for p in 0..n {
+            for q in p + 1..n {
+                off_diagonal += a[p * n + q] * a[p * n + q];
+            }
+        }
+        if off_diagonal <= total * 1e-24 {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                // rotate rows and columns p and q so that a[p][q] becomes 0
+                let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&i, &j| a[j * n + j].total_cmp(&a[i * n + i]));
+
+    let eigenvalues: Vec<f64> = order.iter().map(|&i| a[i * n + i]).collect();
+    let mut eigenvectors: Vec<f64> = Vec::with_capacity(n * n);
+    for &i in &order {
+        eigenvectors.extend((0..n).map(|k| v[k * n + i]));
+    }
+    (eigenvalues, eigenvectors)
+}
+
+/// @return the squared euclidean distance of 2 vectors
+pub(crate) fn squared_distance(vec1: &[f32], vec2: &[f32]) -> f32 {
+    vec1.iter().zip(vec2).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// @return the index of the row of the row-major `centroids` matrix closest to `point`
+pub(crate) fn nearest_centroid(point: &[f32], centroids: &[f32]) -> usize {
+    let mut best: (usize, f32) = (0, f32::INFINITY);
+    for (idx, centroid) in centroids.chunks_exact(point.len()).enumerate() {
+        let dist = squared_distance(point, centroid);
+        if dist < best.1 {
+            best = (idx, dist);
+        }
+    }
+    best.0
+}
+
+/// Cluster the rows of length `dim` of `points` into `k` clusters with Lloyd's
+/// algorithm, running at most `iterations` assignment steps. The centroids start at
+/// evenly spaced rows, so the result is deterministic; the centroid of a cluster
+/// that becomes empty stays in place.
+/// @return the row-major `k` x `dim` centroid matrix and the cluster of each row
+pub(crate) fn kmeans(
+    points: &[f32],
+    dim: usize,
+    k: usize,
+    iterations: usize,
+) -> (Vec<f32>, Vec<usize>) {
+    let n = points.len() / dim;
+    assert!(
+        k > 0 && k <= n,
+        "k must be between 1 and the number of points"
+    );
+    let row = |idx: usize| &points[idx * dim..(idx + 1) * dim];
+
+    let mut centroids: Vec<f32> = (0..k).flat_map(|c| row(c * n / k).to_vec()).collect();
+    let mut assignments: Vec<usize> = vec![usize::MAX; n];
+    for _ in 0..iterations {
+        let mut changed = false;
+        for (idx, assignment) in assignments.iter_mut().enumerate() {
+            let nearest = nearest_centroid(row(idx), &centroids);
+            if nearest != *assignment {
+                *assignment = nearest;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums: Vec<f64> = vec![0.0; k * dim];
+        let mut counts: Vec<usize> = vec![0; k];
+        for (idx, &c) in assignments.iter().enumerate() {
+            for (s, &x) in sums[c * dim..(c + 1) * dim].iter_mut().zip(row(idx)) {
+                *s += x as f64;
+            }
+            counts[c] += 1;
+        }
+        for c in 0..k {
+            if counts[c] > 0 {
+                for i in 0..dim {
+                    centroids[c * dim + i] = (sums[c * dim + i] / counts[c] as f64) as f32;
+                }
+            }
+        }
+    }
+    (centroids, assignments)
+}
+
+/// Solve the orthogonal Procrustes problem: given the row-major `n` x `n` matrix
+/// m = X^T Y, find the orthogonal matrix R minimizing ||X R - Y||, which is U V^T for
+/// the singular value decomposition m = U S V^T. The SVD is obtained from the
+/// eigen-decomposition of m^T m = V S^2 V^T, then u_i = m v_i / s_i; the columns of U
+/// of singular values near 0 are completed to an orthonormal basis.
+/// @return R as a row-major `n` x `n` matrix
+pub(crate) fn orthogonal_procrustes(m: &[f64], n: usize) -> Vec<f64> {
+    let mut mtm: Vec<f64> = vec![0.0; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            mtm[i * n + j] = (0..n).map(|k| m[k * n + i] * m[k * n + j]).sum();
+        }
+    }
+    let (eigenvalues, v) = symmetric_eigen(mtm, n);
+    let max_singular = eigenvalues.first().map_or(0.0, |x| x.max(0.0).sqrt());
+
+    // the columns of U, u[i] belonging to v[i]
+    let mut u: Vec<Vec<f64>> = Vec::with_capacity(n);
+    let mut missing: Vec<usize> = Vec::new();
+    for i in 0..n {
+        let singular = eigenvalues[i].max(0.0).sqrt();
+        let vi = &v[i * n..(i + 1) * n];
+        if singular > max_singular * 1e-9 {
+            u.push(
+                (0..n)
+                    .map(|r| (0..n).map(|k| m[r * n + k] * vi[k]).sum::<f64>() / singular)
+                    .collect(),
+            );
+        } else {
+            u.push(vec![0.0; n]);
+            missing.push(i);
+        }
+    }
+    // complete U by Gram-Schmidt orthogonalization of the unit vectors
+    let mut basis = 0;
+    for i in missing {
+        while basis < n {
+            let mut candidate: Vec<f64> = vec![0.0; n];
+            candidate[basis] = 1.0;
+            basis += 1;
+            for other in u.iter().filter(|col| col.iter().any(|&x| x != 0.0)) {
+                let dot: f64 = candidate.iter().zip(other).map(|(a, b)| a * b).sum();
+                candidate
+                    .iter_mut()
+                    .zip(other)
+                    .for_each(|(c, o)| *c -= dot * o);
+            }
+            let norm = candidate.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm > 1e-6 {
+                u[i] = candidate.iter().map(|x| x / norm).collect();
+                break;
+            }
+        }
+    }
+
+    let mut r: Vec<f64> = vec![0.0; n * n];
+    for (k, uk) in u.iter().enumerate() {
+        let vk = &v[k * n..(k + 1) * n];
+        for i in 0..n {
+            for j in 0..n {
+                r[i * n + j] += uk[i] * vk[j];
+            }
+        }
+    }
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// @return true if `x` and `y` are equal up to `tolerance`
+    fn near(x: f64, y: f64, tolerance: f64) -> bool {
+        (x - y).abs() <= tolerance
+    }
+
+    #[test]
+    fn gram_matrix_of_two_rows() {
+        // X = [1 2; 3 4], X^T X = [10 14; 14 20]
+        assert_eq!(
+            gram_matrix(&[1.0, 2.0, 3.0, 4.0], 2),
+            vec![10.0, 14.0, 14.0, 20.0]
+        );
+    }
+
+    #[test]
+    fn symmetric_eigen_of_a_2x2_matrix() {
+        let (eigenvalues, eigenvectors) = symmetric_eigen(vec![2.0, 1.0, 1.0, 2.0], 2);
+        assert!(near(eigenvalues[0], 3.0, 1e-12));
+        assert!(near(eigenvalues[1], 1.0, 1e-12));
+        // (1, 1) / sqrt(2) and (1, -1) / sqrt(2), up to their sign
+        let half = std::f64::consts::FRAC_1_SQRT_2;
+        assert!(near(eigenvectors[0].abs(), half, 1e-12));
+        assert!(near(eigenvectors[0], eigenvectors[1], 1e-12));
+        assert!(near(eigenvectors[2].abs(), half, 1e-12));
+        assert!(near(eigenvectors[2], -eigenvectors[3], 1e-12));
+    }
+
+    #[test]
+    fn symmetric_eigen_of_a_diagonal_matrix_sorts_the_eigenvalues() {
+        let a = vec![1.0, 0.0, 0.0, 0.0, 5.0, 0.0, 0.0, 0.0, -2.0];
+        let (eigenvalues, eigenvectors) = symmetric_eigen(a, 3);
+        assert_eq!(eigenvalues, vec![5.0, 1.0, -2.0]);
+        assert_eq!(
+            eigenvectors,
+            vec![0.0, 1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0]
+        );
+    }
+
+    #[test]
+    fn symmetric_eigen_decomposes_a_dense_matrix() {
+        let n = 4;
+        let a = vec![
+            4.0, 1.0, -2.0, 2.0, 1.0, 2.0, 0.0, 1.0, -2.0, 0.0, 3.0, -2.0, 2.0, 1.0, -2.0, -1.0,
+        ];
+        let (eigenvalues, eigenvectors) = symmetric_eigen(a.clone(), n);
+        // the trace is the sum of the eigenvalues
+        assert!(near(eigenvalues.iter().sum(), 8.0, 1e-9));
+        for (lambda, v) in eigenvalues.iter().zip(eigenvectors.chunks_exact(n)) {
+            // A v = lambda v for a unit vector v
+            assert!(near(v.iter().map(|x| x * x).sum(), 1.0, 1e-9));
+            for i in 0..n {
+                let av: f64 = (0..n).map(|k| a[i * n + k] * v[k]).sum();
+                assert!(near(av, lambda * v[i], 1e-9));
+            }
+        }
+    }
+}
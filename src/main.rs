@@ -15,22 +15,75 @@
 
 use std::fs::metadata;
 use std::sync::Arc;
-use std::sync::atomic::AtomicU64;
-use std::thread;
+use std::sync::atomic::Ordering;
 
-use word2vec_rust::nnet::{NeuralNet, TrainigParams, TrainigProgress, train_model_thread};
-use word2vec_rust::vocab::Vocabulary;
+use word2vec_rust::nnet::{
+    Architecture, NeuralNet, TrainigParams, TrainigProgress, TrainingObserver, train_model_from,
+};
+use word2vec_rust::tokenizer::TokenizerParams;
+use word2vec_rust::vocab::{VocabParams, Vocabulary};
+
+/// prints the neighbors of the probe words during training
+struct ConsoleObserver {}
+
+impl TrainingObserver for ConsoleObserver {
+    fn probe_neighbors(&mut self, word_count: u64, word: &str, neighbors: &[(String, f32)]) {
+        let neighbors: Vec<String> = neighbors
+            .iter()
+            .map(|(w, sim)| format!("{w} ({sim:.03})"))
+            .collect();
+        println!("\n[{word_count} words] {word}: {}", neighbors.join(", "));
+    }
+}
 
 fn train(params: &mut TrainigParams) -> Result<(), Box<dyn std::error::Error>> {
     params.training_file_size = metadata(&params.training_file)?.len();
-    let vocab: Vocabulary = if params.vocab_file.is_empty() {
-        Vocabulary::learn_vocabulary_from_training_file(&params.training_file, params.min_count)?
-    } else {
-        Vocabulary::load_from_file(&params.vocab_file)?
+    // a checkpoint has the vocabulary and the net of the training it resumes
+    let (mut vocab, checkpoint) = match &params.resume_from {
+        Some(checkpoint_file) => {
+            let (vocab, net, position) = NeuralNet::load_checkpoint(checkpoint_file)?;
+            (vocab, Some((net, position)))
+        }
+        None if params.vocab_file.is_empty() => {
+            let vocab_params = VocabParams {
+                min_count: params.min_count,
+                tokenizer: params.tokenizer.clone(),
+                ..Default::default()
+            };
+            let vocab =
+                Vocabulary::learn_vocabulary_with_params(&params.training_file, &vocab_params)?;
+            (vocab, None)
+        }
+        None => (Vocabulary::load_from_file(&params.vocab_file)?, None),
+    };
+    // the words of the model training continues from are kept, even if they are
+    // missing from the training file
+    let pretrained = match &params.continue_from {
+        Some(model_file) if checkpoint.is_none() => {
+            let (pretrained_vocab, pretrained) = NeuralNet::load_auto(model_file)?;
+            vocab.add_missing_words(&pretrained_vocab);
+            Some((pretrained_vocab, pretrained))
+        }
+        _ => None,
     };
 
     if params.debug_mode > 0 {
         vocab.debug_print_summary();
+        if params.check_singletons && !params.span_sentences {
+            let singletons =
+                vocab.count_singleton_sentences(&params.training_file, &params.tokenizer)?;
+            println!(
+                "Single word sentences: {} of {}",
+                singletons.singletons, singletons.sentences
+            );
+            if singletons.is_suspicious() {
+                println!(
+                    "Warning: {:.01}% of the sentences have a single word and add nothing to \
+                     training, does the training file have one word per line?",
+                    singletons.fraction() * 100.0
+                );
+            }
+        }
     }
 
     if !params.save_vocab_file.is_empty() {
@@ -45,26 +98,121 @@ fn train(params: &mut TrainigParams) -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    let progress = TrainigProgress {
-        word_count_actual: AtomicU64::new(0),
-    };
+    let progress = TrainigProgress::new();
 
-    let net = NeuralNet::new(vocab.len(), params.vectors_size);
+    let (mut net, position) = match checkpoint {
+        Some((net, position)) => {
+            if params.debug_mode > 0 {
+                println!(
+                    "Resuming at epoch {} after {} words",
+                    position.epoch + 1,
+                    position.word_count_actual
+                );
+            }
+            (net, Some(position))
+        }
+        None => match &pretrained {
+            Some((pretrained_vocab, pretrained)) => {
+                let (net, reused) =
+                    NeuralNet::warm_start(&vocab, pretrained_vocab, pretrained, params.seed);
+                if params.debug_mode > 0 {
+                    println!("Pretrained vectors: {reused} of {} words", vocab.len());
+                }
+                (net, None)
+            }
+            None => (
+                NeuralNet::with_seed(vocab.len(), params.vectors_size, params.seed),
+                None,
+            ),
+        },
+    };
+    if params.output_bias {
+        net.enable_output_bias();
+    }
+    if params.adam {
+        net.enable_adam();
+    }
+    if params.adagrad {
+        net.enable_adagrad();
+    }
+    if params.hierarchical_softmax {
+        net.enable_hierarchical_softmax(&vocab);
+    }
     let net = Arc::new(net);
+    // the epochs trained before resuming have no durations or validation losses in the
+    // progress
+    let first_epoch = position.as_ref().map_or(0, |position| position.epoch);
 
-    thread::scope(|scope| {
-        // we don't need these "moved", but "thread_id" has to be moved
-        let vocab = &vocab;
-        let params = &params;
-        let progress = &progress;
+    train_model_from(
+        &net,
+        &vocab,
+        params,
+        &progress,
+        &mut ConsoleObserver {},
+        position.as_ref(),
+    )?;
 
-        for thread_id in 0..params.num_threads {
-            let net = Arc::clone(&net);
-            scope.spawn(move || train_model_thread(net, vocab, thread_id, params, progress));
+    if params.debug_mode > 0 {
+        match *progress.empty_sentences.lock().unwrap() {
+            Some(empty_sentences) => println!("\nEmpty sentences: {empty_sentences}"),
+            // the resumed epoch only trained on part of the file
+            None => println!("\nEmpty sentences: not counted, no epoch was trained in full"),
+        }
+        println!("Saturated dot products: {:.04}", progress.saturation_rate());
+        for (epoch, duration) in progress.epoch_durations.lock().unwrap().iter().enumerate() {
+            println!(
+                "Epoch {}: {:.03}s",
+                first_epoch + epoch as u64 + 1,
+                duration.as_secs_f64()
+            );
         }
-    });
+        for (epoch, loss) in progress
+            .validation_losses
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+        {
+            println!(
+                "Validation loss after epoch {}: {loss:.06}",
+                first_epoch + epoch as u64 + 1
+            );
+        }
+    }
 
-    net.save(&vocab, &params.output_file, params.binary)?;
+    if params.gradient_histogram {
+        println!("\nContext vector update norms:");
+        for (bucket, count) in progress.gradient_histogram.iter().enumerate() {
+            let count = count.load(Ordering::Relaxed);
+            if count > 0 {
+                println!("  ~2^{}: {count}", bucket as i32 - 24);
+            }
+        }
+    }
+
+    // with early stopping the vectors of the epoch with the least validation loss are
+    // saved, unless that is the last epoch
+    let last_epoch = first_epoch + progress.validation_losses.lock().unwrap().len() as u64;
+    let best_epoch = progress
+        .best_validation
+        .lock()
+        .unwrap()
+        .map(|(_, epoch)| epoch);
+    let net = match &params.best_checkpoint_file {
+        Some(file_name) if best_epoch.is_some_and(|epoch| epoch + 1 != last_epoch) => {
+            if params.debug_mode > 0 {
+                println!("Saving the vectors of epoch {}", best_epoch.unwrap() + 1);
+            }
+            Arc::new(NeuralNet::load_checkpoint(file_name)?.1)
+        }
+        _ => net,
+    };
+
+    if params.save_counts {
+        net.save_binary_with_counts(&vocab, &params.output_file)?;
+    } else {
+        net.save(&vocab, &params.output_file, params.binary)?;
+    }
     Ok(())
 }
 
@@ -92,9 +240,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         training_file: String::new(),
         training_file_size: 0,
         vocab_file: String::new(),
+        continue_from: None,
+        resume_from: None,
         save_vocab_file: String::new(),
         output_file: String::new(),
         vectors_size: 100,
+        architecture: Architecture::Cbow,
         window: 5,
         total_iter: 5,
         negative_samples: 5,
@@ -103,8 +254,47 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         debug_mode: 2,
         min_count: 5,
         binary: false,
+        save_counts: false,
+        span_sentences: false,
+        skip_sentences: 0,
+        probe_words: Vec::new(),
+        probe_every: 0,
+        progress_every: 0,
+        tokenizer: TokenizerParams::default(),
+        seed: 0,
+        normalize_context: false,
+        gradient_histogram: false,
+        f64_gradients: false,
+        decay: true,
+        output_bias: false,
+        reverse_odd_epochs: false,
+        loss_log_path: None,
+        validation_file: None,
+        patience: 1,
+        best_checkpoint_file: None,
+        deterministic: false,
+        renorm_every: None,
+        warmup_words: 0,
+        min_alpha: None,
+        adam: false,
+        adagrad: false,
+        max_grad: None,
+        keep_probabilities: None,
+        sample: 1e-3,
+        banded_negatives: false,
+        record_alpha: false,
+        snapshot_prefix: None,
+        snapshot_every: 0,
+        snapshot_words: 0,
+        checkpoint_file: None,
+        checkpoint_every: 0,
+        hierarchical_softmax: false,
+        lock_free: true,
+        check_singletons: false,
     };
 
+    // the default learning rate depends on the architecture and the optimizer
+    let mut alpha_given = false;
     let mut args = std::env::args().skip(1);
     while let Some(arg) = args.next() {
         match &arg[..] {
@@ -124,39 +314,103 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     panic!("No value specified for parameter --output.");
                 }
             }
-            "-s" | "--size" => {
+            "-s" | "-size" | "--size" => {
                 // Set size of word vectors; default is 100
-                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()).filter(|&x| x >= 1) {
                     params.vectors_size = val;
                 } else {
                     panic!("No valid value specified for parameter --size, must be >= 1");
                 }
             }
+            "--architecture" => {
+                // The model to train, cbow or skip-gram; default is cbow
+                params.architecture = match args.next().as_deref() {
+                    Some("cbow") => Architecture::Cbow,
+                    Some("skip-gram") => Architecture::SkipGram,
+                    _ => panic!(
+                        "No valid value specified for parameter --architecture, must be cbow or skip-gram"
+                    ),
+                };
+            }
+            "-cbow" => {
+                // The original word2vec switch: 1 trains cbow, 0 trains skip-gram
+                params.architecture = match args.next().as_deref() {
+                    Some("1") => Architecture::Cbow,
+                    Some("0") => Architecture::SkipGram,
+                    _ => panic!("No valid value specified for parameter -cbow, must be 0 or 1"),
+                };
+            }
+            "-hs" => {
+                // The original word2vec switch: 1 trains hierarchical softmax, combine
+                // with -ns 0 to turn negative sampling off; default is 0
+                params.hierarchical_softmax = match args.next().as_deref() {
+                    Some("1") => true,
+                    Some("0") => false,
+                    _ => panic!("No valid value specified for parameter -hs, must be 0 or 1"),
+                };
+            }
+            "-sample" | "--sample" => {
+                // Down-sample words more frequent than this threshold, 0 disables it;
+                // default is 1e-3, like the original word2vec
+                if let Some(val) = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .filter(|&x: &f32| x >= 0.0)
+                {
+                    params.sample = val;
+                } else {
+                    panic!("No valid value specified for parameter --sample, must be >= 0");
+                }
+            }
             "-w" | "--window" => {
                 // Set max skip length between words; default is 5
-                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()).filter(|&x| x >= 1) {
                     params.window = val;
                 } else {
                     panic!("No valid value specified for parameter --window, must be >= 1");
                 }
             }
-            "-ns" | "--negative-samples" => {
-                // Number of negative examples; default is 5, common values are 3 - 10 (0 = not used)
-                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+            "-ns" | "-negative" | "--negative-samples" => {
+                // Number of negative examples; default is 5, common values are 3 - 10 (0 = not used, requires -hs 1)
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()).filter(|&x| x >= 0) {
                     params.negative_samples = val;
                 } else {
-                    panic!("No valid value specified for parameter --window, must be >= 0");
+                    panic!(
+                        "No valid value specified for parameter --negative-samples, must be >= 0"
+                    );
                 }
             }
             "--threads" => {
                 // Use <usize> threads (default 1)
-                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()).filter(|&x| x >= 1) {
                     params.num_threads = val;
                 } else {
                     panic!("No valid value specified for parameter --threads, must be >= 1");
                 }
             }
-            "--iter" => {
+            "--lock-free" => {
+                // Update the net without per row locks (Hogwild), --adam and --adagrad
+                // always lock the rows; default is true
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.lock_free = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --lock-free, must be true or false"
+                    );
+                }
+            }
+            "--check-singletons" => {
+                // Count the sentences having a single word before training, which takes
+                // an extra pass over the training file; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.check_singletons = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --check-singletons, must be true or false"
+                    );
+                }
+            }
+            "-iter" | "--iter" => {
                 // Run more training iterations or epochs (default 5)
                 if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
                     params.total_iter = val;
@@ -164,16 +418,29 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     panic!("No valid value specified for parameter --iter, must be >= 1");
                 }
             }
-            "--alpha" => {
+            "-alpha" | "--alpha" => {
                 // Set the starting learning rate; default is 0.025 for skip-gram and 0.05 for CBOW
+                // with plain SGD, 0.0025 with --adam and 0.05 with --adagrad
                 if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
                     params.starting_alpha = val;
+                    alpha_given = true;
                 } else {
                     panic!(
                         "No valid value specified for parameter --alpha, must be a fraction in (0, 1)"
                     );
                 }
             }
+            "--min-alpha" => {
+                // Set the minimum learning rate alpha decays to; default is 0.0001 * the
+                // starting learning rate
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.min_alpha = Some(val);
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --min-alpha, must be a fraction in (0, 1)"
+                    );
+                }
+            }
             "--binary" => {
                 // Save the resulting vectors in binary moded; default is 0 (off)
                 if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
@@ -184,6 +451,323 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     );
                 }
             }
+            "--span-sentences" => {
+                // Let the context window cross sentence boundaries; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.span_sentences = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --span-sentences, must be true or false"
+                    );
+                }
+            }
+            "--terminate-last-line" => {
+                // End a last line lacking a trailing newline with a sentence separator;
+                // default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.tokenizer.terminate_last_line = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --terminate-last-line, must be true or false"
+                    );
+                }
+            }
+            "--normalize-context" => {
+                // Scale context vectors to unit length before averaging them (experimental);
+                // default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.normalize_context = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --normalize-context, must be true or false"
+                    );
+                }
+            }
+            "--collapse-separators" => {
+                // Treat a run of blank lines as a single sentence separator; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.tokenizer.collapse_separators = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --collapse-separators, must be true or false"
+                    );
+                }
+            }
+            "--gradient-histogram" => {
+                // Print a histogram of the context vector update norms; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.gradient_histogram = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --gradient-histogram, must be true or false"
+                    );
+                }
+            }
+            "--f64-gradients" => {
+                // Accumulate the context vector updates in double precision; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.f64_gradients = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --f64-gradients, must be true or false"
+                    );
+                }
+            }
+            "--decay" => {
+                // Decrease the learning rate linearly during training; default is true
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.decay = val;
+                } else {
+                    panic!("No valid value specified for parameter --decay, must be true or false");
+                }
+            }
+            "--output-bias" => {
+                // Train a per word output layer bias (experimental); default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.output_bias = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --output-bias, must be true or false"
+                    );
+                }
+            }
+            "--adam" => {
+                // Use the Adam optimizer, with --alpha as its step size; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.adam = val;
+                } else {
+                    panic!("No valid value specified for parameter --adam, must be true or false");
+                }
+            }
+            "--adagrad" => {
+                // Use the AdaGrad optimizer, with --alpha as its step size; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.adagrad = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --adagrad, must be true or false"
+                    );
+                }
+            }
+            "--max-grad" => {
+                // Clip the output errors to [-<float>, <float>]; default is no clipping
+                if let Some(val) = args
+                    .next()
+                    .and_then(|x| x.parse().ok())
+                    .filter(|&x: &f32| x > 0.0)
+                {
+                    params.max_grad = Some(val);
+                } else {
+                    panic!("No valid value specified for parameter --max-grad, must be > 0");
+                }
+            }
+            "--banded-negatives" => {
+                // Draw negative samples from words of similar frequency as the target
+                // (experimental); default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.banded_negatives = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --banded-negatives, must be true or false"
+                    );
+                }
+            }
+            "--reverse-odd-epochs" => {
+                // Train on the sentences in reverse order on every second epoch; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.reverse_odd_epochs = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --reverse-odd-epochs, must be true or false"
+                    );
+                }
+            }
+            "--skip-whitespace-lines" => {
+                // Don't end a sentence at lines holding only spaces or tabs; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.tokenizer.skip_whitespace_lines = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --skip-whitespace-lines, must be true or false"
+                    );
+                }
+            }
+            "--token-weights" => {
+                // Read tokens as word:weight pairs and scale the updates by the weights;
+                // default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.tokenizer.token_weights = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --token-weights, must be true or false"
+                    );
+                }
+            }
+            "--loss-log" => {
+                // Append the loss after each epoch to the CSV <file>
+                if let Some(val) = args.next() {
+                    params.loss_log_path = Some(val);
+                } else {
+                    panic!("No valid value specified for parameter --loss-log");
+                }
+            }
+            "--validation" => {
+                // Stop training once the loss on the held-out text <file> stops
+                // improving, see --patience
+                if let Some(arg_file) = args.next() {
+                    params.validation_file = Some(arg_file);
+                } else {
+                    panic!("No value specified for parameter --validation.");
+                }
+            }
+            "--patience" => {
+                // Number of epochs without improvement of the validation loss before
+                // training stops; default is 1
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()).filter(|&x| x >= 1) {
+                    params.patience = val;
+                } else {
+                    panic!("No valid value specified for parameter --patience, must be >= 1");
+                }
+            }
+            "--best-checkpoint" => {
+                // Save a checkpoint to <file> each time the validation loss improves, the
+                // vectors of the best epoch are the ones saved to the output file
+                if let Some(arg_file) = args.next() {
+                    params.best_checkpoint_file = Some(arg_file);
+                } else {
+                    panic!("No value specified for parameter --best-checkpoint.");
+                }
+            }
+            "--lowercase-sentence-start" => {
+                // Lowercase the first word of each sentence; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.tokenizer.lowercase_sentence_start = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --lowercase-sentence-start, must be true or false"
+                    );
+                }
+            }
+            "--seed" => {
+                // Seed the initial weights and the training random numbers; default is 0
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.seed = val;
+                } else {
+                    panic!("No valid value specified for parameter --seed, must be >= 0");
+                }
+            }
+            "--deterministic" => {
+                // Fail unless training is reproducible (requires 1 thread); default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.deterministic = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --deterministic, must be true or false"
+                    );
+                }
+            }
+            "--renorm-every" => {
+                // Scale the word vectors to unit length every <int> words
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.renorm_every = Some(val);
+                } else {
+                    panic!("No valid value specified for parameter --renorm-every, must be >= 1");
+                }
+            }
+            "--warmup-words" => {
+                // Ramp the learning rate up over the first <int> words; default is 0
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.warmup_words = val;
+                } else {
+                    panic!("No valid value specified for parameter --warmup-words");
+                }
+            }
+            "--save-counts" => {
+                // Save the resulting vectors in binary format followed by the word counts; default is false
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.save_counts = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --save-counts, must be true or false"
+                    );
+                }
+            }
+            "--skip-sentences" => {
+                // Don't train on the first <int> sentences of the training file; default is 0
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.skip_sentences = val;
+                } else {
+                    panic!("No valid value specified for parameter --skip-sentences, must be >= 0");
+                }
+            }
+            "--probe-words" => {
+                // Comma separated words whose nearest neighbors are printed during training
+                if let Some(val) = args.next() {
+                    params.probe_words = val.split(',').map(String::from).collect();
+                } else {
+                    panic!("No value specified for parameter --probe-words.");
+                }
+            }
+            "--probe-every" => {
+                // Print the neighbors of the probe words every <int> words; default is 0 (never)
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.probe_every = val;
+                } else {
+                    panic!("No valid value specified for parameter --probe-every, must be >= 0");
+                }
+            }
+            "--snapshot-prefix" => {
+                // Save 2D PCA projections of the most frequent words during training to
+                // <prefix>00001.txt, <prefix>00002.txt, ...
+                if let Some(val) = args.next() {
+                    params.snapshot_prefix = Some(val);
+                } else {
+                    panic!("No value specified for parameter --snapshot-prefix.");
+                }
+            }
+            "--snapshot-every" => {
+                // Save a snapshot every <int> words; default is 0 (never)
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.snapshot_every = val;
+                } else {
+                    panic!("No valid value specified for parameter --snapshot-every, must be >= 0");
+                }
+            }
+            "--snapshot-words" => {
+                // Number of most frequent words in each snapshot; default is 0
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.snapshot_words = val;
+                } else {
+                    panic!("No valid value specified for parameter --snapshot-words, must be >= 0");
+                }
+            }
+            "--checkpoint" => {
+                // Save a checkpoint of the training to <file>, see --checkpoint-every
+                if let Some(arg_file) = args.next() {
+                    params.checkpoint_file = Some(arg_file);
+                } else {
+                    panic!("No value specified for parameter --checkpoint.");
+                }
+            }
+            "--checkpoint-every" => {
+                // Save a checkpoint every <int> words; default is 0 (never)
+                if let Some(val) = args.next().and_then(|x| x.parse().ok()) {
+                    params.checkpoint_every = val;
+                } else {
+                    panic!(
+                        "No valid value specified for parameter --checkpoint-every, must be >= 0"
+                    );
+                }
+            }
+            "--resume" => {
+                // Resume the training saved in the checkpoint <file>, with the same
+                // parameters it was started with
+                if let Some(arg_file) = args.next() {
+                    params.resume_from = Some(arg_file);
+                } else {
+                    panic!("No value specified for parameter --resume.");
+                }
+            }
             "-v" | "--read-vocab" => {
                 // The vocabulary will be read from <file>, not constructed from the training data
                 if let Some(arg_file) = args.next() {
@@ -192,6 +776,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     panic!("No value specified for parameter --read-vocab.");
                 }
             }
+            "--continue" => {
+                // Start from the vectors of the model <file> and keep its words; the
+                // vector size of the model overrides --size
+                if let Some(arg_file) = args.next() {
+                    params.continue_from = Some(arg_file);
+                } else {
+                    panic!("No value specified for parameter --continue.");
+                }
+            }
             "--save-vocab" => {
                 // The vocabulary will be saved to <file>
                 if let Some(arg_file) = args.next() {
@@ -225,5 +818,17 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     }
+    if !alpha_given {
+        if params.adam {
+            // the step size of Adam, the SGD defaults would be far too large
+            params.starting_alpha = 0.0025;
+        } else if params.adagrad {
+            // AdaGrad shrinks the steps anyway, like GloVe start above the SGD default
+            params.starting_alpha = 0.05;
+        } else if params.architecture == Architecture::Cbow {
+            // like the original, CBOW gets a larger learning rate
+            params.starting_alpha = 0.05;
+        }
+    }
     train(&mut params)
 }
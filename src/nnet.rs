@@ -14,32 +14,86 @@
 //  limitations under the License.
 
 use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::num::NonZeroU64;
 use std::ptr::slice_from_raw_parts_mut;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::error::W2vError;
+use crate::huffman::HuffmanTree;
 use crate::mem_block_lock::MemBlockLocker;
-use crate::tokenizer::FileTokenIterator;
+use crate::tokenizer::{FileTokenIterator, TokenizerParams, sentence_offset};
 use crate::vocab::Vocabulary;
 
+/// The model trained by `train_model`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Architecture {
+    /// continuous bag of words: predict each word from the average of its context
+    /// vectors; fast, good for frequent words
+    Cbow,
+    /// skip-gram: predict each context word from the vector of the word; slower by
+    /// about the window size, better for rare words
+    SkipGram,
+}
 
 pub struct TrainigParams {
     pub training_file: String,
     pub training_file_size: u64,
     pub vocab_file: String,
+    pub continue_from: Option<String>, // model whose vectors main starts from, see NeuralNet::warm_start
+    pub resume_from: Option<String>, // checkpoint main resumes training from, see NeuralNet::load_checkpoint
     pub save_vocab_file: String,
     pub output_file: String,
     pub vectors_size: usize,
-    pub window: usize,         // the train window parameter
+    pub architecture: Architecture,
+    pub window: usize,         // the max number of context words on each side, >= 1
     pub total_iter: u64,       // number of training epochs
-    pub negative_samples: i32, // number of negative samples
+    pub negative_samples: i32, // number of negative samples drawn per target, >= 0
     pub num_threads: usize,    // the total number of training threads
     pub starting_alpha: f32,   // the starting learning rate
     pub debug_mode: i32,
     pub min_count: u32,
     pub binary: bool,
+    pub save_counts: bool, // save binary vectors with counts, see NeuralNet::save_binary_with_counts
+    pub span_sentences: bool, // let context windows cross sentence boundaries
+    pub skip_sentences: u64, // number of leading sentences not trained on
+    pub probe_words: Vec<String>, // words whose neighbors are reported during training
+    pub probe_every: u64,  // report probe word neighbors every N words (0 = never)
+    pub progress_every: u64, // call TrainingObserver::progress every N words (0 = never)
+    pub tokenizer: TokenizerParams, // how the training file is split into sentences
+    pub seed: u64,         // base seed of the training random number streams
+    pub normalize_context: bool, // experimental: average unit-length CBOW context vectors
+    pub gradient_histogram: bool, // fill TrainigProgress::gradient_histogram
+    pub f64_gradients: bool, // accumulate the context vector errors in f64, a bit slower
+    pub decay: bool, // decrease the learning rate linearly during training, else keep starting_alpha
+    pub output_bias: bool, // train the output layer bias, see NeuralNet::enable_output_bias
+    pub reverse_odd_epochs: bool, // read the sentences backwards on every second epoch
+    pub loss_log_path: Option<String>, // CSV file getting the loss after each epoch
+    pub validation_file: Option<String>, // held-out text for early stopping
+    pub patience: u64, // epochs without validation loss improvement before stopping
+    pub best_checkpoint_file: Option<String>, // checkpoint saved when the validation loss improves
+    pub deterministic: bool, // identical vectors for identical inputs, requires 1 thread
+    pub renorm_every: Option<NonZeroU64>, // rescale the input vectors to unit length every N words
+    pub warmup_words: u64, // ramp the learning rate up over the first N words (0 = never)
+    pub min_alpha: Option<f32>, // learning rate floor, None = starting_alpha * 0.0001
+    pub adam: bool,  // Adam updates, see NeuralNet::enable_adam
+    pub adagrad: bool, // AdaGrad updates, see NeuralNet::enable_adagrad
+    pub max_grad: Option<f32>, // clip each output error to [-max_grad, max_grad]
+    pub keep_probabilities: Option<Vec<f32>>, // per word probability of keeping an occurrence
+    pub sample: f32, // subsampling threshold of frequent words (0 = off)
+    pub check_singletons: bool, // main counts the single word sentences, an extra pass over the training file
+    pub banded_negatives: bool, // experimental, see Vocabulary::sample_word_in_band
+    pub record_alpha: bool,     // fill TrainigProgress::alpha_trajectory
+    pub snapshot_prefix: Option<String>, // file prefix of the PCA snapshots, see NeuralNet::save_pca_snapshot
+    pub snapshot_every: u64,             // save a snapshot every N words
+    pub snapshot_words: usize,           // number of most frequent words in each snapshot
+    pub checkpoint_file: Option<String>, // checkpoint replaced every checkpoint_every words
+    pub checkpoint_every: u64,           // save a checkpoint every N words
+    pub hierarchical_softmax: bool,      // see NeuralNet::enable_hierarchical_softmax
+    pub lock_free: bool, // update the rows without locking them (Hogwild) unless adam or adagrad is set
 }
 
 pub struct NeuralNet {
@@ -47,36 +101,156 @@ pub struct NeuralNet {
     layer1_size: usize,
     syn0: Vec<f32>,
     syn1neg: Vec<f32>,
+    /// the output layer of hierarchical softmax, one row per inner node of
+    /// `huffman_tree`, empty unless enabled by `enable_hierarchical_softmax`
+    syn1: Vec<f32>,
+    huffman_tree: Option<HuffmanTree>,
+    /// per word bias of the output layer, empty unless enabled by `enable_output_bias`
+    bias: Vec<f32>,
+    /// the Adam optimizer state, None unless enabled by `enable_adam`
+    adam: Option<AdamState>,
+    /// the AdaGrad optimizer state, None unless enabled by `enable_adagrad`
+    adagrad: Option<AdaGradState>,
     locker: MemBlockLocker,
 }
 
-struct LcRandomGen {
+/// the exponential decay rates of the Adam moment estimates and the term added to the
+/// denominator of the update, the defaults of the Adam paper except for a larger
+/// epsilon, which keeps the first updates of rarely seen words small
+const ADAM_BETA1: f32 = 0.9;
+const ADAM_BETA2: f32 = 0.999;
+const ADAM_EPSILON: f32 = 1e-6;
+
+/// The moment estimates of each `syn0` and `syn1neg` weight. Only the rows of the
+/// words in a training step are updated, each row having its own step count for the
+/// bias correction, so the moments of rare words don't decay while they are unused
+/// (like the "lazy" Adam of sparse embeddings). A row and its moments are guarded by
+/// the same `MemBlockLocker` lock.
+struct AdamState {
+    syn0_m: Vec<f32>,
+    syn0_v: Vec<f32>,
+    syn0_steps: Vec<u32>,
+    syn1neg_m: Vec<f32>,
+    syn1neg_v: Vec<f32>,
+    syn1neg_steps: Vec<u32>,
+}
+
+/// the initial sum of squared gradients of each weight of AdaGrad, which keeps the
+/// first updates of a weight from being alpha, whatever the size of its gradient
+const ADAGRAD_INITIAL_ACCUMULATOR: f32 = 0.1;
+
+/// The sum of the squared gradients of each `syn0` and `syn1neg` weight. A row and its
+/// sums are guarded by the same `MemBlockLocker` lock.
+struct AdaGradState {
+    syn0_sums: Vec<f32>,
+    syn1neg_sums: Vec<f32>,
+}
+
+pub(crate) struct LcRandomGen {
     state: i64,
 }
 
 impl LcRandomGen {
-    fn new(seed: i64) -> LcRandomGen {
+    pub(crate) fn new(seed: i64) -> LcRandomGen {
         LcRandomGen { state: seed }
     }
 
-    fn next_rand(&mut self) -> i64 {
+    /// @return a generator whose sequence depends on both `seed` and `stream`, so that
+    /// generators for different streams of the same seed are unrelated
+    pub(crate) fn for_stream(seed: u64, stream: u64) -> LcRandomGen {
+        LcRandomGen::new(mix_seed(seed, stream) as i64)
+    }
+
+    pub(crate) fn next_rand(&mut self) -> i64 {
         self.state = self.state.wrapping_mul(25214903917).wrapping_add(11);
         self.state
     }
+
+    /// @return the number of context words on each side of a word, from 1 to
+    /// `window`, drawn like the original word2vec: window - next_random % window, the
+    /// state being read as unsigned
+    pub(crate) fn shrunk_window(&mut self, window: usize) -> usize {
+        window - (self.next_rand() as u64 % window as u64) as usize
+    }
+}
+
+/// @return a seed derived from `seed` and `stream` with the splitmix64 finalizer, the
+/// seeds derived from different streams being unrelated. For a given stream distinct
+/// seeds give distinct results.
+fn mix_seed(seed: u64, stream: u64) -> u64 {
+    let mut z = seed ^ stream.wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// A separate random number stream for each source of randomness in training, so that
+/// changing how one of them draws numbers leaves the others unchanged. Each stream
+/// draws like the original word2vec, but the original shares a single stream between
+/// them, seeded by the thread id, so its individual draws differ from ours.
+struct RandomStreams {
+    /// shrinks the context window of each word
+    window: LcRandomGen,
+    /// picks the negative samples
+    negative: LcRandomGen,
+    /// decides which words are dropped, see `TrainigParams::keep_probabilities` and
+    /// `TrainigParams::sample`
+    subsample: LcRandomGen,
+}
+
+impl RandomStreams {
+    fn new(seed: u64) -> RandomStreams {
+        RandomStreams {
+            window: LcRandomGen::for_stream(seed, 1),
+            negative: LcRandomGen::for_stream(seed, 2),
+            // stream 3 is used by `ShuffleBuffer`, stream 5 by `NeuralNet::with_seed`
+            subsample: LcRandomGen::for_stream(seed, 4),
+        }
+    }
+
+    /// @return the streams of training thread `thread_id` in epoch `epoch`. The tuple is
+    /// hashed rather than summed, so that no thread or epoch reuses the streams another
+    /// one gets with a nearby seed.
+    fn for_thread(seed: u64, epoch: u64, thread_id: usize) -> RandomStreams {
+        RandomStreams::new(mix_seed(mix_seed(seed, epoch), thread_id as u64))
+    }
 }
 
+/// the random number stream of the initial weights of `NeuralNet::with_seed`
+const WEIGHTS_STREAM: u64 = 5;
+
+const CHECKPOINT_FILE_MAGIC: &[u8] = b"word2vec-rust checkpoint 1\n";
+/// the first line of the files written by `NeuralNet::save_binary_f16`, followed by
+/// the header of the word2vec binary format
+const F16_FILE_MAGIC: &[u8] = b"word2vec-rust f16 vectors 1\n";
+
 impl NeuralNet {
     pub fn new(vocab_size: usize, layer1_size: usize) -> NeuralNet {
+        NeuralNet::with_seed(vocab_size, layer1_size, 0)
+    }
+
+    /// Same as `new`, drawing the random initial input vectors from a sequence that
+    /// depends on `seed`. Seed 0 gives the fixed sequence of the original word2vec.
+    pub fn with_seed(vocab_size: usize, layer1_size: usize, seed: u64) -> NeuralNet {
         let size = vocab_size * layer1_size;
         let mut net = NeuralNet {
             vocab_size,
             layer1_size,
             syn0: Vec::with_capacity(size),
             syn1neg: Vec::with_capacity(size),
+            syn1: Vec::new(),
+            huffman_tree: None,
+            bias: Vec::new(),
+            adam: None,
+            adagrad: None,
             locker: MemBlockLocker::new(),
         };
 
-        let mut lc_rand = LcRandomGen::new(1);
+        let mut lc_rand = if seed == 0 {
+            LcRandomGen::new(1)
+        } else {
+            LcRandomGen::for_stream(seed, WEIGHTS_STREAM)
+        };
         let rand_gen =
             || (((lc_rand.next_rand() & 0xffff) as f32 / 65536.0) - 0.5) / layer1_size as f32;
         net.syn0.resize_with(size, rand_gen);
@@ -84,6 +258,236 @@ impl NeuralNet {
         net
     }
 
+    /// Add a per word bias to the output layer, initially 0, which is added to the
+    /// score of each target word and trained along with the output weights when
+    /// `TrainigParams::output_bias` is set. This deviates from the original word2vec,
+    /// similar to the word biases of GloVe. Its updates are clipped like the output
+    /// weights by `TrainigParams::max_grad`, but they are always SGD steps of the
+    /// learning rate, even with adam or adagrad. The bias is not saved in model files,
+    /// it is kept if already enabled, e.g. in a net restored by `load_checkpoint`.
+    pub fn enable_output_bias(&mut self) {
+        if self.bias.is_empty() {
+            self.bias = vec![0.0; self.vocab_size];
+        }
+    }
+
+    /// Build the Huffman tree of `vocab` and allocate the output layer of hierarchical
+    /// softmax used when `TrainigParams::hierarchical_softmax` is set, initially 0 like
+    /// `syn1neg`. Neither is saved in model files, both are kept if already enabled,
+    /// e.g. in a net restored by `load_checkpoint`. Hierarchical softmax is trained in
+    /// addition to negative sampling, or instead of it if `negative_samples` is 0; it
+    /// doesn't support adam and the output bias only applies to negative sampling.
+    pub fn enable_hierarchical_softmax(&mut self, vocab: &Vocabulary) {
+        assert!(self.vocab_size == vocab.len());
+        if self.huffman_tree.is_none() {
+            self.huffman_tree = Some(HuffmanTree::new(vocab));
+            self.syn1 = vec![0.0; self.vocab_size * self.layer1_size];
+        }
+    }
+
+    /// Allocate the state of the Adam optimizer used when `TrainigParams::adam` is set,
+    /// 4 floats per weight of the net, tripling the memory used by training. Adam
+    /// replaces plain SGD and uses the learning rate as its step size, so
+    /// starting_alpha should be well below the SGD default, e.g. 0.001 to 0.005;
+    /// training is a few times slower. The gradient histogram then counts the
+    /// gradients before they are scaled by the step size.
+    pub fn enable_adam(&mut self) {
+        let size = self.vocab_size * self.layer1_size;
+        self.adam = Some(AdamState {
+            syn0_m: vec![0.0; size],
+            syn0_v: vec![0.0; size],
+            syn0_steps: vec![0; self.vocab_size],
+            syn1neg_m: vec![0.0; size],
+            syn1neg_v: vec![0.0; size],
+            syn1neg_steps: vec![0; self.vocab_size],
+        });
+    }
+
+    /// Allocate the state of the AdaGrad optimizer used when `TrainigParams::adagrad` is
+    /// set, 1 float per weight of the net, doubling the memory used by training.
+    /// AdaGrad divides the learning rate of each weight by the root of the sum of its
+    /// squared gradients, so weights updated often, like those of frequent words, take
+    /// smaller steps. As the steps shrink over time anyway, starting_alpha can be above
+    /// the SGD default, e.g. 0.05 to 0.5. It can't be combined with adam.
+    pub fn enable_adagrad(&mut self) {
+        let size = self.vocab_size * self.layer1_size;
+        self.adagrad = Some(AdaGradState {
+            syn0_sums: vec![ADAGRAD_INITIAL_ACCUMULATOR; size],
+            syn1neg_sums: vec![ADAGRAD_INITIAL_ACCUMULATOR; size],
+        });
+    }
+
+    /// return the output layer bias of each word, None if it is not enabled
+    pub fn output_bias(&self) -> Option<&[f32]> {
+        if self.bias.is_empty() {
+            None
+        } else {
+            Some(&self.bias)
+        }
+    }
+
+    /// return the number of words (rows) in the network
+    pub fn vocab_size(&self) -> usize {
+        self.vocab_size
+    }
+
+    /// return the dimension of the word vectors
+    pub fn layer1_size(&self) -> usize {
+        self.layer1_size
+    }
+
+    /// return the input vector (the `syn0` row) of the word with index `word_idx`
+    pub fn word_vector(&self, word_idx: usize) -> &[f32] {
+        &self.syn0[word_idx * self.layer1_size..(word_idx + 1) * self.layer1_size]
+    }
+
+    /// return false for nets loaded from model files, which only hold input vectors
+    pub(crate) fn has_output_layer(&self) -> bool {
+        self.syn1neg.len() == self.syn0.len()
+    }
+
+    /// return the output vector (the `syn1neg` row) of the word with index `word_idx`
+    pub(crate) fn output_vector(&self, word_idx: usize) -> &[f32] {
+        &self.syn1neg[word_idx * self.layer1_size..(word_idx + 1) * self.layer1_size]
+    }
+
+    /// return the number of NaN or infinite weights in the input vectors, the output
+    /// vectors and the output bias, e.g. to check a model before exporting it
+    pub fn count_nonfinite(&self) -> usize {
+        self.syn0
+            .iter()
+            .chain(&self.syn1neg)
+            .chain(&self.bias)
+            .filter(|x| !x.is_finite())
+            .count()
+    }
+
+    /// return true if no weight of the net is NaN or infinite, see `count_nonfinite`
+    pub fn is_finite(&self) -> bool {
+        self.syn0
+            .iter()
+            .chain(&self.syn1neg)
+            .chain(&self.bias)
+            .all(|x| x.is_finite())
+    }
+
+    /// scale every input vector (`syn0` row) to have L2 norm `target`, zero vectors
+    /// are left unchanged
+    pub fn rescale_to_norm(&mut self, target: f32) {
+        for row in self.syn0.chunks_exact_mut(self.layer1_size) {
+            let norm = row.iter().map(|x| x * x).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                let scale = target / norm;
+                row.iter_mut().for_each(|x| *x *= scale);
+            }
+        }
+    }
+
+    /// Same as `rescale_to_norm(1.0)`, while training threads may be updating the
+    /// vectors: each row is locked while it is rescaled if `use_locks` is set, like
+    /// the threads do unless they are lock-free. This is approximate, the threads keep
+    /// training the rows already rescaled during the pass and lock-free updates aren't
+    /// waited for, so the norms are only close to 1 afterwards
+    fn normalize_rows_concurrently(&self, use_locks: bool) {
+        let layer1_size = self.layer1_size;
+        for idx in 0..self.vocab_size {
+            let word_vector = self.word_vector(idx);
+            unsafe {
+                let mutable_unsafe_slice =
+                    slice_from_raw_parts_mut(word_vector.as_ptr().cast_mut(), layer1_size);
+
+                if use_locks {
+                    self.locker.lock(idx);
+                }
+                let norm = dot_product(word_vector, word_vector).sqrt();
+                if norm > 0.0 {
+                    for i in 0..layer1_size {
+                        (*mutable_unsafe_slice)[i] /= norm;
+                    }
+                }
+                if use_locks {
+                    self.locker.unlock(idx);
+                }
+            }
+        }
+    }
+
+    /// Apply an Adam step of size `alpha` for the gradient `grad` to row `row` of the
+    /// output layer if `output_layer` is set, else of the input vectors. The net is
+    /// shared by the training threads, the caller must hold the lock of `row`.
+    unsafe fn adam_update(&self, output_layer: bool, row: usize, grad: &[f32], alpha: f32) {
+        let adam = self
+            .adam
+            .as_ref()
+            .expect("adam requires NeuralNet::enable_adam");
+        let (weights, m, v, steps) = if output_layer {
+            (
+                &self.syn1neg,
+                &adam.syn1neg_m,
+                &adam.syn1neg_v,
+                &adam.syn1neg_steps,
+            )
+        } else {
+            (&self.syn0, &adam.syn0_m, &adam.syn0_v, &adam.syn0_steps)
+        };
+        let start = row * self.layer1_size;
+        let row_mut = |x: &Vec<f32>| unsafe {
+            &mut *slice_from_raw_parts_mut(x.as_ptr().add(start).cast_mut(), self.layer1_size)
+        };
+        adam_step(
+            row_mut(weights),
+            row_mut(m),
+            row_mut(v),
+            unsafe { &mut *steps.as_ptr().add(row).cast_mut() },
+            grad,
+            alpha,
+        );
+    }
+
+    /// Apply an AdaGrad step of size `alpha` for the gradient `grad` to row `row` of the
+    /// output layer if `output_layer` is set, else of the input vectors. The caller must
+    /// hold the lock of `row`, like for `adam_update`.
+    unsafe fn adagrad_update(&self, output_layer: bool, row: usize, grad: &[f32], alpha: f32) {
+        let adagrad = self
+            .adagrad
+            .as_ref()
+            .expect("adagrad requires NeuralNet::enable_adagrad");
+        let (weights, sums) = if output_layer {
+            (&self.syn1neg, &adagrad.syn1neg_sums)
+        } else {
+            (&self.syn0, &adagrad.syn0_sums)
+        };
+        let start = row * self.layer1_size;
+        let row_mut = |x: &Vec<f32>| unsafe {
+            &mut *slice_from_raw_parts_mut(x.as_ptr().add(start).cast_mut(), self.layer1_size)
+        };
+        let (weights, sums) = (row_mut(weights), row_mut(sums));
+        for i in 0..weights.len() {
+            sums[i] += grad[i] * grad[i];
+            weights[i] += alpha * grad[i] / sums[i].sqrt();
+        }
+    }
+
+    /// Project the component along `direction` out of every input vector (`syn0` row),
+    /// e.g. to remove a bias direction like gender; afterwards every vector is
+    /// orthogonal to `direction`, which doesn't need to have unit length
+    pub fn remove_component(&mut self, direction: &[f32]) {
+        assert!(
+            direction.len() == self.layer1_size,
+            "the direction must have the vector dimension"
+        );
+        let norm = dot_product(direction, direction).sqrt();
+        assert!(norm > 0.0, "the direction must not be a zero vector");
+        let unit: Vec<f32> = direction.iter().map(|x| x / norm).collect();
+
+        for row in self.syn0.chunks_exact_mut(self.layer1_size) {
+            let projection = dot_product(row, &unit);
+            for (x, u) in row.iter_mut().zip(&unit) {
+                *x -= projection * u;
+            }
+        }
+    }
+
     pub fn save(
         &self,
         vocab: &Vocabulary,
@@ -91,10 +495,119 @@ impl NeuralNet {
         binary: bool,
     ) -> Result<(), std::io::Error> {
         let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(output_file_name)?);
-        writeln!(buf_writer, "{} {}", self.vocab_size, self.layer1_size)?;
-        let syn0 = &self.syn0;
+        self.save_to(vocab, &mut buf_writer, binary)?;
+        buf_writer.flush()
+    }
+
+    /// @return the size in bytes of the file written by `save` in binary format, whose
+    /// header has the number of words of `vocab`, the vocabulary of the net
+    pub fn binary_size_bytes(&self, vocab: &Vocabulary) -> usize {
+        assert!(self.vocab_size == vocab.len());
+        let header = format!("{} {}\n", vocab.len(), self.layer1_size);
+        let vector_size = self.layer1_size * std::mem::size_of::<f32>();
+        // "<word> <vector>\n" for each word
+        header.len()
+            + vocab
+                .into_iter()
+                .map(|word| word.len() + 1 + vector_size + 1)
+                .sum::<usize>()
+    }
+
+    /// Same as `save`, writing to `writer`, letting callers choose their own buffering
+    pub fn save_to<W: Write>(
+        &self,
+        vocab: &Vocabulary,
+        writer: &mut W,
+        binary: bool,
+    ) -> Result<(), std::io::Error> {
+        self.save_filtered_to(vocab, writer, binary, |_| true)
+    }
+
+    /// Same as `save`, only writing the words for which `keep` returns true, e.g.
+    /// alphabetic words for a domain specific export. The word count in the header is
+    /// the number of words written.
+    pub fn save_filtered<F: FnMut(&str) -> bool>(
+        &self,
+        vocab: &Vocabulary,
+        output_file_name: &str,
+        binary: bool,
+        keep: F,
+    ) -> Result<(), std::io::Error> {
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(output_file_name)?);
+        self.save_filtered_to(vocab, &mut buf_writer, binary, keep)?;
+        buf_writer.flush()
+    }
+
+    /// Same as `save_filtered`, writing to `writer`
+    pub fn save_filtered_to<W: Write, F: FnMut(&str) -> bool>(
+        &self,
+        vocab: &Vocabulary,
+        writer: &mut W,
+        binary: bool,
+        keep: F,
+    ) -> Result<(), std::io::Error> {
+        self.write_model(vocab, writer, binary, false, keep)
+    }
+
+    /// Save the model in an extended binary format keeping the word counts, which the
+    /// word2vec format drops: the count of each word follows its vector as a 4 byte
+    /// integer in the byte order of the floats. Other word2vec tools can't read such
+    /// files, use `load_binary_with_counts` to load them.
+    pub fn save_binary_with_counts(
+        &self,
+        vocab: &Vocabulary,
+        output_file_name: &str,
+    ) -> Result<(), std::io::Error> {
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(output_file_name)?);
+        self.write_model(vocab, &mut buf_writer, true, true, |_| true)?;
+        buf_writer.flush()
+    }
+
+    /// Save the model in the word2vec binary format with each value stored as an IEEE
+    /// 754 half precision float instead of a 4 byte float, halving the file size. The
+    /// values are rounded to the nearest half, a relative error of at most 2^-11 for
+    /// magnitudes from 2^-14 to 65504, smaller magnitudes losing more precision and
+    /// larger ones becoming infinite. The file starts with a magic line, other
+    /// word2vec tools can't read it, use `load_binary_f16` or `load_auto` to load it.
+    pub fn save_binary_f16(
+        &self,
+        vocab: &Vocabulary,
+        output_file_name: &str,
+    ) -> Result<(), std::io::Error> {
+        assert!(self.vocab_size == vocab.len());
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(output_file_name)?);
+        buf_writer.write_all(F16_FILE_MAGIC)?;
+        writeln!(buf_writer, "{} {}", vocab.len(), self.layer1_size)?;
         for (idx, word) in vocab.into_iter().enumerate() {
             write!(buf_writer, "{word} ")?;
+            for &f in self.word_vector(idx) {
+                buf_writer.write_all(&f32_to_f16(f).to_ne_bytes())?;
+            }
+            writeln!(buf_writer)?;
+        }
+        buf_writer.flush()
+    }
+
+    /// write the words for which `keep` returns true, in binary format followed by
+    /// their counts if `with_counts` is set
+    fn write_model<W: Write, F: FnMut(&str) -> bool>(
+        &self,
+        vocab: &Vocabulary,
+        writer: &mut W,
+        binary: bool,
+        with_counts: bool,
+        keep: F,
+    ) -> Result<(), std::io::Error> {
+        // the header needs the number of words, so decide on all of them first
+        let kept: Vec<bool> = vocab.into_iter().map(keep).collect();
+        let kept_count = kept.iter().filter(|&&k| k).count();
+        writeln!(writer, "{} {}", kept_count, self.layer1_size)?;
+        let syn0 = &self.syn0;
+        for (idx, word) in vocab.into_iter().enumerate() {
+            if !kept[idx] {
+                continue;
+            }
+            write!(writer, "{word} ")?;
             let word_vec = &syn0[idx * self.layer1_size..(idx + 1) * self.layer1_size];
             if binary {
                 unsafe {
@@ -102,343 +615,3448 @@ impl NeuralNet {
                         word_vec.as_ptr() as *const u8,
                         word_vec.len() * std::mem::size_of_val(&word_vec[0]),
                     );
-                    buf_writer.write_all(data)?;
+                    writer.write_all(data)?;
+                }
+                if with_counts {
+                    writer.write_all(&vocab.word_count(idx).to_ne_bytes())?;
                 }
             } else {
                 for f in word_vec {
-                    write!(buf_writer, "{f:.06} ")?;
+                    write!(writer, "{f:.06} ")?;
                 }
             }
-            writeln!(buf_writer)?;
+            writeln!(writer)?;
         }
 
         Ok(())
     }
-}
 
-/// @return None on EOF, Some(-1) if token is not in the vocabulary, Some(token_index) otherwise
-fn read_word_index(fi: &mut FileTokenIterator, vocab: &Vocabulary) -> Option<i32> {
-    fi.read_token().map(|t| vocab.search_word(&t))
-}
+    /// Save everything needed to resume training at `position` with
+    /// `train_model_from`: the vocabulary and its `UNKNOWN_WORD` if rare words were
+    /// merged into it, the input vectors and the output layers,
+    /// including the hierarchical softmax layer and the output bias if enabled. The
+    /// optimizer state of adam or adagrad is not saved. The file is written under a
+    /// temporary name first and then renamed, so an interrupted save keeps the previous
+    /// checkpoint.
+    pub fn save_checkpoint(
+        &self,
+        vocab: &Vocabulary,
+        position: &TrainingPosition,
+        file_name: &str,
+    ) -> std::io::Result<()> {
+        let temp_file_name = format!("{file_name}.tmp");
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(&temp_file_name)?);
+        buf_writer.write_all(CHECKPOINT_FILE_MAGIC)?;
+        writeln!(
+            buf_writer,
+            "{} {} {} {} {} {} {} {} {} {} {}",
+            vocab.len(),
+            self.layer1_size,
+            self.huffman_tree.is_some() as u8,
+            !self.bias.is_empty() as u8,
+            // "</s>" is at index 0, so 0 means no unknown word
+            vocab.unknown_word_index().unwrap_or(0),
+            position.epoch,
+            position.word_count_actual,
+            position.threads.len(),
+            position.best_validation.is_some() as u8,
+            // the exact bits of the loss
+            position
+                .best_validation
+                .map_or(0, |(loss, _)| loss.to_bits()),
+            position.best_validation.map_or(0, |(_, epoch)| epoch)
+        )?;
+        for thread in &position.threads {
+            writeln!(buf_writer, "{} {}", thread.offset, thread.word_count)?;
+        }
+        vocab.save_to(&mut buf_writer)?;
+        for val in self
+            .syn0
+            .iter()
+            .chain(&self.syn1neg)
+            .chain(&self.syn1)
+            .chain(&self.bias)
+        {
+            buf_writer.write_all(&val.to_le_bytes())?;
+        }
+        buf_writer.flush()?;
+        drop(buf_writer);
+        std::fs::rename(&temp_file_name, file_name)
+    }
 
-/// @return the dot product of 2 f32 vectors
-fn dot_product(vec1: &[f32], vec2: &[f32]) -> f32 {
-    debug_assert!(vec1.len() == vec2.len());
-    vec1.iter()
-        .zip(vec2)
-        .fold(0.0, |acc, cur| acc + cur.0 * cur.1)
-}
+    /// Load a checkpoint saved by `save_checkpoint`
+    /// @return the vocabulary, the net and the position to resume training at
+    pub fn load_checkpoint(
+        file_name: &str,
+    ) -> std::io::Result<(Vocabulary, NeuralNet, TrainingPosition)> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "Invalid checkpoint file");
+        let mut buf_reader = BufReader::new(File::open(file_name)?);
+        let mut magic: Vec<u8> = Vec::new();
+        buf_reader.read_until(b'\n', &mut magic)?;
+        if magic != CHECKPOINT_FILE_MAGIC {
+            return Err(invalid());
+        }
 
-// /// y <- a * x + y, named after Fortran's axpy
-// fn axpy(a: f32, x: &[f32], y: &mut [f32]) {
-//     x.iter()
-//         .zip(y.iter_mut())
-//         .for_each(|(src, dest)| *dest += a * src);
-// }
+        let mut read_numbers = |count: usize| -> std::io::Result<Vec<u64>> {
+            let mut line = String::new();
+            buf_reader.read_line(&mut line)?;
+            let numbers: Vec<u64> = line
+                .split_ascii_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map_err(|_| invalid())?;
+            if numbers.len() != count {
+                return Err(invalid());
+            }
+            Ok(numbers)
+        };
+        let header = read_numbers(11)?;
+        let (words, layer1_size) = (header[0] as usize, header[1] as usize);
+        let (hierarchical_softmax, bias) = (header[2], header[3]);
+        let unknown_word = header[4] as usize;
+        if words == 0
+            || layer1_size == 0
+            || hierarchical_softmax > 1
+            || bias > 1
+            || unknown_word >= words
+            || header[8] > 1
+        {
+            return Err(invalid());
+        }
+        let mut position = TrainingPosition {
+            epoch: header[5],
+            word_count_actual: header[6],
+            threads: Vec::new(),
+            best_validation: Some((f64::from_bits(header[9]), header[10]))
+                .filter(|_| header[8] == 1),
+        };
+        for _ in 0..header[7] {
+            let thread = read_numbers(2)?;
+            position.threads.push(ThreadPosition {
+                offset: thread[0],
+                word_count: thread[1],
+            });
+        }
 
-pub struct TrainigProgress {
-    pub word_count_actual: AtomicU64,
-}
+        let mut vocab = Vocabulary::read_from(&mut buf_reader, words)?;
+        if unknown_word > 0 {
+            vocab.set_unknown_word(unknown_word);
+        }
+        let size = words * layer1_size;
+        let syn0 = read_f32_table(&mut buf_reader, size)?;
+        let mut net = NeuralNet::from_input_vectors(layer1_size, syn0);
+        net.syn1neg = read_f32_table(&mut buf_reader, size)?;
+        if hierarchical_softmax == 1 {
+            net.huffman_tree = Some(HuffmanTree::new(&vocab));
+            net.syn1 = read_f32_table(&mut buf_reader, size)?;
+        }
+        if bias == 1 {
+            net.bias = read_f32_table(&mut buf_reader, words)?;
+        }
+        Ok((vocab, net, position))
+    }
 
-const MAX_SENTENCE_LENGTH: usize = 1024;
+    /// Load a model saved in binary or text format, a half precision model saved by
+    /// `save_binary_f16`, or the vocabulary and the net bundled in a checkpoint saved
+    /// by `save_checkpoint`. The last two are detected by their magic number, else the
+    /// format is detected by looking at the data following the first word: a text file
+    /// only has characters that can make up printed numbers there, a binary file has
+    /// raw float bytes.
+    pub fn load_auto(file_name: &str) -> Result<(Vocabulary, NeuralNet), W2vError> {
+        let mut reader = BufReader::new(File::open(file_name)?);
+        let mut first_line: Vec<u8> = vec![];
+        reader.read_until(b'\n', &mut first_line)?;
+        if first_line == CHECKPOINT_FILE_MAGIC {
+            let (vocab, net, _) = NeuralNet::load_checkpoint(file_name)?;
+            return Ok((vocab, net));
+        }
+        if first_line == F16_FILE_MAGIC {
+            return Ok(NeuralNet::load_binary_f16(file_name)?);
+        }
+        let (_, layer1_size) =
+            read_model_header(&mut first_line.as_slice()).map_err(|_| W2vError::UnknownFormat)?;
 
-/// train the word2vec neural net `net` with training data found in `training_file`
-pub fn train_model_thread(
-    net: Arc<NeuralNet>,
-    vocab: &Vocabulary,
-    thread_id: usize,
-    params: &TrainigParams,
-    progress: &TrainigProgress,
-) -> Result<(), std::io::Error> {
-    assert!(net.vocab_size == vocab.len());
-    assert!(net.vocab_size * net.layer1_size == net.syn0.len());
-    assert!(net.syn0.len() == net.syn1neg.len());
+        let mut word: Vec<u8> = vec![];
+        reader.read_until(b' ', &mut word)?;
+        let mut data: Vec<u8> = vec![];
+        reader
+            .take((layer1_size * std::mem::size_of::<f32>()) as u64)
+            .read_to_end(&mut data)?;
 
-    let offset = params.training_file_size / params.num_threads as u64 * thread_id as u64;
-    let mut fi = FileTokenIterator::new(&params.training_file, offset)?;
-    let mut eof_reached: bool = false;
-    let layer1_size = net.layer1_size;
+        let is_text = data
+            .iter()
+            .all(|b| b.is_ascii_whitespace() || b"0123456789+-.eEinfaINFA".contains(b));
+        let loaded = if is_text {
+            NeuralNet::load_text(file_name)?
+        } else {
+            NeuralNet::load_binary(file_name)?
+        };
+        Ok(loaded)
+    }
 
-    let mut neu1: Vec<f32> = Vec::with_capacity(layer1_size);
-    neu1.resize(layer1_size, 0.0);
-    let mut neu1e: Vec<f32> = Vec::with_capacity(layer1_size);
-    neu1e.resize(layer1_size, 0.0);
+    /// Load a model saved in the word2vec binary format. The output layer is not part
+    /// of the file, so the loaded net can only be queried. None of the training state
+    /// is allocated: the vocabulary has no unigram table and a hash table sized for
+    /// its words, so memory use stays close to the size of the vectors.
+    pub fn load_binary(file_name: &str) -> std::io::Result<(Vocabulary, NeuralNet)> {
+        NeuralNet::read_binary(file_name, false, false)
+    }
 
-    let mut rand_gen = LcRandomGen::new(thread_id as i64);
-    // progress tracking
-    let mut word_count: u64 = 0;
-    let mut last_word_count: u64 = 0;
-    let start: Instant = Instant::now();
+    /// Load a model saved by `save_binary_with_counts`, restoring the word counts of
+    /// the vocabulary. Like for `load_binary` no unigram table is built.
+    pub fn load_binary_with_counts(file_name: &str) -> std::io::Result<(Vocabulary, NeuralNet)> {
+        NeuralNet::read_binary(file_name, true, false)
+    }
 
-    let mut sentence = [-1; MAX_SENTENCE_LENGTH + 1];
-    let mut sentence_length: usize = 0;
-    let mut sentence_position: usize = 0;
-    let mut local_iter = params.total_iter;
-    let mut alpha: f32 = params.starting_alpha;
+    /// Load a model saved by `save_binary_f16`, converting the values back to 4 byte
+    /// floats
+    pub fn load_binary_f16(file_name: &str) -> std::io::Result<(Vocabulary, NeuralNet)> {
+        NeuralNet::read_binary(file_name, false, true)
+    }
 
-    'thread_loop: loop {
-        // This block prints a progress update, and also adjusts the training
-        // 'alpha' parameter.
-        if word_count - last_word_count > 10000 {
-            progress
-                .word_count_actual
-                .fetch_add(word_count - last_word_count, Ordering::Relaxed);
-            last_word_count = word_count;
+    /// read a binary model, its vectors being followed by word counts if `with_counts`
+    /// is set and made of half precision values if `f16` is set
+    fn read_binary(
+        file_name: &str,
+        with_counts: bool,
+        f16: bool,
+    ) -> std::io::Result<(Vocabulary, NeuralNet)> {
+        let mut reader = BufReader::new(File::open(file_name)?);
+        if f16 {
+            let mut magic: Vec<u8> = Vec::new();
+            reader.read_until(b'\n', &mut magic)?;
+            if magic != F16_FILE_MAGIC {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Not a half precision model file",
+                ));
+            }
+        }
+        let (vocab_size, layer1_size) = read_model_header(&mut reader)?;
 
-            let wc = progress.word_count_actual.load(Ordering::Relaxed) as f64;
+        let mut words: Vec<(String, u32)> = Vec::with_capacity(vocab_size);
+        let mut syn0: Vec<f32> = Vec::with_capacity(vocab_size * layer1_size);
+        let mut word: Vec<u8> = vec![];
+        let value_size = if f16 { 2 } else { std::mem::size_of::<f32>() };
+        let mut data: Vec<u8> = vec![0; layer1_size * value_size];
+        let mut count: [u8; 4] = [0; 4];
+        for _ in 0..vocab_size {
+            word.clear();
+            reader.read_until(b' ', &mut word)?;
+            // the previous vector is followed by a newline
+            let word = word.trim_ascii();
+            if word.is_empty() {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Missing word"));
+            }
+            let word = String::from_utf8_lossy(word).into_owned();
 
-            // The percentage complete is based on the total number of passes we are
-            // doing and not just the current pass.
-            if params.debug_mode > 1 {
-                print!(
-                    "\rAlpha: {alpha:.06} Progress: {:.02}%  Words/sec: {:.02}k ",
-                    wc / (params.total_iter * vocab.train_words() + 1) as f64 * 100_f64,
-                    (wc / 1000_f64) / start.elapsed().as_secs_f64()
+            reader.read_exact(&mut data)?;
+            if f16 {
+                syn0.extend(
+                    data.chunks_exact(2)
+                        .map(|b| f16_to_f32(u16::from_ne_bytes([b[0], b[1]]))),
+                );
+            } else {
+                syn0.extend(
+                    data.chunks_exact(4)
+                        .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]])),
                 );
             }
-
-            std::io::stdout().flush().unwrap_or_default();
-
-            // Update alpha to: [initial alpha] * [percent of training remaining]
-            // This means that alpha will gradually decrease as we progress through
-            // the training text.
-            alpha = params.starting_alpha
-                * (1_f32 - wc as f32 / (params.total_iter * vocab.train_words() + 1) as f32);
-
-            // Don't let alpha go below [initial alpha] * 0.0001.
-            if alpha < params.starting_alpha * 0.0001 {
-                alpha = params.starting_alpha * 0.0001;
+            if with_counts {
+                reader.read_exact(&mut count)?;
             }
+            words.push((word, u32::from_ne_bytes(count)));
         }
 
-        // Retrieve the next sentence from the training set and store it in `sentence`
-        if sentence_length == 0 {
-            loop {
-                let idx = match read_word_index(&mut fi, vocab) {
-                    Some(x) if x < 0 => continue,
-                    Some(x) if x as usize >= net.vocab_size => continue,
-                    Some(x) => x,
-                    None => {
-                        eof_reached = true;
-                        break;
-                    }
-                };
-
-                word_count += 1;
+        let vocab = Vocabulary::from_word_counts(words)?;
+        Ok((vocab, NeuralNet::from_input_vectors(layer1_size, syn0)))
+    }
 
-                // word 0 is the special token "</s>" which indicates the end of a sentence
-                if idx == 0 {
-                    // an empty sentence, or one consisting only of out-of-vocabulary words
-                    if sentence_length == 0 {
-                        continue;
-                    }
-                    break;
-                }
+    /// Load a model saved in the word2vec text format. The output layer is not part
+    /// of the file, so the loaded net can only be queried. None of the training state
+    /// is allocated: the vocabulary has no unigram table and a hash table sized for
+    /// its words, so memory use stays close to the size of the vectors.
+    pub fn load_text(file_name: &str) -> std::io::Result<(Vocabulary, NeuralNet)> {
+        let mut reader = BufReader::new(File::open(file_name)?);
+        let (vocab_size, layer1_size) = read_model_header(&mut reader)?;
 
-                sentence[sentence_length] = idx;
-                sentence_length += 1;
-                if sentence_length > MAX_SENTENCE_LENGTH {
-                    break;
-                }
-                sentence_position = 0;
+        let mut words: Vec<String> = Vec::with_capacity(vocab_size);
+        let mut syn0: Vec<f32> = Vec::with_capacity(vocab_size * layer1_size);
+        let mut line = String::new();
+        while words.len() < vocab_size {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Missing word"));
+            }
+            let Some((word, vec_len)) = parse_vector_line(&line, &mut syn0)? else {
+                continue;
+            };
+            if vec_len != layer1_size {
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid vector size"));
             }
+            words.push(word.to_string());
         }
 
-        if (sentence_length == 0 && eof_reached)
-            || (word_count > vocab.train_words() / params.num_threads as u64)
-        {
-            local_iter -= 1;
-            if local_iter == 0 {
-                break 'thread_loop;
+        let vocab = Vocabulary::from_model_words(words)?;
+        Ok((vocab, NeuralNet::from_input_vectors(layer1_size, syn0)))
+    }
+
+    /// Save the input vectors in the GloVe text format: one "word v1 v2 ..." line per
+    /// word, without the "<vocab_size> <layer1_size>" header of the word2vec format
+    pub fn save_glove(&self, vocab: &Vocabulary, output_file_name: &str) -> std::io::Result<()> {
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(output_file_name)?);
+        for (idx, word) in vocab.into_iter().enumerate() {
+            write!(buf_writer, "{word}")?;
+            for f in self.word_vector(idx) {
+                write!(buf_writer, " {f:.06}")?;
             }
-            word_count = 0;
-            last_word_count = 0;
-            sentence_length = 0;
-            // alpha *= 0.75;
-            fi.reset(offset)?;
-            eof_reached = false;
-            continue 'thread_loop;
+            writeln!(buf_writer)?;
         }
+        buf_writer.flush()
+    }
 
-        let word = sentence[sentence_position];
-        // assertion taken care of when filling sentence
-        debug_assert!(word >= 0 && (word as usize) < net.vocab_size);
+    /// Export the vectors of the `k` most frequent words (ignoring "</s>") for the
+    /// TensorFlow Embedding Projector: `vectors_file` gets one line of tab separated
+    /// values per word and `metadata_file` the matching words, one per line.
+    /// Words with equal counts keep their vocabulary order.
+    pub fn export_top_k_tsv(
+        &self,
+        vocab: &Vocabulary,
+        k: usize,
+        vectors_file: &str,
+        metadata_file: &str,
+    ) -> std::io::Result<()> {
+        let mut indices: Vec<usize> = (1..vocab.len().min(self.vocab_size)).collect();
+        indices.sort_by_key(|&idx| u32::MAX - vocab.word_count(idx));
+        indices.truncate(k);
+
+        let mut vectors: BufWriter<File> = BufWriter::new(File::create(vectors_file)?);
+        let mut metadata: BufWriter<File> = BufWriter::new(File::create(metadata_file)?);
+        for idx in indices {
+            let values: Vec<String> = self
+                .word_vector(idx)
+                .iter()
+                .map(|f| format!("{f:.06}"))
+                .collect();
+            writeln!(vectors, "{}", values.join("\t"))?;
+            writeln!(metadata, "{}", vocab.word(idx))?;
+        }
+        vectors.flush()?;
+        metadata.flush()
+    }
 
-        neu1.fill(0.0);
-        // `cw` stores the context word count
-        let mut cw = 0;
-        let b = rand_gen.next_rand() as usize % params.window;
+    /// Load vectors saved in the GloVe text format, the vector size is given by the
+    /// first line. GloVe files usually lack the "</s>" sentence separator that other
+    /// functions expect at index 0, if so it is inserted with a zero vector. The
+    /// loaded net has no output layer, so it can only be queried.
+    pub fn load_glove(file_name: &str) -> std::io::Result<(Vocabulary, NeuralNet)> {
+        let reader = BufReader::new(File::open(file_name)?);
+        let mut words: Vec<String> = Vec::new();
+        let mut syn0: Vec<f32> = Vec::new();
+        let mut layer1_size: usize = 0;
 
-        for a in b..params.window * 2 + 1 - b {
-            if a == params.window {
+        for line in reader.lines() {
+            let line = line?;
+            let Some((word, vec_len)) = parse_vector_line(&line, &mut syn0)? else {
                 continue;
+            };
+            if layer1_size == 0 {
+                layer1_size = vec_len;
             }
-            let c: isize = sentence_position as isize - params.window as isize + a as isize;
-            if c < 0 || c >= sentence_length as isize {
-                continue;
+            if vec_len == 0 || vec_len != layer1_size {
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid vector size"));
             }
+            words.push(word.to_string());
+        }
 
-            let last_word = sentence[c as usize] as usize;
+        if words.is_empty() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "Missing word"));
+        }
+        if words[0] != "</s>" {
+            words.insert(0, String::from("</s>"));
+            syn0.splice(0..0, std::iter::repeat_n(0.0, layer1_size));
+        }
 
-            // sum all the context word vectors and store the result in neu1
-            let net_word_index = last_word * layer1_size;
-            let word_vec = unsafe {
-                net.syn0
-                    .get_unchecked(net_word_index..net_word_index + layer1_size)
-            };
-            for i in 0..neu1.len() {
-                neu1[i] += word_vec[i];
-            }
-            cw += 1;
-        }
-
-        // if there were any context words
-        if cw > 0 {
-            // neu1e is used in this block only
-            neu1e.fill(0.0);
-            // `neu1` is the sum of the context word vectors, and now
-            // becomes their average.
-            for n in &mut neu1 {
-                *n /= cw as f32;
-            }
-
-            // NEGATIVE SAMPLING
-            // Rather than performing backpropagation for every word in our
-            // vocabulary, we only perform it for the positive sample and a few
-            // negative samples (the number of words is given by 'negative').
-            // These negative words are selected using a "unigram" distribution,
-            // which is generated in the function InitUnigramTable.
-            for d in 0..params.negative_samples + 1 {
-                let target: i32;
-                let label: f32;
-
-                if d == 0 {
-                    // On the first iteration, we're going to train the positive sample.
-                    target = word;
-                    label = 1.0;
-                } else {
-                    // On the other iterations, we'll train the negative samples.
-                    // Pick a random word to use as a 'negative sample'; do this using
-                    // the unigram table.
-                    target = vocab.sample_random_word(rand_gen.next_rand());
-                    // Don't use the positive sample as a negative sample!
-                    if target == word {
-                        continue;
-                    }
-                    // this condition allows us to use unsafe code to index the nets
-                    if target < 0 || target as usize >= net.vocab_size {
-                        continue;
-                    }
-                    // Mark this as a negative example.
-                    label = 0.0;
-                }
+        let vocab = Vocabulary::from_model_words(words)?;
+        Ok((vocab, NeuralNet::from_input_vectors(layer1_size, syn0)))
+    }
 
-                // At this point, target might either be the positive sample or a
-                // negative sample, depending on the value of `label`.
-
-                // Get the index of the target word in the output layer.
-                let l2 = target as usize * layer1_size;
-                let target_output_weights =
-                    unsafe { net.syn1neg.get_unchecked(l2..l2 + layer1_size) };
-
-                // Calculate the dot product between:
-                //   neu1 - The average of the context word vectors.
-                //   syn1neg[l2] - The output weights for the target word.
-                let f: f32 = dot_product(&neu1, target_output_weights);
-
-                // This block does two things:
-                //   1. Calculates the output of the network for this training
-                //      pair, using the expTable to evaluate the output layer
-                //      activation function.
-                //   2. Calculate the error at the output, stored in 'g', by
-                //      subtracting the network output from the desired output,
-                //      and finally multiply this by the learning rate.
-
-                // activation function: 1 / (1 + e^(-x)) = e^x / (e^x + 1)
-                let expx = f64::exp(f as f64);
-                let output = expx / (expx + 1.0);
-                let err = (label - output as f32) * alpha;
-
-                // Multiply the error by the output layer weights.
-                // (I think this is the gradient calculation?)
-                // Accumulate these gradients over all of the negative samples.
-                for i in 0..layer1_size {
-                    neu1e[i] += err * target_output_weights[i];
-                }
+    /// Construct a net for training on `vocab` that starts from the input vectors of
+    /// the pretrained `pretrained` of the words `pretrained_vocab`, e.g. loaded with
+    /// `load_auto`, to fine-tune them on a new corpus. The words of `vocab` known to
+    /// the pretrained model get its vector, the others a random one like `with_seed`
+    /// draws. The output layer starts at 0 as it is not saved in model files.
+    /// @return the net and the number of words that got a pretrained vector
+    pub fn warm_start(
+        vocab: &Vocabulary,
+        pretrained_vocab: &Vocabulary,
+        pretrained: &NeuralNet,
+        seed: u64,
+    ) -> (NeuralNet, usize) {
+        let layer1_size = pretrained.layer1_size;
+        let mut net = NeuralNet::with_seed(vocab.len(), layer1_size, seed);
+        let mut reused = 0;
+        for (word_idx, word) in vocab.into_iter().enumerate() {
+            let pretrained_idx = pretrained_vocab.search_word(word);
+            if pretrained_idx >= 0 && (pretrained_idx as usize) < pretrained.vocab_size {
+                net.syn0[word_idx * layer1_size..(word_idx + 1) * layer1_size]
+                    .copy_from_slice(pretrained.word_vector(pretrained_idx as usize));
+                reused += 1;
+            }
+        }
+        (net, reused)
+    }
 
-                // Update the output layer weights by multiplying the output error
-                // by the average of the context word vectors.
-                unsafe {
-                    let target_output_weights_mut = slice_from_raw_parts_mut(
-                        target_output_weights.as_ptr().cast_mut(),
-                        target_output_weights.len(),
-                    );
+    /// Merge two models trained on different data into one over the union of their
+    /// vocabularies: words known to both models get the average of their two vectors,
+    /// the other words keep the vector of the model they come from. Word counts are
+    /// summed and the merged vocabulary is sorted by count. The merged net has no
+    /// output layer, so it can only be queried.
+    pub fn merge_models(
+        a: &NeuralNet,
+        vocab_a: &Vocabulary,
+        b: &NeuralNet,
+        vocab_b: &Vocabulary,
+    ) -> (Vocabulary, NeuralNet) {
+        assert!(
+            a.layer1_size == b.layer1_size,
+            "can't merge models with different vector sizes"
+        );
+        let layer1_size = a.layer1_size;
 
-                    net.locker.lock(target as usize);
-                    for (i, n) in neu1.iter().enumerate() {
-                        (*target_output_weights_mut)[i] += n;
-                    }
-                    net.locker.unlock(target as usize);
-                }
+        // (word, count, index in a, index in b)
+        let mut words: Vec<(&str, u32, Option<usize>, Option<usize>)> = vec![];
+        for idx in 0..vocab_a.len() {
+            let word = vocab_a.word(idx);
+            let idx_b = usize::try_from(vocab_b.search_word(word)).ok();
+            let count_b = idx_b.map_or(0, |i| vocab_b.word_count(i));
+            words.push((
+                word,
+                vocab_a.word_count(idx).saturating_add(count_b),
+                Some(idx),
+                idx_b,
+            ));
+        }
+        for idx in 0..vocab_b.len() {
+            let word = vocab_b.word(idx);
+            if vocab_a.search_word(word) == -1 {
+                words.push((word, vocab_b.word_count(idx), None, Some(idx)));
             }
+        }
+        // keep "</s>" at index 0 if both vocabularies have it there
+        let first = usize::from(!words.is_empty() && words[0].0 == "</s>");
+        words[first..].sort_by_key(|w| u32::MAX - w.1);
 
-            // hidden -> in
-            // Backpropagate the error to the hidden layer (the word vectors).
-            // This code is used both for heirarchical softmax and for negative
-            // sampling.
-            //
-            // Loop over the positions in the context window (skipping the word at
-            // the center). 'a' is just the offset within the window, it's not
-            // the index relative to the beginning of the sentence.
-            for a in b..params.window * 2 + 1 - b {
-                if a == params.window {
-                    continue;
-                }
-                // Convert the window offset 'a' into an index 'c' into the sentence
-                // array.
-                let c: isize = sentence_position as isize - params.window as isize + a as isize;
+        let mut syn0: Vec<f32> = Vec::with_capacity(words.len() * layer1_size);
+        for &(_, _, idx_a, idx_b) in &words {
+            match (idx_a, idx_b) {
+                (Some(i), Some(j)) => syn0.extend(
+                    a.word_vector(i)
+                        .iter()
+                        .zip(b.word_vector(j))
+                        .map(|(x, y)| (x + y) / 2.0),
+                ),
+                (Some(i), None) => syn0.extend_from_slice(a.word_vector(i)),
+                (None, Some(j)) => syn0.extend_from_slice(b.word_vector(j)),
+                (None, None) => unreachable!(),
+            }
+        }
 
-                // Verify c isn't outisde the bounds of the sentence.
-                if c < 0 || c >= sentence_length as isize {
+        let vocab = Vocabulary::from_word_counts(
+            words
+                .iter()
+                .map(|&(word, count, _, _)| (word.to_string(), count))
+                .collect(),
+        )
+        .expect("merged words are unique");
+        (vocab, NeuralNet::from_input_vectors(layer1_size, syn0))
+    }
+
+    /// construct a net from loaded input vectors, without an output layer
+    pub(crate) fn from_input_vectors(layer1_size: usize, syn0: Vec<f32>) -> NeuralNet {
+        NeuralNet {
+            vocab_size: syn0.len() / layer1_size,
+            layer1_size,
+            syn0,
+            syn1neg: Vec::new(),
+            syn1: Vec::new(),
+            huffman_tree: None,
+            bias: Vec::new(),
+            adam: None,
+            adagrad: None,
+            locker: MemBlockLocker::new(),
+        }
+    }
+}
+
+/// read `len` little endian f32 values from `reader`
+fn read_f32_table<R: Read>(reader: &mut R, len: usize) -> std::io::Result<Vec<f32>> {
+    let mut bytes: Vec<u8> = vec![0; len * 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+/// read the "<vocab_size> <layer1_size>" header line of a model file
+fn read_model_header<R: BufRead>(reader: &mut R) -> std::io::Result<(usize, usize)> {
+    let mut header = String::new();
+    reader.read_line(&mut header)?;
+    let mut parts = header.split_ascii_whitespace().map(|x| x.parse::<usize>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(vocab_size)), Some(Ok(layer1_size))) if layer1_size > 0 => {
+            Ok((vocab_size, layer1_size))
+        }
+        _ => Err(Error::new(ErrorKind::InvalidData, "Invalid model header")),
+    }
+}
+
+/// Parse a "word v1 v2 ..." line of a text model file, appending the values to `syn0`
+/// @return the word and the number of values read, None for a blank line
+fn parse_vector_line<'a>(
+    line: &'a str,
+    syn0: &mut Vec<f32>,
+) -> std::io::Result<Option<(&'a str, usize)>> {
+    let mut parts = line.split_ascii_whitespace();
+    let Some(word) = parts.next() else {
+        return Ok(None);
+    };
+
+    let vec_len = syn0.len();
+    for part in parts {
+        let Ok(val) = part.parse() else {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid vector value"));
+        };
+        syn0.push(val);
+    }
+    Ok(Some((word, syn0.len() - vec_len)))
+}
+
+/// @return None on EOF, Some(-1) if token is not in the vocabulary, Some(token_index) otherwise
+fn read_word_index(fi: &mut FileTokenIterator, vocab: &Vocabulary) -> Option<i32> {
+    fi.read_token().map(|t| vocab.word_index_or_unknown(&t))
+}
+
+/// The vocabulary indices of the words a training thread learns from, with their
+/// weights (see `TokenizerParams::token_weights`)
+enum WordSource {
+    /// read from the training file
+    File(FileTokenIterator),
+    /// buffered in memory by `read_reversed_sentences`, consumed from the back
+    Reversed(ReversedSentences),
+}
+
+impl WordSource {
+    /// @return None at the end of the data, otherwise the next word index, -1 for an
+    /// out-of-vocabulary word, and its weight
+    fn next_word_index(&mut self, vocab: &Vocabulary) -> Option<(i32, f32)> {
+        match self {
+            WordSource::File(fi) => fi
+                .read_weighted_token()
+                .map(|(t, weight)| (vocab.word_index_or_unknown(&t), weight)),
+            WordSource::Reversed(sentences) => sentences.pop(),
+        }
+    }
+
+    /// @return the offset in the training file of the next word, None if the words
+    /// are not read in file order
+    fn position(&self) -> Option<u64> {
+        match self {
+            WordSource::File(fi) => Some(fi.position()),
+            WordSource::Reversed(_) => None,
+        }
+    }
+}
+
+/// The word indices read by `read_reversed_sentences`
+struct ReversedSentences {
+    indices: Vec<i32>,
+    /// the weight of each word, empty if the tokens have no weights
+    weights: Vec<f32>,
+}
+
+impl ReversedSentences {
+    fn pop(&mut self) -> Option<(i32, f32)> {
+        let idx = self.indices.pop()?;
+        Some((idx, self.weights.pop().unwrap_or(1.0)))
+    }
+}
+
+/// Read the vocabulary indices of the words of `fi`, skipping out-of-vocabulary words,
+/// until EOF or until more than `max_words` were read, like a training thread would.
+/// Each sentence is reversed in place, so popping the indices from the back yields
+/// the sentences in reverse order, each with its words in the original order. The
+/// word weights are kept if `weighted` is set.
+fn read_reversed_sentences(
+    fi: &mut FileTokenIterator,
+    vocab: &Vocabulary,
+    max_words: u64,
+    weighted: bool,
+) -> ReversedSentences {
+    let mut result = ReversedSentences {
+        indices: Vec::new(),
+        weights: Vec::new(),
+    };
+    while result.indices.len() as u64 <= max_words {
+        match fi.read_weighted_token() {
+            Some((token, weight)) => {
+                let idx = vocab.word_index_or_unknown(&token);
+                if idx < 0 {
                     continue;
                 }
+                result.indices.push(idx);
+                if weighted {
+                    result.weights.push(weight);
+                }
+            }
+            None => break,
+        }
+    }
+    // sentences end with "</s>" (index 0), except maybe the last one
+    let mut start = 0;
+    for sentence in result.indices.split_inclusive_mut(|&idx| idx == 0) {
+        sentence.reverse();
+        if weighted {
+            result.weights[start..start + sentence.len()].reverse();
+        }
+        start += sentence.len();
+    }
+    result
+}
 
-                // Get the context word. That is, get the id of the word (its index in
-                // the vocab table).
-                let last_word = sentence[c as usize] as usize;
+/// @return the bits of the IEEE 754 half precision float nearest to `value`, ties
+/// rounding to even; out of range values become infinite. Converted by hand rather
+/// than with the `half` crate, so that the crate keeps having no dependencies and no
+/// feature flag; the tests round-trip every half value through both conversions.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exp == 0xff {
+        // infinity, or NaN keeping a non-zero mantissa
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
 
-                // Add the gradient in the vector `neu1e` to the word vector for
-                // the current context word.
-                // syn0[last_word * layer1_size] <-- Accesses the word vector.
-                let word_vector = unsafe {
-                    net.syn0
-                        .get_unchecked(last_word * layer1_size..(last_word + 1) * layer1_size)
-                };
-                unsafe {
-                    let mutable_unsafe_slice = slice_from_raw_parts_mut(
-                        word_vector.as_ptr().cast_mut(),
-                        word_vector.len(),
-                    );
+    // round `half` up if the `shift` bits dropped from `full` are above the halfway
+    // point, or at it and `half` is odd
+    let round = |half: u32, full: u32, shift: u32| {
+        let rem = full & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        if rem > halfway || (rem == halfway && half & 1 == 1) {
+            half + 1
+        } else {
+            half
+        }
+    };
+    let exp = exp - 127 + 15;
+    if exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if exp <= 0 {
+        // a subnormal half or zero, a carry gives the smallest normal half
+        if exp < -10 {
+            return sign;
+        }
+        let full = mantissa | 0x80_0000;
+        let shift = (14 - exp) as u32;
+        return sign | round(full >> shift, full, shift) as u16;
+    }
+    // a carry into the exponent gives the next power of 2, or infinity
+    let half = ((exp as u32) << 10) | (mantissa >> 13);
+    sign | round(half, mantissa, 13) as u16
+}
+
+/// @return the value of the IEEE 754 half precision float with bits `half`
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half & 0x8000) as u32) << 16;
+    let exp = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+    match exp {
+        0 => {
+            let value = mantissa as f32 * f32::powi(2.0, -24);
+            if sign != 0 { -value } else { value }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exp + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+/// @return the dot product of 2 f32 vectors
+fn dot_product(vec1: &[f32], vec2: &[f32]) -> f32 {
+    debug_assert!(vec1.len() == vec2.len());
+    vec1.iter()
+        .zip(vec2)
+        .fold(0.0, |acc, cur| acc + cur.0 * cur.1)
+}
+
+/// neu1 <- neu1 + word_vec, with `word_vec` scaled to unit length first if `normalize`
+/// is set (zero vectors are added unchanged)
+fn add_context_vector(neu1: &mut [f32], word_vec: &[f32], normalize: bool) {
+    debug_assert!(neu1.len() == word_vec.len());
+    let mut scale: f32 = 1.0;
+    if normalize {
+        let norm = dot_product(word_vec, word_vec).sqrt();
+        if norm > 0.0 {
+            scale = 1.0 / norm;
+        }
+    }
+    for (n, w) in neu1.iter_mut().zip(word_vec) {
+        *n += w * scale;
+    }
+}
+
+/// Adam update of `weights` for the gradient `grad` of the objective being maximized,
+/// given the moment estimates `m` and `v` and the number of previous updates `steps`
+fn adam_step(
+    weights: &mut [f32],
+    m: &mut [f32],
+    v: &mut [f32],
+    steps: &mut u32,
+    grad: &[f32],
+    alpha: f32,
+) {
+    *steps = steps.saturating_add(1);
+    let t = (*steps).min(i32::MAX as u32) as i32;
+    let correction1 = 1.0 - ADAM_BETA1.powi(t);
+    let correction2 = 1.0 - ADAM_BETA2.powi(t);
+    for i in 0..weights.len() {
+        let g = grad[i];
+        m[i] = ADAM_BETA1 * m[i] + (1.0 - ADAM_BETA1) * g;
+        v[i] = ADAM_BETA2 * v[i] + (1.0 - ADAM_BETA2) * g * g;
+        weights[i] += alpha * (m[i] / correction1) / ((v[i] / correction2).sqrt() + ADAM_EPSILON);
+    }
+}
+
+// /// y <- a * x + y, named after Fortran's axpy
+// fn axpy(a: f32, x: &[f32], y: &mut [f32]) {
+//     x.iter()
+//         .zip(y.iter_mut())
+//         .for_each(|(src, dest)| *dest += a * src);
+// }
+
+/// Where a training thread is in its part of the training file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ThreadPosition {
+    /// the offset of the next sentence the thread trains on, `u64::MAX` once it
+    /// finished the epoch
+    pub offset: u64,
+    /// the number of words the thread read in the epoch before that sentence
+    pub word_count: u64,
+}
+
+/// How far a training run got, saved in checkpoints to resume it with
+/// `train_model_from`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainingPosition {
+    /// the epoch being trained, counting from 0
+    pub epoch: u64,
+    /// the total number of words trained on, `TrainigProgress::word_count_actual`
+    pub word_count_actual: u64,
+    /// the position of each training thread in the epoch
+    pub threads: Vec<ThreadPosition>,
+    /// `TrainigProgress::best_validation`, so that early stopping resumes with it
+    pub best_validation: Option<(f64, u64)>,
+}
+
+pub struct TrainigProgress {
+    pub word_count_actual: AtomicU64,
+    /// number of sentences without any token (e.g. blank lines) in the training file,
+    /// counted in each epoch trained from its start; approximate with several threads,
+    /// which may stop a little before or after the end of their part of the file. None
+    /// if no epoch was trained from its start, e.g. when resuming in the last epoch.
+    pub empty_sentences: Mutex<Option<u64>>,
+    /// the time training started, used for reporting the training speed
+    pub start: Instant,
+    /// histogram of the L2 norms of the updates applied to the context vectors, only
+    /// filled if `TrainigParams::gradient_histogram` is set, see `gradient_bucket`
+    pub gradient_histogram: [AtomicU64; GRADIENT_HISTOGRAM_BUCKETS],
+    /// the wall-clock time of each completed epoch, excluding the loss computed for
+    /// `TrainigParams::loss_log_path`
+    pub epoch_durations: Mutex<Vec<Duration>>,
+    /// number of output layer dot products computed during training
+    pub forward_passes: AtomicU64,
+    /// number of those dot products outside [-MAX_EXP, MAX_EXP] or NaN, see
+    /// `saturation_rate`
+    pub saturated_passes: AtomicU64,
+    /// (word_count_actual, alpha) each time a training thread started or updated its
+    /// learning rate, about every 10000 words, in increasing word count order; only
+    /// filled if `TrainigParams::record_alpha` is set
+    pub alpha_trajectory: Mutex<Vec<(u64, f32)>>,
+    /// the position of each training thread in the current epoch, published about
+    /// every 10000 words, saved in checkpoints
+    pub thread_positions: Mutex<Vec<ThreadPosition>>,
+    /// the loss on `TrainigParams::validation_file` after each completed epoch, from
+    /// the epoch training started or resumed at
+    pub validation_losses: Mutex<Vec<f64>>,
+    /// the average loss of the training examples, as defined by
+    /// `NeuralNet::compute_loss`, smoothed over the batches of about 10000 words the
+    /// threads publish their progress after, see `LOSS_SMOOTHING`; None before the
+    /// first batch
+    pub smoothed_loss: Mutex<Option<f64>>,
+    /// (loss, epoch counting from 0) of the least validation loss, including the
+    /// epochs trained before resuming
+    pub best_validation: Mutex<Option<(f64, u64)>>,
+}
+
+impl TrainigProgress {
+    pub fn new() -> TrainigProgress {
+        TrainigProgress {
+            word_count_actual: AtomicU64::new(0),
+            empty_sentences: Mutex::new(None),
+            start: Instant::now(),
+            gradient_histogram: std::array::from_fn(|_| AtomicU64::new(0)),
+            epoch_durations: Mutex::new(Vec::new()),
+            forward_passes: AtomicU64::new(0),
+            saturated_passes: AtomicU64::new(0),
+            alpha_trajectory: Mutex::new(Vec::new()),
+            thread_positions: Mutex::new(Vec::new()),
+            validation_losses: Mutex::new(Vec::new()),
+            smoothed_loss: Mutex::new(None),
+            best_validation: Mutex::new(None),
+        }
+    }
+
+    /// @return the fraction of output layer dot products outside [-MAX_EXP, MAX_EXP],
+    /// the range of the sigmoid table of the original word2vec, which skips the
+    /// update of the positive sample and clamps the sigmoid beyond it. The sigmoid is
+    /// computed exactly here, but it saturates too: a high rate means the model is
+    /// over-confident and learns little. NaN dot products of a diverged model count as
+    /// saturated.
+    pub fn saturation_rate(&self) -> f64 {
+        let passes = self.forward_passes.load(Ordering::Relaxed);
+        if passes == 0 {
+            return 0.0;
+        }
+        self.saturated_passes.load(Ordering::Relaxed) as f64 / passes as f64
+    }
+
+    /// @return the learning rate the training threads currently use, as given by the
+    /// number of words processed so far
+    pub fn learning_rate(&self, params: &TrainigParams, vocab: &Vocabulary) -> f32 {
+        learning_rate(
+            params,
+            vocab,
+            self.word_count_actual.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// the bound of the sigmoid table of the original word2vec, see `saturation_rate`
+pub const MAX_EXP: f32 = 6.0;
+
+pub const GRADIENT_HISTOGRAM_BUCKETS: usize = 32;
+/// the base 2 logarithm of the lower bound of the second histogram bucket
+const GRADIENT_HISTOGRAM_MIN_EXP: i32 = -24;
+
+/// @return the `TrainigProgress::gradient_histogram` bucket counting `norm`. Bucket `i`
+/// counts norms in [2^(i-24), 2^(i-23)), the first bucket also counts smaller norms
+/// and the last one larger and non-finite norms.
+pub fn gradient_bucket(norm: f32) -> usize {
+    if !norm.is_finite() {
+        return GRADIENT_HISTOGRAM_BUCKETS - 1;
+    }
+    // log2(0) is -inf, which saturates to i32::MIN
+    let exp = norm.log2().floor() as i32;
+    exp.saturating_sub(GRADIENT_HISTOGRAM_MIN_EXP)
+        .clamp(0, GRADIENT_HISTOGRAM_BUCKETS as i32 - 1) as usize
+}
+
+impl Default for TrainigProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const MAX_SENTENCE_LENGTH: usize = 1024;
+
+/// number of neighbors reported for each of the `probe_words`
+const PROBE_NEIGHBORS: usize = 5;
+
+/// the weight of the latest batch in the exponential moving average
+/// `TrainigProgress::smoothed_loss`
+const LOSS_SMOOTHING: f64 = 0.1;
+
+/// number of sentences the loss written to `loss_log_path` is computed on
+pub const LOSS_LOG_SENTENCES: u64 = 10_000;
+
+/// Receives notifications about the training progress from `train_model`, all
+/// methods are invoked on the thread that called `train_model`
+pub trait TrainingObserver {
+    /// invoked after each completed epoch (pass over the training file) with the
+    /// number of the epoch, 1 for the first
+    fn epoch_end(&mut self, _epoch: u64, _progress: &TrainigProgress) {}
+
+    /// invoked every `probe_every` words for each of the `probe_words` found in the
+    /// vocabulary, with its current nearest neighbors, best first
+    fn probe_neighbors(&mut self, _word_count: u64, _word: &str, _neighbors: &[(String, f32)]) {}
+
+    /// invoked every `progress_every` words, e.g. to show a progress bar
+    fn progress(&mut self, _report: &ProgressReport) {}
+}
+
+/// The training progress passed to `TrainingObserver::progress`
+#[derive(Clone, Copy, Debug)]
+pub struct ProgressReport {
+    /// the number of words trained on so far, `TrainigProgress::word_count_actual`
+    pub word_count: u64,
+    /// the number of words of all epochs
+    pub total_words: u64,
+    /// the current learning rate
+    pub alpha: f32,
+    /// the words trained on per second since training started (or was resumed)
+    pub words_per_sec: f64,
+    /// the estimated time until training completes at that speed, None before the
+    /// speed is known
+    pub eta: Option<Duration>,
+    /// `TrainigProgress::smoothed_loss`
+    pub loss: Option<f64>,
+}
+
+/// a closure observes the end of each epoch
+impl<F: FnMut(u64, &TrainigProgress)> TrainingObserver for F {
+    fn epoch_end(&mut self, epoch: u64, progress: &TrainigProgress) {
+        self(epoch, progress)
+    }
+}
+
+/// Train the word2vec neural net `net` with training data found in `training_file`,
+/// using `params.num_threads` threads which share the net, `observer` is notified of
+/// the training progress.
+pub fn train_model<O: TrainingObserver>(
+    net: &Arc<NeuralNet>,
+    vocab: &Vocabulary,
+    params: &TrainigParams,
+    progress: &TrainigProgress,
+    observer: &mut O,
+) -> Result<(), std::io::Error> {
+    train_model_from(net, vocab, params, progress, observer, None)
+}
+
+/// Same as `train_model`, resuming the training at `start`, e.g. the position of a
+/// checkpoint loaded with `NeuralNet::load_checkpoint`, which must have been trained
+/// with the same parameters. The random numbers drawn after resuming differ from the
+/// ones of an uninterrupted run, so the results are not identical to it.
+pub fn train_model_from<O: TrainingObserver>(
+    net: &Arc<NeuralNet>,
+    vocab: &Vocabulary,
+    params: &TrainigParams,
+    progress: &TrainigProgress,
+    observer: &mut O,
+    start: Option<&TrainingPosition>,
+) -> Result<(), std::io::Error> {
+    if let Some(start) = start
+        && start.threads.len() != params.num_threads
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "The training was checkpointed with {} threads, {} were requested",
+                start.threads.len(),
+                params.num_threads
+            ),
+        ));
+    }
+
+    if params.num_threads == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Training requires at least 1 thread",
+        ));
+    }
+
+    // word hashing, the random streams (given the seed) and the tie-breaking of queries
+    // are always deterministic, concurrent updates of the net are not
+    if params.deterministic && params.num_threads != 1 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Deterministic training requires 1 thread, {} were requested",
+                params.num_threads
+            ),
+        ));
+    }
+
+    if params.deterministic && params.renorm_every.is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Deterministic training can't renormalize the vectors, they are rescaled while being trained",
+        ));
+    }
+
+    if let Some(keep) = &params.keep_probabilities
+        && keep.len() != vocab.len()
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{} keep probabilities were given for {} vocabulary words",
+                keep.len(),
+                vocab.len()
+            ),
+        ));
+    }
+
+    if params.negative_samples < 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "The number of negative samples must be >= 0, not {}",
+                params.negative_samples
+            ),
+        ));
+    }
+
+    // like the original, without negative samples only hierarchical softmax trains the
+    // output layer
+    if params.negative_samples == 0 && !params.hierarchical_softmax {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Training requires negative samples or hierarchical softmax",
+        ));
+    }
+
+    if params.sample.is_nan() || params.sample < 0.0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("The sample threshold must be >= 0, not {}", params.sample),
+        ));
+    }
+
+    if params.window == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The context window must span at least 1 word",
+        ));
+    }
+
+    if params.hierarchical_softmax && (params.adam || params.adagrad) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Hierarchical softmax can't be trained with Adam or AdaGrad",
+        ));
+    }
+
+    if let Some(max_grad) = params.max_grad
+        && (max_grad.is_nan() || max_grad <= 0.0)
+    {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("The gradient bound must be > 0, not {max_grad}"),
+        ));
+    }
+
+    if params.adam && params.adagrad {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Only one of Adam and AdaGrad can be used",
+        ));
+    }
 
-                    net.locker.lock(last_word);
-                    for (i, err) in neu1e.iter().enumerate() {
-                        (*mutable_unsafe_slice)[i] += err;
+    if params.validation_file.is_some() && params.patience == 0 {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Early stopping requires a patience of at least 1 epoch",
+        ));
+    }
+
+    // the skipped sentences are excluded from the chunks assigned to the threads
+    let start_offset = sentence_offset(&params.training_file, params.skip_sentences)?;
+    let chunk_size =
+        params.training_file_size.saturating_sub(start_offset) / params.num_threads as u64;
+    let first_epoch = start.map_or(0, |start| start.epoch);
+    // the positions the threads start an epoch at
+    let epoch_start_positions = || -> Vec<ThreadPosition> {
+        (0..params.num_threads as u64)
+            .map(|thread_id| ThreadPosition {
+                offset: start_offset + chunk_size * thread_id,
+                word_count: 0,
+            })
+            .collect()
+    };
+    if let Some(start) = start {
+        progress
+            .word_count_actual
+            .store(start.word_count_actual, Ordering::Relaxed);
+    }
+    let mut next_probe = params.probe_every;
+    let mut next_progress = params.progress_every;
+    // the words trained on before resuming, excluded from the training speed
+    let resumed_words = start.map_or(0, |start| start.word_count_actual);
+    let total_words = params.total_iter * vocab.train_words();
+    let snapshots_enabled = params.snapshot_prefix.is_some() && params.snapshot_every > 0;
+    // a resumed run continues the numbering of the snapshots
+    let mut snapshots: u64 = if snapshots_enabled {
+        resumed_words / params.snapshot_every
+    } else {
+        0
+    };
+    let mut next_snapshot = (snapshots + 1) * params.snapshot_every;
+    // save a snapshot for each multiple of snapshot_every up to `word_count`, so that
+    // none is missed when the word count is polled after several of them
+    let mut save_snapshots = |word_count: u64| -> std::io::Result<()> {
+        if let Some(prefix) = &params.snapshot_prefix
+            && snapshots_enabled
+        {
+            while word_count >= next_snapshot {
+                snapshots += 1;
+                let file_name = format!("{prefix}{snapshots:05}.txt");
+                net.save_pca_snapshot(vocab, params.snapshot_words, &file_name)?;
+                next_snapshot += params.snapshot_every;
+            }
+        }
+        Ok(())
+    };
+    // renormalizing deviates from the original word2vec, which lets the norms grow, and
+    // may hurt or help depending on the data. The vectors are rescaled from here while
+    // the threads keep training, so their norms are only close to 1 until the epoch ends
+    let renorm_every = params.renorm_every.map_or(0, NonZeroU64::get);
+    let mut next_renorm = renorm_every;
+    let mut next_checkpoint = match params.checkpoint_file {
+        Some(_) => params.checkpoint_every,
+        None => 0,
+    };
+    let mut loss_log = match &params.loss_log_path {
+        Some(path) => {
+            let mut file = File::create(path)?;
+            writeln!(file, "epoch,avg_loss,alpha,elapsed_secs")?;
+            Some(file)
+        }
+        None => None,
+    };
+    *progress.best_validation.lock().unwrap() = start.and_then(|start| start.best_validation);
+
+    for epoch in first_epoch..params.total_iter {
+        let epoch_start = Instant::now();
+        let resumed_epoch = start.is_some_and(|start| start.epoch == epoch);
+        let thread_positions: Vec<ThreadPosition> = match start {
+            Some(start) if resumed_epoch => start.threads.clone(),
+            _ => epoch_start_positions(),
+        };
+        *progress.thread_positions.lock().unwrap() = thread_positions.clone();
+        let empty_sentences = thread::scope(|scope| {
+            let handles: Vec<_> = thread_positions
+                .into_iter()
+                .enumerate()
+                .map(|(thread_id, thread_start)| {
+                    let net = Arc::clone(net);
+                    scope.spawn(move || {
+                        train_model_thread(
+                            net,
+                            vocab,
+                            thread_id,
+                            thread_start,
+                            epoch,
+                            params,
+                            progress,
+                        )
+                    })
+                })
+                .collect();
+
+            // watch the word count while the threads run, the net is read while being
+            // updated, like the training threads do
+            while (next_probe > 0
+                || next_progress > 0
+                || next_renorm > 0
+                || snapshots_enabled
+                || next_checkpoint > 0)
+                && !handles.iter().all(|h| h.is_finished())
+            {
+                let word_count = progress.word_count_actual.load(Ordering::Relaxed);
+                if next_progress > 0 && word_count >= next_progress {
+                    let words_per_sec = word_count.saturating_sub(resumed_words) as f64
+                        / progress.start.elapsed().as_secs_f64();
+                    let eta = Some(total_words.saturating_sub(word_count) as f64 / words_per_sec)
+                        .filter(|secs| secs.is_finite())
+                        .map(Duration::from_secs_f64);
+                    observer.progress(&ProgressReport {
+                        word_count,
+                        total_words,
+                        alpha: progress.learning_rate(params, vocab),
+                        words_per_sec,
+                        eta,
+                        loss: *progress.smoothed_loss.lock().unwrap(),
+                    });
+                    next_progress =
+                        (word_count / params.progress_every + 1) * params.progress_every;
+                }
+                // renormalized first, so the observer is notified with unit-length vectors
+                if next_renorm > 0 && word_count >= next_renorm {
+                    net.normalize_rows_concurrently(locks_rows(params));
+                    next_renorm = (word_count / renorm_every + 1) * renorm_every;
+                }
+                if next_probe > 0 && word_count >= next_probe {
+                    for word in &params.probe_words {
+                        let neighbors = net.most_similar(vocab, word, PROBE_NEIGHBORS);
+                        if !neighbors.is_empty() {
+                            observer.probe_neighbors(word_count, word, &neighbors);
+                        }
                     }
-                    net.locker.unlock(last_word);
+                    next_probe = (word_count / params.probe_every + 1) * params.probe_every;
+                }
+                save_snapshots(word_count)?;
+                if let Some(file_name) = &params.checkpoint_file
+                    && next_checkpoint > 0
+                    && word_count >= next_checkpoint
+                {
+                    // the threads publish their position and word count together
+                    let position = {
+                        let threads = progress.thread_positions.lock().unwrap();
+                        TrainingPosition {
+                            epoch,
+                            word_count_actual: progress.word_count_actual.load(Ordering::Relaxed),
+                            threads: threads.clone(),
+                            best_validation: *progress.best_validation.lock().unwrap(),
+                        }
+                    };
+                    net.save_checkpoint(vocab, &position, file_name)?;
+                    next_checkpoint =
+                        (word_count / params.checkpoint_every + 1) * params.checkpoint_every;
                 }
+                thread::sleep(Duration::from_millis(10));
             }
+
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("training thread panicked"))
+                .sum::<std::io::Result<u64>>()
+        })?;
+        // the threads of a resumed epoch only read the rest of their part of the file
+        if !resumed_epoch {
+            *progress.empty_sentences.lock().unwrap() = Some(empty_sentences);
+        }
+        // the epoch may end before the word count was polled, renormalize while no
+        // thread is training so the observer gets unit-length vectors at the epoch end
+        let word_count = progress.word_count_actual.load(Ordering::Relaxed);
+        if next_renorm > 0 && word_count >= next_renorm {
+            net.normalize_rows_concurrently(locks_rows(params));
+            next_renorm = (word_count / renorm_every + 1) * renorm_every;
         }
+        // the words trained on after the last poll
+        save_snapshots(word_count)?;
+        progress
+            .epoch_durations
+            .lock()
+            .unwrap()
+            .push(epoch_start.elapsed());
 
-        // Advance to the next word in the sentence.
-        sentence_position += 1;
+        if let Some(file) = &mut loss_log {
+            let loss =
+                net.compute_loss(vocab, &params.training_file, params, LOSS_LOG_SENTENCES)?;
+            writeln!(
+                file,
+                "{},{loss:.06},{:.06},{:.03}",
+                epoch + 1,
+                progress.learning_rate(params, vocab),
+                progress.start.elapsed().as_secs_f64()
+            )?;
+        }
 
-        // Check if we've reached the end of the sentence. If so, set sentence_length
-        // to 0 and we'll read a new sentence at the beginning of this loop.
-        if sentence_position >= sentence_length {
-            sentence_length = 0;
-            continue;
+        let mut stop = false;
+        if let Some(validation_file) = &params.validation_file {
+            let loss = net.compute_loss(vocab, validation_file, params, u64::MAX)?;
+            progress.validation_losses.lock().unwrap().push(loss);
+            let best_validation = *progress.best_validation.lock().unwrap();
+            match best_validation {
+                Some((best_loss, best_epoch)) if loss >= best_loss => {
+                    stop = epoch - best_epoch >= params.patience;
+                }
+                _ => {
+                    *progress.best_validation.lock().unwrap() = Some((loss, epoch));
+                    if let Some(file_name) = &params.best_checkpoint_file {
+                        let position = TrainingPosition {
+                            epoch: epoch + 1,
+                            word_count_actual: progress.word_count_actual.load(Ordering::Relaxed),
+                            threads: epoch_start_positions(),
+                            best_validation: Some((loss, epoch)),
+                        };
+                        net.save_checkpoint(vocab, &position, file_name)?;
+                    }
+                }
+            }
         }
-    }
 
+        observer.epoch_end(epoch + 1, progress);
+        if stop {
+            break;
+        }
+    }
     Ok(())
 }
+
+/// @return true if the training threads lock the rows of the net they update. Like
+/// the original, plain SGD updates race without locks (Hogwild) if `lock_free` is set,
+/// an update racing with another one on the same row may be partly lost; the Adam
+/// and AdaGrad states must stay consistent with their rows.
+fn locks_rows(params: &TrainigParams) -> bool {
+    !params.lock_free || params.adam || params.adagrad
+}
+
+/// @return the probability of keeping an occurrence of word `idx` when the frequent
+/// words are subsampled with `TrainigParams::sample`: like the original word2vec,
+/// sqrt(sample / f) + sample / f for a word of frequency f, words with a frequency
+/// below about 2.6 * sample being always kept
+fn subsample_keep_probability(vocab: &Vocabulary, sample: f32, idx: usize) -> f64 {
+    let threshold = sample as f64 * vocab.train_words() as f64;
+    let count = vocab.word_count(idx) as f64;
+    ((count / threshold).sqrt() + 1.0) * threshold / count
+}
+
+/// @return the learning rate after `word_count_actual` words were processed: it
+/// ramps up linearly from the minimum learning rate to starting_alpha over the first
+/// `warmup_words` words, then decays linearly (if `decay` is set) to the end of
+/// training, never dropping below `min_alpha`
+fn learning_rate(params: &TrainigParams, vocab: &Vocabulary, word_count_actual: u64) -> f32 {
+    let min_alpha = params.min_alpha.unwrap_or(params.starting_alpha * 0.0001);
+    if word_count_actual < params.warmup_words {
+        let warmup_done = word_count_actual as f32 / params.warmup_words as f32;
+        return (params.starting_alpha * warmup_done).max(min_alpha);
+    }
+    if !params.decay {
+        return params.starting_alpha;
+    }
+
+    // Update alpha to: [initial alpha] * [percent of training remaining]
+    // This means that alpha will gradually decrease as we progress through
+    // the training text (the part following the warm-up).
+    let decay_words =
+        (params.total_iter * vocab.train_words() + 1).saturating_sub(params.warmup_words);
+    let alpha = params.starting_alpha
+        * (1_f32 - (word_count_actual - params.warmup_words) as f32 / decay_words.max(1) as f32);
+
+    // Don't let alpha go below the minimum, by default [initial alpha] * 0.0001.
+    alpha.max(min_alpha)
+}
+
+/// run one training epoch of thread `thread_id` over its part of the training file,
+/// starting at `start`
+/// @return the number of sentences without any token read by the thread
+fn train_model_thread(
+    net: Arc<NeuralNet>,
+    vocab: &Vocabulary,
+    thread_id: usize,
+    start: ThreadPosition,
+    epoch: u64,
+    params: &TrainigParams,
+    progress: &TrainigProgress,
+) -> Result<u64, std::io::Error> {
+    assert!(net.vocab_size == vocab.len());
+    assert!(net.vocab_size * net.layer1_size == net.syn0.len());
+    assert!(net.syn0.len() == net.syn1neg.len());
+    assert!(
+        !params.output_bias || net.bias.len() == net.vocab_size,
+        "output_bias requires NeuralNet::enable_output_bias"
+    );
+    assert!(
+        !params.adam || net.adam.is_some(),
+        "adam requires NeuralNet::enable_adam"
+    );
+    assert!(
+        !params.adagrad || net.adagrad.is_some(),
+        "adagrad requires NeuralNet::enable_adagrad"
+    );
+    assert!(
+        !params.hierarchical_softmax || net.huffman_tree.is_some(),
+        "hierarchical_softmax requires NeuralNet::enable_hierarchical_softmax"
+    );
+    let huffman_tree = net
+        .huffman_tree
+        .as_ref()
+        .filter(|_| params.hierarchical_softmax);
+    if start.offset == u64::MAX {
+        return Ok(0);
+    }
+
+    let mut fi =
+        FileTokenIterator::with_params(&params.training_file, start.offset, &params.tokenizer)?;
+    let max_words = vocab.train_words() / params.num_threads as u64;
+    // reversing keeps the word indices of the part of the thread in memory, 4 bytes
+    // per word (8 with token weights), to even out which sentences are trained on last
+    let mut words = if params.reverse_odd_epochs && epoch % 2 == 1 {
+        WordSource::Reversed(read_reversed_sentences(
+            &mut fi,
+            vocab,
+            max_words,
+            params.tokenizer.token_weights,
+        ))
+    } else {
+        WordSource::File(fi)
+    };
+    let mut eof_reached: bool = false;
+    let layer1_size = net.layer1_size;
+    // deviates from the original word2vec: the error is still propagated back to the
+    // context vectors unchanged, as in standard CBOW
+    let normalize_context = params.normalize_context && params.architecture == Architecture::Cbow;
+    let use_locks = locks_rows(params);
+    let lock_row = |idx: usize| {
+        if use_locks {
+            net.locker.lock(idx);
+        }
+    };
+    let unlock_row = |idx: usize| {
+        if use_locks {
+            net.locker.unlock(idx);
+        }
+    };
+    // the positive and negative samples trained on for each example, none if only
+    // hierarchical softmax is used
+    let negative_samples = if huffman_tree.is_some() && params.negative_samples <= 0 {
+        0
+    } else {
+        params.negative_samples + 1
+    };
+
+    let mut neu1: Vec<f32> = Vec::with_capacity(layer1_size);
+    neu1.resize(layer1_size, 0.0);
+    let mut neu1e: Vec<f32> = Vec::with_capacity(layer1_size);
+    neu1e.resize(layer1_size, 0.0);
+    // accumulates `neu1e` if `params.f64_gradients` is set
+    let mut neu1e_f64: Vec<f64> = vec![0.0; if params.f64_gradients { layer1_size } else { 0 }];
+    // the gradient of the weights updated with Adam or AdaGrad if `params.adam` or
+    // `params.adagrad` is set
+    let optimizer = params.adam || params.adagrad;
+    let mut optimizer_grad: Vec<f32> = vec![0.0; if optimizer { layer1_size } else { 0 }];
+    // clips an output error to `params.max_grad`, with adam or adagrad the error is not
+    // scaled by alpha; the original only skips the hierarchical softmax outputs beyond
+    // MAX_EXP
+    let clip = |err: f64| match params.max_grad {
+        Some(max_grad) => err.clamp(-max_grad as f64, max_grad as f64),
+        None => err,
+    };
+
+    // each thread and epoch gets its own random number streams
+    let mut rand_gen = RandomStreams::for_thread(params.seed, epoch, thread_id);
+    // progress tracking
+    let mut word_count: u64 = start.word_count;
+    let mut last_word_count: u64 = start.word_count;
+    // the position of the sentence being trained on, where a resumed run restarts
+    let mut position: ThreadPosition = start;
+    let mut gradient_histogram = [0u64; GRADIENT_HISTOGRAM_BUCKETS];
+    let mut forward_passes: u64 = 0;
+    let mut saturated_passes: u64 = 0;
+    // the loss of the examples trained on since the last progress update
+    let mut loss_sum: f64 = 0.0;
+    let mut loss_examples: u64 = 0;
+
+    let mut sentence = [-1; MAX_SENTENCE_LENGTH + 1];
+    // the weight of each word of `sentence`
+    let mut sentence_weights = [1.0f32; MAX_SENTENCE_LENGTH + 1];
+    let mut sentence_length: usize = 0;
+    let mut sentence_position: usize = 0;
+    // true while no token was read since the last "</s>"
+    let mut line_is_empty: bool = true;
+    let mut empty_sentences: u64 = 0;
+    // computes the current learning rate, recording it if `params.record_alpha` is set
+    let update_alpha = || {
+        if !params.record_alpha {
+            return progress.learning_rate(params, vocab);
+        }
+        // the word count is read under the lock to keep the trajectory sorted
+        let mut trajectory = progress.alpha_trajectory.lock().unwrap();
+        let word_count = progress.word_count_actual.load(Ordering::Relaxed);
+        let alpha = learning_rate(params, vocab, word_count);
+        trajectory.push((word_count, alpha));
+        alpha
+    };
+    let mut alpha: f32 = update_alpha();
+
+    'thread_loop: loop {
+        // This block prints a progress update, and also adjusts the training
+        // 'alpha' parameter.
+        if word_count - last_word_count > 10000 {
+            let mut positions = progress.thread_positions.lock().unwrap();
+            progress
+                .word_count_actual
+                .fetch_add(word_count - last_word_count, Ordering::Relaxed);
+            positions[thread_id] = position;
+            drop(positions);
+            last_word_count = word_count;
+
+            let loss = {
+                let mut smoothed_loss = progress.smoothed_loss.lock().unwrap();
+                if loss_examples > 0 {
+                    let batch_loss = loss_sum / loss_examples as f64;
+                    *smoothed_loss = Some(match *smoothed_loss {
+                        Some(loss) => loss + (batch_loss - loss) * LOSS_SMOOTHING,
+                        None => batch_loss,
+                    });
+                }
+                loss_sum = 0.0;
+                loss_examples = 0;
+                smoothed_loss.unwrap_or(f64::NAN)
+            };
+            let wc = progress.word_count_actual.load(Ordering::Relaxed) as f64;
+
+            // The percentage complete is based on the total number of passes we are
+            // doing and not just the current pass.
+            if params.debug_mode > 1 {
+                print!(
+                    "\rAlpha: {alpha:.06} Loss: {loss:.04} Progress: {:.02}%  Words/sec: {:.02}k ",
+                    wc / (params.total_iter * vocab.train_words() + 1) as f64 * 100_f64,
+                    (wc / 1000_f64) / progress.start.elapsed().as_secs_f64()
+                );
+            }
+
+            std::io::stdout().flush().unwrap_or_default();
+
+            alpha = update_alpha();
+        }
+
+        // Retrieve the next sentence from the training set and store it in `sentence`
+        if sentence_length == 0 {
+            if let Some(offset) = words.position() {
+                position = ThreadPosition { offset, word_count };
+            }
+            loop {
+                let (idx, weight) = match words.next_word_index(vocab) {
+                    // an out-of-vocabulary word is skipped, but its line isn't empty
+                    Some((x, _)) if x < 0 || x as usize >= net.vocab_size => {
+                        line_is_empty = false;
+                        continue;
+                    }
+                    Some(x) => x,
+                    None => {
+                        eof_reached = true;
+                        break;
+                    }
+                };
+
+                word_count += 1;
+
+                // word 0 is the special token "</s>" which indicates the end of a sentence,
+                // it is never stored in `sentence` so it can't become a context word
+                if idx == 0 {
+                    if line_is_empty {
+                        empty_sentences += 1;
+                    }
+                    line_is_empty = true;
+                    // an empty sentence, or one consisting only of out-of-vocabulary words
+                    if sentence_length == 0 || params.span_sentences {
+                        continue;
+                    }
+                    break;
+                }
+
+                line_is_empty = false;
+                // a dropped occurrence is removed from its sentence before the context
+                // windows are formed, so it is neither a center nor a context word
+                if let Some(keep) = &params.keep_probabilities {
+                    let ran = (rand_gen.subsample.next_rand() & 0xffff) as f32 / 65536.0;
+                    if ran >= keep[idx as usize] {
+                        continue;
+                    }
+                }
+                if params.sample > 0.0 {
+                    let keep = subsample_keep_probability(vocab, params.sample, idx as usize);
+                    let ran = (rand_gen.subsample.next_rand() & 0xffff) as f64 / 65536.0;
+                    if keep < ran {
+                        continue;
+                    }
+                }
+                sentence[sentence_length] = idx;
+                sentence_weights[sentence_length] = weight;
+                sentence_length += 1;
+                if sentence_length > MAX_SENTENCE_LENGTH {
+                    break;
+                }
+                sentence_position = 0;
+            }
+        }
+
+        if (sentence_length == 0 && eof_reached) || (word_count > max_words) {
+            let mut positions = progress.thread_positions.lock().unwrap();
+            progress
+                .word_count_actual
+                .fetch_add(word_count - last_word_count, Ordering::Relaxed);
+            positions[thread_id] = ThreadPosition {
+                offset: u64::MAX,
+                word_count,
+            };
+            break 'thread_loop;
+        }
+
+        let word = sentence[sentence_position];
+        let center_weight = sentence_weights[sentence_position];
+        // assertion taken care of when filling sentence
+        debug_assert!(word >= 0 && (word as usize) < net.vocab_size);
+
+        let b = params.window - rand_gen.window.shrunk_window(params.window);
+        let window_offsets = b..params.window * 2 + 1 - b;
+        // CBOW trains one example per center word, its hidden layer being the average of
+        // the context vectors; skip-gram trains one example per context word, like the
+        // original, its hidden layer being the vector of that word
+        let examples = match params.architecture {
+            Architecture::Cbow => 0..1,
+            Architecture::SkipGram => window_offsets.clone(),
+        };
+        for example in examples {
+            // the window offsets of the context words of this example
+            let offsets = match params.architecture {
+                Architecture::Cbow => window_offsets.clone(),
+                Architecture::SkipGram => example..example + 1,
+            };
+
+            neu1.fill(0.0);
+            // `cw` stores the context word count
+            let mut cw = 0;
+
+            for a in offsets.clone() {
+                if a == params.window {
+                    continue;
+                }
+                let c: isize = sentence_position as isize - params.window as isize + a as isize;
+                if c < 0 || c >= sentence_length as isize {
+                    continue;
+                }
+
+                let last_word = sentence[c as usize] as usize;
+
+                // sum all the context word vectors and store the result in neu1
+                let net_word_index = last_word * layer1_size;
+                let word_vec = unsafe {
+                    net.syn0
+                        .get_unchecked(net_word_index..net_word_index + layer1_size)
+                };
+                add_context_vector(&mut neu1, word_vec, normalize_context);
+                cw += 1;
+            }
+
+            // if there were any context words
+            if cw > 0 {
+                // neu1e is used in this block only
+                neu1e.fill(0.0);
+                neu1e_f64.fill(0.0);
+                // `neu1` is the sum of the context word vectors, and now
+                // becomes their average.
+                for n in &mut neu1 {
+                    *n /= cw as f32;
+                }
+                loss_examples += 1;
+
+                // HIERARCHICAL SOFTMAX
+                // The output is the probability of each branch on the path from the
+                // root of the Huffman tree to the word, the label of a branch being
+                // 1 - its code.
+                if let Some(tree) = huffman_tree {
+                    for (&node, &code) in tree
+                        .points(word as usize)
+                        .iter()
+                        .zip(tree.code(word as usize))
+                    {
+                        let l2 = node as usize * layer1_size;
+                        // inner nodes are in 0..vocab_size - 1
+                        let node_output_weights =
+                            unsafe { net.syn1.get_unchecked(l2..l2 + layer1_size) };
+
+                        let f: f32 = dot_product(&neu1, node_output_weights);
+                        forward_passes += 1;
+                        // like the original, skip the branches the output saturated for
+                        if f.is_nan() || f.abs() > MAX_EXP {
+                            saturated_passes += 1;
+                            continue;
+                        }
+                        let expx = f64::exp(f as f64);
+                        let output = expx / (expx + 1.0);
+                        let label = 1.0 - code as f32;
+                        loss_sum -= log_sigmoid(f64::from(if code == 0 { f } else { -f }));
+                        let step = alpha * center_weight;
+                        let err = clip(((label - output as f32) * step) as f64) as f32;
+
+                        if params.f64_gradients {
+                            let err_f64 = clip((label as f64 - output) * step as f64);
+                            for (e, &w) in neu1e_f64.iter_mut().zip(node_output_weights) {
+                                *e += err_f64 * w as f64;
+                            }
+                        } else {
+                            for i in 0..layer1_size {
+                                neu1e[i] += err * node_output_weights[i];
+                            }
+                        }
+
+                        unsafe {
+                            let node_output_weights_mut = slice_from_raw_parts_mut(
+                                node_output_weights.as_ptr().cast_mut(),
+                                node_output_weights.len(),
+                            );
+                            lock_row(node as usize);
+                            for (i, n) in neu1.iter().enumerate() {
+                                (*node_output_weights_mut)[i] += err * n;
+                            }
+                            unlock_row(node as usize);
+                        }
+                    }
+                }
+
+                // NEGATIVE SAMPLING
+                // Rather than performing backpropagation for every word in our
+                // vocabulary, we only perform it for the positive sample and a few
+                // negative samples (the number of words is given by 'negative').
+                // These negative words are selected using a "unigram" distribution,
+                // which is generated in the function InitUnigramTable.
+                for d in 0..negative_samples {
+                    let target: i32;
+                    let label: f32;
+
+                    if d == 0 {
+                        // On the first iteration, we're going to train the positive sample.
+                        target = word;
+                        label = 1.0;
+                    } else {
+                        // On the other iterations, we'll train the negative samples.
+                        // Pick a random word to use as a 'negative sample'; do this using
+                        // the unigram table.
+                        target = if params.banded_negatives {
+                            vocab.sample_word_in_band(word as usize, rand_gen.negative.next_rand())
+                        } else {
+                            vocab.sample_random_word(rand_gen.negative.next_rand())
+                        };
+                        // Don't use the positive sample as a negative sample!
+                        if target == word {
+                            continue;
+                        }
+                        // this condition allows us to use unsafe code to index the nets
+                        if target < 0 || target as usize >= net.vocab_size {
+                            continue;
+                        }
+                        // Mark this as a negative example.
+                        label = 0.0;
+                    }
+
+                    // At this point, target might either be the positive sample or a
+                    // negative sample, depending on the value of `label`.
+
+                    // Get the index of the target word in the output layer.
+                    let l2 = target as usize * layer1_size;
+                    let target_output_weights =
+                        unsafe { net.syn1neg.get_unchecked(l2..l2 + layer1_size) };
+
+                    // Calculate the dot product between:
+                    //   neu1 - The average of the context word vectors.
+                    //   syn1neg[l2] - The output weights for the target word.
+                    let mut f: f32 = dot_product(&neu1, target_output_weights);
+                    if params.output_bias {
+                        f += unsafe { *net.bias.get_unchecked(target as usize) };
+                    }
+                    forward_passes += 1;
+                    if f.is_nan() || f.abs() > MAX_EXP {
+                        saturated_passes += 1;
+                    }
+
+                    // This block does two things:
+                    //   1. Calculates the output of the network for this training
+                    //      pair, using the expTable to evaluate the output layer
+                    //      activation function.
+                    //   2. Calculate the error at the output, stored in 'g', by
+                    //      subtracting the network output from the desired output,
+                    //      and finally multiply this by the learning rate.
+
+                    // activation function: 1 / (1 + e^(-x)) = e^x / (e^x + 1)
+                    let expx = f64::exp(f as f64);
+                    let output = expx / (expx + 1.0);
+                    loss_sum -= log_sigmoid(f64::from(if d == 0 { f } else { -f }));
+                    // with Adam or AdaGrad the gradients are not scaled by alpha, the step
+                    // size is applied by the optimizer update
+                    // the updates are scaled by the weight of the center word
+                    let step: f32 = if optimizer { 1.0 } else { alpha } * center_weight;
+                    let err = clip(((label - output as f32) * step) as f64) as f32;
+
+                    // Multiply the error by the output layer weights.
+                    // (I think this is the gradient calculation?)
+                    // Accumulate these gradients over all of the negative samples.
+                    if params.f64_gradients {
+                        let err_f64 = clip((label as f64 - output) * step as f64);
+                        for (e, &w) in neu1e_f64.iter_mut().zip(target_output_weights) {
+                            *e += err_f64 * w as f64;
+                        }
+                    } else {
+                        for i in 0..layer1_size {
+                            neu1e[i] += err * target_output_weights[i];
+                        }
+                    }
+
+                    // Update the output layer weights by multiplying the output error
+                    // by the average of the context word vectors.
+                    unsafe {
+                        let target_output_weights_mut = slice_from_raw_parts_mut(
+                            target_output_weights.as_ptr().cast_mut(),
+                            target_output_weights.len(),
+                        );
+
+                        lock_row(target as usize);
+                        if optimizer {
+                            for (g, n) in optimizer_grad.iter_mut().zip(&neu1) {
+                                *g = err * n;
+                            }
+                            if params.adam {
+                                net.adam_update(true, target as usize, &optimizer_grad, alpha);
+                            } else {
+                                net.adagrad_update(true, target as usize, &optimizer_grad, alpha);
+                            }
+                        } else {
+                            for (i, n) in neu1.iter().enumerate() {
+                                (*target_output_weights_mut)[i] += err * n;
+                            }
+                        }
+                        // the bias has no optimizer state, with adam or adagrad it takes
+                        // plain SGD steps of the clipped error
+                        if params.output_bias {
+                            *net.bias.as_ptr().cast_mut().add(target as usize) +=
+                                if optimizer { err * alpha } else { err };
+                        }
+                        unlock_row(target as usize);
+                    }
+                }
+
+                if params.f64_gradients {
+                    for (e, &e_f64) in neu1e.iter_mut().zip(&neu1e_f64) {
+                        *e = e_f64 as f32;
+                    }
+                }
+
+                if params.gradient_histogram {
+                    gradient_histogram[gradient_bucket(dot_product(&neu1e, &neu1e).sqrt())] += 1;
+                }
+
+                // hidden -> in
+                // Backpropagate the error to the hidden layer (the word vectors).
+                // This code is used both for heirarchical softmax and for negative
+                // sampling.
+                //
+                // Loop over the positions in the context window (skipping the word at
+                // the center). 'a' is just the offset within the window, it's not
+                // the index relative to the beginning of the sentence.
+                for a in offsets {
+                    if a == params.window {
+                        continue;
+                    }
+                    // Convert the window offset 'a' into an index 'c' into the sentence
+                    // array.
+                    let c: isize = sentence_position as isize - params.window as isize + a as isize;
+
+                    // Verify c isn't outisde the bounds of the sentence.
+                    if c < 0 || c >= sentence_length as isize {
+                        continue;
+                    }
+
+                    // Get the context word. That is, get the id of the word (its index in
+                    // the vocab table).
+                    let last_word = sentence[c as usize] as usize;
+
+                    // Add the gradient in the vector `neu1e` to the word vector for
+                    // the current context word.
+                    // syn0[last_word * layer1_size] <-- Accesses the word vector.
+                    let word_vector = unsafe {
+                        net.syn0
+                            .get_unchecked(last_word * layer1_size..(last_word + 1) * layer1_size)
+                    };
+                    unsafe {
+                        let mutable_unsafe_slice = slice_from_raw_parts_mut(
+                            word_vector.as_ptr().cast_mut(),
+                            word_vector.len(),
+                        );
+
+                        // scaled by the weight of the context word too
+                        let context_weight = sentence_weights[c as usize];
+                        lock_row(last_word);
+                        if optimizer {
+                            for (g, e) in optimizer_grad.iter_mut().zip(&neu1e) {
+                                *g = e * context_weight;
+                            }
+                            if params.adam {
+                                net.adam_update(false, last_word, &optimizer_grad, alpha);
+                            } else {
+                                net.adagrad_update(false, last_word, &optimizer_grad, alpha);
+                            }
+                        } else {
+                            for (i, err) in neu1e.iter().enumerate() {
+                                (*mutable_unsafe_slice)[i] += err * context_weight;
+                            }
+                        }
+                        unlock_row(last_word);
+                    }
+                }
+            }
+        }
+
+        // Advance to the next word in the sentence.
+        sentence_position += 1;
+
+        // Check if we've reached the end of the sentence. If so, set sentence_length
+        // to 0 and we'll read a new sentence at the beginning of this loop.
+        if sentence_position >= sentence_length {
+            sentence_length = 0;
+            continue;
+        }
+    }
+
+    for (total, count) in progress.gradient_histogram.iter().zip(gradient_histogram) {
+        total.fetch_add(count, Ordering::Relaxed);
+    }
+    progress
+        .forward_passes
+        .fetch_add(forward_passes, Ordering::Relaxed);
+    progress
+        .saturated_passes
+        .fetch_add(saturated_passes, Ordering::Relaxed);
+    Ok(empty_sentences)
+}
+
+/// @return ln(sigmoid(x)), computed without overflow for large |x|
+fn log_sigmoid(x: f64) -> f64 {
+    if x >= 0.0 {
+        -(-x).exp().ln_1p()
+    } else {
+        x - x.exp().ln_1p()
+    }
+}
+
+impl NeuralNet {
+    /// Compute the average negative sampling loss over the first
+    /// `sample_sentences` sentences of `file_name`, without updating the net. The loss
+    /// of a word w is -ln(sigmoid(h * v_w)) - sum(ln(sigmoid(-h * v_n))) over the
+    /// negative samples n, where h is the average of the context vectors of w and v
+    /// are output layer vectors. With the skip-gram architecture each context word of w
+    /// is an example on its own, h being its vector. With hierarchical softmax the loss
+    /// -ln(sigmoid(+-h * u_n)) of each inner node n on the path of w is added, u_n being
+    /// its vector and the sign + for code 0. The window, the number of negative samples
+    /// and the output layers are taken from `params`, the random sequence is fixed so
+    /// that repeated calls on the same data draw the same windows and negative samples.
+    /// An InvalidInput error is returned for a net without output layer, without the
+    /// Huffman tree required by `params.hierarchical_softmax`, or for a window of 0.
+    pub fn compute_loss(
+        &self,
+        vocab: &Vocabulary,
+        file_name: &str,
+        params: &TrainigParams,
+        sample_sentences: u64,
+    ) -> std::io::Result<f64> {
+        if !self.has_output_layer() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The net has no output layer, e.g. because it was loaded from a model file",
+            ));
+        }
+        if params.hierarchical_softmax && self.huffman_tree.is_none() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Hierarchical softmax requires NeuralNet::enable_hierarchical_softmax",
+            ));
+        }
+        if params.window == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The context window must span at least 1 word",
+            ));
+        }
+        if params.negative_samples <= 0 && !params.hierarchical_softmax {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "The loss requires negative samples or hierarchical softmax",
+            ));
+        }
+        let layer1_size = self.layer1_size;
+        let huffman_tree = self
+            .huffman_tree
+            .as_ref()
+            .filter(|_| params.hierarchical_softmax);
+        let negative_samples = if huffman_tree.is_some() && params.negative_samples <= 0 {
+            0
+        } else {
+            params.negative_samples + 1
+        };
+        let mut fi = FileTokenIterator::with_params(file_name, 0, &params.tokenizer)?;
+        let mut rand_gen = LcRandomGen::new(1);
+        let mut sentence: Vec<usize> = Vec::with_capacity(MAX_SENTENCE_LENGTH);
+        let mut neu1: Vec<f32> = vec![0.0; layer1_size];
+        let mut total_loss: f64 = 0.0;
+        let mut examples: u64 = 0;
+
+        let mut eof_reached = false;
+        for _ in 0..sample_sentences {
+            if eof_reached {
+                break;
+            }
+            sentence.clear();
+            while sentence.len() < MAX_SENTENCE_LENGTH {
+                match read_word_index(&mut fi, vocab) {
+                    None => {
+                        eof_reached = true;
+                        break;
+                    }
+                    // a "</s>" ends a non-empty sentence
+                    Some(0) if sentence.is_empty() => continue,
+                    Some(0) => break,
+                    Some(x) if x < 0 || x as usize >= self.vocab_size => continue,
+                    Some(x) => sentence.push(x as usize),
+                }
+            }
+
+            for (pos, &word) in sentence.iter().enumerate() {
+                // the same shrunk window as in training
+                let half_window = rand_gen.shrunk_window(params.window);
+                let first = pos.saturating_sub(half_window);
+                let last = (pos + half_window).min(sentence.len() - 1);
+
+                // the loss of predicting `word` from the hidden layer `hidden`
+                let mut example_loss = |hidden: &[f32]| -> f64 {
+                    let mut loss: f64 = 0.0;
+                    if let Some(tree) = huffman_tree {
+                        for (&node, &code) in tree.points(word).iter().zip(tree.code(word)) {
+                            let l2 = node as usize * layer1_size;
+                            let f = dot_product(hidden, &self.syn1[l2..l2 + layer1_size]) as f64;
+                            loss -= log_sigmoid(if code == 0 { f } else { -f });
+                        }
+                    }
+                    for d in 0..negative_samples {
+                        let (target, label) = if d == 0 {
+                            (word, true)
+                        } else {
+                            let target = vocab.sample_random_word(rand_gen.next_rand());
+                            if target < 0
+                                || target as usize == word
+                                || target as usize >= self.vocab_size
+                            {
+                                continue;
+                            }
+                            (target as usize, false)
+                        };
+                        let l2 = target * layer1_size;
+                        let mut f = dot_product(hidden, &self.syn1neg[l2..l2 + layer1_size]) as f64;
+                        if let Some(bias) = self.output_bias() {
+                            f += bias[target] as f64;
+                        }
+                        loss -= if label {
+                            log_sigmoid(f)
+                        } else {
+                            log_sigmoid(-f)
+                        };
+                    }
+                    loss
+                };
+                let contexts = (first..last + 1).filter(|&c| c != pos);
+
+                match params.architecture {
+                    Architecture::Cbow => {
+                        neu1.fill(0.0);
+                        let mut cw = 0;
+                        for c in contexts {
+                            add_context_vector(
+                                &mut neu1,
+                                self.word_vector(sentence[c]),
+                                params.normalize_context,
+                            );
+                            cw += 1;
+                        }
+                        if cw == 0 {
+                            continue;
+                        }
+                        neu1.iter_mut().for_each(|n| *n /= cw as f32);
+                        total_loss += example_loss(&neu1);
+                        examples += 1;
+                    }
+                    // one example per context word
+                    Architecture::SkipGram => {
+                        for c in contexts {
+                            total_loss += example_loss(self.word_vector(sentence[c]));
+                            examples += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(if examples == 0 {
+            0.0
+        } else {
+            total_loss / examples as f64
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::test_util::{
+        TOPIC_WORDS, fresh_net, learn_vocab, net_of, temp_path, train, training_params, vocab_of,
+        vocab_params, write_temp_file, write_two_topic_corpus,
+    };
+    use crate::vocab::VocabParams;
+
+    /// records the reports of `TrainingObserver::progress`
+    struct ReportRecorder(Vec<ProgressReport>);
+
+    impl TrainingObserver for ReportRecorder {
+        fn progress(&mut self, report: &ProgressReport) {
+            self.0.push(*report);
+        }
+    }
+
+    /// train a fresh net on `vocab` with `params`
+    /// @return the progress reports
+    fn progress_reports(vocab: &Vocabulary, params: &TrainigParams) -> Vec<ProgressReport> {
+        let net = Arc::new(fresh_net(vocab, params));
+        let mut recorder = ReportRecorder(Vec::new());
+        train_model(&net, vocab, params, &TrainigProgress::new(), &mut recorder).unwrap();
+        recorder.0
+    }
+
+    #[test]
+    fn context_windows_stop_at_sentence_ends() {
+        // "solo" is alone in its sentences, so it is never a context word unless the
+        // windows span sentences
+        let file = write_temp_file("sentence-ends.txt", &"solo\nthe quick fox\n".repeat(200));
+        let vocab = learn_vocab(&file);
+        let solo = vocab.search_word("solo") as usize;
+        let mut params = training_params(&file);
+        let initial = fresh_net(&vocab, &params).word_vector(solo).to_vec();
+
+        let net = Arc::new(fresh_net(&vocab, &params));
+        train(&net, &vocab, &params);
+        assert_eq!(net.word_vector(solo), &initial[..]);
+
+        params.span_sentences = true;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        train(&net, &vocab, &params);
+        assert_ne!(net.word_vector(solo), &initial[..]);
+    }
+
+    #[test]
+    fn blank_lines_are_counted_as_empty_sentences() {
+        // the line of words too rare to be in the vocabulary is not empty
+        let corpus = "the quick fox\n\njumps over\n\n\nthe dog\n".repeat(10) + "zebra yak\n";
+        let file = write_temp_file("empty-sentences.txt", &corpus);
+        let vocab_params = VocabParams {
+            min_count: 2,
+            ..vocab_params()
+        };
+        let vocab = Vocabulary::learn_vocabulary_with_params(&file, &vocab_params).unwrap();
+        assert_eq!(vocab.search_word("zebra"), -1);
+        let mut params = training_params(&file);
+        params.total_iter = 2;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = train(&net, &vocab, &params);
+        assert_eq!(*progress.empty_sentences.lock().unwrap(), Some(30));
+    }
+
+    #[test]
+    fn rescale_to_norm_sets_the_norm_of_nonzero_rows() {
+        let mut net = net_of(&[&[3.0, 4.0], &[0.0, 0.0], &[-1.0, 0.5]]);
+        net.rescale_to_norm(2.5);
+        let norm = |row: &[f32]| dot_product(row, row).sqrt();
+        assert!((norm(net.word_vector(0)) - 2.5).abs() < 1e-5);
+        assert_eq!(net.word_vector(1), &[0.0, 0.0]);
+        assert!((norm(net.word_vector(2)) - 2.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn load_auto_detects_every_model_format() {
+        let vocab = vocab_of(&[("</s>", 0), ("alpha", 2), ("beta", 1)]);
+        let mut net = net_of(&[&[0.5, -0.25], &[1.0, 2.0], &[-3.5, 0.125]]);
+        net.syn1neg = vec![0.0; 6];
+        let position = TrainingPosition {
+            epoch: 0,
+            word_count_actual: 0,
+            threads: vec![],
+            best_validation: None,
+        };
+        // the values are exact in half precision
+        for format in ["binary", "text", "f16", "checkpoint"] {
+            let file = temp_path(&format!("load-auto-{format}.bin"));
+            match format {
+                "f16" => net.save_binary_f16(&vocab, &file).unwrap(),
+                "checkpoint" => net.save_checkpoint(&vocab, &position, &file).unwrap(),
+                _ => net.save(&vocab, &file, format == "binary").unwrap(),
+            }
+            let (loaded_vocab, loaded) = NeuralNet::load_auto(&file).unwrap();
+            assert_eq!(
+                loaded_vocab.into_iter().collect::<Vec<_>>(),
+                vec!["</s>", "alpha", "beta"],
+                "{format}"
+            );
+            for idx in 0..3 {
+                assert_eq!(loaded.word_vector(idx), net.word_vector(idx), "{format}");
+            }
+        }
+
+        let file = write_temp_file(
+            "load-auto-unknown.txt",
+            "neither a model nor a checkpoint\n",
+        );
+        assert!(matches!(
+            NeuralNet::load_auto(&file),
+            Err(W2vError::UnknownFormat)
+        ));
+        assert!(matches!(
+            NeuralNet::load_auto(&temp_path("load-auto-missing.bin")),
+            Err(W2vError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn save_to_a_small_buffer_writes_the_same_model() {
+        let vocab = vocab_of(&[("</s>", 0), ("alpha", 2), ("beta", 1)]);
+        let net = net_of(&[&[0.5, -0.25], &[1.0, 2.0], &[-3.5, 0.125]]);
+        for binary in [true, false] {
+            let file = temp_path(&format!("save-to-{binary}.bin"));
+            net.save(&vocab, &file, binary).unwrap();
+            let mut writer = BufWriter::with_capacity(3, Vec::new());
+            net.save_to(&vocab, &mut writer, binary).unwrap();
+            assert_eq!(writer.into_inner().unwrap(), std::fs::read(&file).unwrap());
+        }
+    }
+
+    #[test]
+    fn epoch_end_is_invoked_after_each_epoch() {
+        let file = write_temp_file("epoch-end.txt", &"the quick fox jumps\n".repeat(50));
+        let vocab = learn_vocab(&file);
+        let params = training_params(&file);
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let mut epochs: Vec<u64> = Vec::new();
+        let mut observer = |epoch: u64, _: &TrainigProgress| epochs.push(epoch);
+        train_model(
+            &net,
+            &vocab,
+            &params,
+            &TrainigProgress::new(),
+            &mut observer,
+        )
+        .unwrap();
+        assert_eq!(epochs, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn skipped_sentences_are_not_trained_on() {
+        let file = write_temp_file(
+            "skip-sentences.txt",
+            &format!(
+                "alpha beta gamma\ngamma beta\n{}",
+                "the quick fox\n".repeat(100)
+            ),
+        );
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.skip_sentences = 2;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        train(&net, &vocab, &params);
+
+        let initial = fresh_net(&vocab, &params);
+        for word in ["alpha", "beta", "gamma"] {
+            let idx = vocab.search_word(word) as usize;
+            assert_eq!(net.word_vector(idx), initial.word_vector(idx));
+        }
+        let idx = vocab.search_word("quick") as usize;
+        assert_ne!(net.word_vector(idx), initial.word_vector(idx));
+    }
+
+    #[test]
+    fn merge_models_averages_shared_words_and_copies_the_others() {
+        let vocab_a = vocab_of(&[("</s>", 0), ("shared", 3), ("only_a", 2)]);
+        let a = net_of(&[&[0.0, 0.0], &[1.0, 2.0], &[5.0, 5.0]]);
+        let vocab_b = vocab_of(&[("</s>", 0), ("only_b", 4), ("shared", 1)]);
+        let b = net_of(&[&[0.0, 0.0], &[-1.0, 7.0], &[3.0, 0.0]]);
+
+        let (vocab, net) = NeuralNet::merge_models(&a, &vocab_a, &b, &vocab_b);
+        // sorted by the summed counts
+        assert_eq!(
+            vocab.into_iter().collect::<Vec<_>>(),
+            vec!["</s>", "shared", "only_b", "only_a"]
+        );
+        assert_eq!(vocab.word_count(1), 4);
+        assert_eq!(net.word_vector(1), &[2.0, 1.0]);
+        assert_eq!(net.word_vector(2), &[-1.0, 7.0]);
+        assert_eq!(net.word_vector(3), &[5.0, 5.0]);
+    }
+
+    #[test]
+    fn probe_words_get_their_neighbors_reported() {
+        struct Probes(Vec<(u64, String, usize)>);
+        impl TrainingObserver for Probes {
+            fn probe_neighbors(
+                &mut self,
+                word_count: u64,
+                word: &str,
+                neighbors: &[(String, f32)],
+            ) {
+                self.0.push((word_count, word.to_string(), neighbors.len()));
+            }
+        }
+
+        // long enough for the word count to be polled a few times
+        let file = write_two_topic_corpus("probe-words.txt", 5000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.probe_words = vec![String::from("cat"), String::from("missing")];
+        params.probe_every = 20_000;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let mut probes = Probes(Vec::new());
+        train_model(&net, &vocab, &params, &TrainigProgress::new(), &mut probes).unwrap();
+
+        assert!(!probes.0.is_empty());
+        for (word_count, word, neighbors) in &probes.0 {
+            assert!(*word_count >= 20_000);
+            assert_eq!(word, "cat");
+            assert_eq!(*neighbors, PROBE_NEIGHBORS);
+        }
+    }
+
+    #[test]
+    fn training_lowers_the_loss() {
+        let file = write_two_topic_corpus("compute-loss.txt", 500);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        for architecture in [Architecture::Cbow, Architecture::SkipGram] {
+            params.architecture = architecture;
+            let net = Arc::new(fresh_net(&vocab, &params));
+            let fresh_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+            train(&net, &vocab, &params);
+            let trained_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+            assert!(
+                trained_loss < fresh_loss,
+                "{architecture:?}: {trained_loss} >= {fresh_loss}"
+            );
+        }
+    }
+
+    #[test]
+    fn compute_loss_requires_the_output_layers() {
+        let file = write_temp_file("compute-loss-errors.txt", "the quick fox\n");
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let loaded = net_of(&vec![&[0.0f32, 0.0][..]; vocab.len()]);
+        let err = loaded.compute_loss(&vocab, &file, &params, 10).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        params.hierarchical_softmax = true;
+        let err = fresh_net(&vocab, &params)
+            .compute_loss(&vocab, &file, &params, 10)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn random_streams_are_independently_reproducible() {
+        let draw = |rand_gen: &mut LcRandomGen| -> Vec<i64> {
+            (0..5).map(|_| rand_gen.next_rand()).collect()
+        };
+        let mut first = RandomStreams::new(42);
+        let mut second = RandomStreams::new(42);
+        // drawing from one stream leaves the others unchanged
+        draw(&mut second.window);
+        draw(&mut second.window);
+        let negative = draw(&mut first.negative);
+        assert_eq!(draw(&mut second.negative), negative);
+        let subsample = draw(&mut first.subsample);
+        assert_eq!(draw(&mut second.subsample), subsample);
+        assert_ne!(negative, subsample);
+        assert_ne!(draw(&mut RandomStreams::new(43).negative), negative);
+    }
+
+    #[test]
+    fn thread_streams_differ_from_those_of_nearby_seeds() {
+        let draw = |(seed, epoch, thread_id): (u64, u64, usize)| -> Vec<i64> {
+            let mut rand_gen = RandomStreams::for_thread(seed, epoch, thread_id).negative;
+            (0..5).map(|_| rand_gen.next_rand()).collect()
+        };
+        assert_eq!(draw((42, 1, 0)), draw((42, 1, 0)));
+        // the second epoch and the second thread of seed 42 against seed 43
+        assert_ne!(draw((42, 1, 0)), draw((43, 0, 0)));
+        assert_ne!(draw((42, 0, 1)), draw((43, 0, 0)));
+        assert_ne!(draw((42, 1, 0)), draw((42, 0, 1)));
+    }
+
+    #[test]
+    fn normalized_context_vectors_are_averaged() {
+        let context: [&[f32]; 3] = [&[3.0, 4.0], &[0.0, 2.0], &[0.0, 0.0]];
+        let hidden = |normalize: bool| -> Vec<f32> {
+            let mut neu1 = vec![0.0; 2];
+            for word_vec in context {
+                add_context_vector(&mut neu1, word_vec, normalize);
+            }
+            neu1.iter().map(|n| n / context.len() as f32).collect()
+        };
+        // (0.6, 0.8), (0, 1) and the zero vector left unchanged
+        let normalized = hidden(true);
+        assert!((normalized[0] - 0.2).abs() < 1e-6 && (normalized[1] - 0.6).abs() < 1e-6);
+        assert_eq!(hidden(false), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn glove_round_trip() {
+        let vocab = vocab_of(&[("</s>", 0), ("alpha", 2), ("beta", 1)]);
+        let net = net_of(&[&[0.0, 0.0, 0.0], &[1.0, 2.0, -0.5], &[-3.5, 0.125, 4.0]]);
+        let file = temp_path("glove.txt");
+        net.save_glove(&vocab, &file).unwrap();
+        let (loaded_vocab, loaded) = NeuralNet::load_glove(&file).unwrap();
+        assert_eq!(loaded.layer1_size(), 3);
+        assert_eq!(loaded_vocab.len(), 3);
+        for idx in 0..3 {
+            assert_eq!(loaded_vocab.word(idx), vocab.word(idx));
+            assert_eq!(loaded.word_vector(idx), net.word_vector(idx));
+        }
+
+        // files of GloVe itself have no "</s>"
+        let file = write_temp_file("glove-without-separator.txt", "alpha 1 2\nbeta 3 4\n");
+        let (loaded_vocab, loaded) = NeuralNet::load_glove(&file).unwrap();
+        assert_eq!(loaded.layer1_size(), 2);
+        assert_eq!(loaded_vocab.word(0), "</s>");
+        assert_eq!(loaded.word_vector(0), &[0.0, 0.0]);
+        assert_eq!(loaded.word_vector(2), &[3.0, 4.0]);
+    }
+
+    #[test]
+    fn gradient_histogram_counts_finite_updates() {
+        let file = write_two_topic_corpus("gradient-histogram.txt", 200);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.gradient_histogram = true;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = train(&net, &vocab, &params);
+
+        let counts: Vec<u64> = progress
+            .gradient_histogram
+            .iter()
+            .map(|c| c.load(Ordering::Relaxed))
+            .collect();
+        assert!(counts.iter().sum::<u64>() > 0);
+        // the last bucket counts the non-finite norms
+        assert_eq!(counts[GRADIENT_HISTOGRAM_BUCKETS - 1], 0);
+        assert!(net.is_finite());
+    }
+
+    #[test]
+    fn export_top_k_tsv_writes_k_rows() {
+        let vocab = vocab_of(&[("</s>", 9), ("a", 3), ("b", 5), ("c", 1), ("d", 4)]);
+        let net = net_of(&[
+            &[0.0, 0.0, 0.0],
+            &[1.0, 1.0, 1.0],
+            &[2.0, 2.0, 2.0],
+            &[3.0, 3.0, 3.0],
+            &[4.0, 4.0, 4.0],
+        ]);
+        let (vectors_file, metadata_file) = (temp_path("top-k.tsv"), temp_path("top-k-words.tsv"));
+        net.export_top_k_tsv(&vocab, 3, &vectors_file, &metadata_file)
+            .unwrap();
+
+        let vectors = std::fs::read_to_string(&vectors_file).unwrap();
+        assert_eq!(vectors.lines().count(), 3);
+        for line in vectors.lines() {
+            assert_eq!(line.split('\t').count(), 3);
+        }
+        assert!(vectors.starts_with("2.000000\t2.000000\t2.000000\n"));
+        assert_eq!(
+            std::fs::read_to_string(&metadata_file).unwrap(),
+            "b\nd\na\n"
+        );
+    }
+
+    #[test]
+    fn f64_gradients_change_the_vectors_slightly() {
+        let file = write_two_topic_corpus("f64-gradients.txt", 200);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let trained = |params: &TrainigParams| {
+            let net = Arc::new(fresh_net(&vocab, params));
+            train(&net, &vocab, params);
+            net
+        };
+        let f32_net = trained(&params);
+        params.f64_gradients = true;
+        let f64_net = trained(&params);
+
+        assert!(f64_net.is_finite());
+        assert_ne!(f64_net.syn0, f32_net.syn0);
+        let max_diff = f64_net
+            .syn0
+            .iter()
+            .zip(&f32_net.syn0)
+            .map(|(x, y)| (x - y).abs())
+            .fold(0.0f32, f32::max);
+        assert!(max_diff < 1e-3, "{max_diff}");
+    }
+
+    #[test]
+    fn alpha_only_decays_with_decay_set() {
+        let file = write_two_topic_corpus("decay.txt", 100);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let total_words = params.total_iter * vocab.train_words();
+        let alphas = |params: &TrainigParams| -> Vec<f32> {
+            (0..=4)
+                .map(|quarter| learning_rate(params, &vocab, total_words * quarter / 4))
+                .collect()
+        };
+
+        let decayed = alphas(&params);
+        assert_eq!(decayed[0], params.starting_alpha);
+        assert!(decayed.windows(2).all(|w| w[1] < w[0]), "{decayed:?}");
+        assert!((decayed[2] - params.starting_alpha / 2.0).abs() < 1e-3);
+
+        params.decay = false;
+        let constant = alphas(&params);
+        assert!(constant.iter().all(|&alpha| alpha == params.starting_alpha));
+    }
+
+    #[test]
+    fn output_bias_is_only_trained_when_enabled() {
+        let file = write_two_topic_corpus("output-bias.txt", 200);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let net = Arc::new(fresh_net(&vocab, &params));
+        train(&net, &vocab, &params);
+        assert_eq!(net.output_bias(), None);
+
+        params.output_bias = true;
+        let mut net = fresh_net(&vocab, &params);
+        net.enable_output_bias();
+        assert!(net.output_bias().unwrap().iter().all(|&b| b == 0.0));
+        let net = Arc::new(net);
+        train(&net, &vocab, &params);
+        let bias = net.output_bias().unwrap();
+        assert_eq!(bias.len(), vocab.len());
+        assert!(bias.iter().any(|&b| b != 0.0));
+    }
+
+    #[test]
+    fn reversed_sentences_come_in_reverse_order() {
+        let file = write_temp_file("reversed-sentences.txt", "a b\nc\nd e\n");
+        let vocab = learn_vocab(&file);
+        let read = |words: &mut WordSource| -> Vec<String> {
+            std::iter::from_fn(|| words.next_word_index(&vocab))
+                .map(|(idx, _)| vocab.word(idx as usize).to_string())
+                .collect()
+        };
+        let tokenizer = TokenizerParams::default();
+        let fi = FileTokenIterator::with_params(&file, 0, &tokenizer).unwrap();
+        let forward = read(&mut WordSource::File(fi));
+
+        let mut fi = FileTokenIterator::with_params(&file, 0, &tokenizer).unwrap();
+        let reversed = read_reversed_sentences(&mut fi, &vocab, u64::MAX, false);
+        let reversed = read(&mut WordSource::Reversed(reversed));
+        assert_eq!(forward, ["a", "b", "</s>", "c", "</s>", "d", "e", "</s>"]);
+        assert_eq!(reversed, ["d", "e", "</s>", "c", "</s>", "a", "b", "</s>"]);
+    }
+
+    #[test]
+    fn binary_size_bytes_is_the_size_of_the_binary_file() {
+        let vocab = vocab_of(&[("</s>", 0), ("alpha", 2), ("beta", 1)]);
+        let net = net_of(&[&[0.5, -0.25, 1.0], &[1.0, 2.0, 3.0], &[-3.5, 0.125, 0.0]]);
+        let file = temp_path("binary-size.bin");
+        net.save(&vocab, &file, true).unwrap();
+        let file_size = std::fs::metadata(&file).unwrap().len();
+        assert_eq!(net.binary_size_bytes(&vocab) as u64, file_size);
+    }
+
+    #[test]
+    fn remove_component_makes_the_rows_orthogonal() {
+        let mut net = net_of(&[&[1.0, 2.0, 3.0], &[-4.0, 0.5, 2.0], &[0.0, 0.0, 0.0]]);
+        // not a unit vector
+        let direction = [2.0, -1.0, 2.0];
+        net.remove_component(&direction);
+        for idx in 0..3 {
+            assert!(dot_product(net.word_vector(idx), &direction).abs() < 1e-5);
+        }
+        // (1, 2, 3) minus its projection 6/9 * (2, -1, 2)
+        let expected = [1.0 - 4.0 / 3.0, 2.0 + 2.0 / 3.0, 3.0 - 4.0 / 3.0];
+        for (x, e) in net.word_vector(0).iter().zip(expected) {
+            assert!((x - e).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn loss_log_has_one_row_per_epoch() {
+        let file = write_two_topic_corpus("loss-log.txt", 100);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.total_iter = 2;
+        let log_file = temp_path("loss-log.csv");
+        params.loss_log_path = Some(log_file.clone());
+        let net = Arc::new(fresh_net(&vocab, &params));
+        train(&net, &vocab, &params);
+
+        let log = std::fs::read_to_string(&log_file).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "epoch,avg_loss,alpha,elapsed_secs");
+        for (epoch, line) in lines[1..].iter().enumerate() {
+            let fields: Vec<f64> = line.split(',').map(|x| x.parse().unwrap()).collect();
+            assert_eq!(fields.len(), 4);
+            assert_eq!(fields[0], (epoch + 1) as f64);
+            assert!(fields[1] > 0.0 && fields[1].is_finite());
+        }
+    }
+
+    #[test]
+    fn save_filtered_only_writes_the_kept_words() {
+        let vocab = vocab_of(&[("</s>", 0), ("alpha", 3), ("b2", 2), ("gamma", 1)]);
+        let net = net_of(&[&[0.0, 0.0], &[1.0, 2.0], &[3.0, 4.0], &[5.0, 6.0]]);
+        let file = temp_path("save-filtered.txt");
+        let alphabetic = |w: &str| w.chars().all(char::is_alphabetic);
+        net.save_filtered(&vocab, &file, false, alphabetic).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "2 2\nalpha 1.000000 2.000000 \ngamma 5.000000 6.000000 \n"
+        );
+        let (loaded_vocab, loaded) = NeuralNet::load_text(&file).unwrap();
+        assert_eq!(
+            loaded_vocab.into_iter().collect::<Vec<_>>(),
+            ["alpha", "gamma"]
+        );
+        assert_eq!(loaded.word_vector(1), &[5.0, 6.0]);
+    }
+
+    #[test]
+    fn each_epoch_duration_is_recorded() {
+        let file = write_two_topic_corpus("epoch-durations.txt", 100);
+        let vocab = learn_vocab(&file);
+        let params = training_params(&file);
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = train(&net, &vocab, &params);
+        let durations = progress.epoch_durations.into_inner().unwrap();
+        assert_eq!(durations.len(), 3);
+        assert!(durations.iter().all(|d| !d.is_zero()));
+    }
+
+    #[test]
+    fn deterministic_runs_give_identical_vectors() {
+        let file = write_two_topic_corpus("deterministic.txt", 200);
+        let mut params = training_params(&file);
+        params.deterministic = true;
+        params.seed = 7;
+        params.sample = 1e-3;
+        let run = || {
+            let vocab = learn_vocab(&file);
+            let net = Arc::new(fresh_net(&vocab, &params));
+            train(&net, &vocab, &params);
+            Arc::into_inner(net).unwrap().syn0
+        };
+        let first = run();
+        let second = run();
+        let bits = |syn0: &[f32]| syn0.iter().map(|x| x.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&first), bits(&second));
+
+        let vocab = learn_vocab(&file);
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let rejected = |params: &TrainigParams| {
+            train_model(
+                &net,
+                &vocab,
+                params,
+                &TrainigProgress::new(),
+                &mut |_, _: &TrainigProgress| {},
+            )
+            .unwrap_err()
+            .kind()
+        };
+        params.num_threads = 2;
+        assert_eq!(rejected(&params), ErrorKind::InvalidInput);
+        params.num_threads = 1;
+        params.renorm_every = NonZeroU64::new(100);
+        assert_eq!(rejected(&params), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn renormalization_keeps_the_norms_near_one() {
+        /// records the largest distance of an input vector norm to 1 at each epoch end
+        struct NormObserver {
+            net: Arc<NeuralNet>,
+            distances: Vec<f32>,
+        }
+        impl TrainingObserver for NormObserver {
+            fn epoch_end(&mut self, _epoch: u64, _progress: &TrainigProgress) {
+                let distance = (1..self.net.vocab_size())
+                    .map(|idx| {
+                        let vector = self.net.word_vector(idx);
+                        (dot_product(vector, vector).sqrt() - 1.0).abs()
+                    })
+                    .fold(0.0, f32::max);
+                self.distances.push(distance);
+            }
+        }
+
+        let file = write_two_topic_corpus("renorm.txt", 5000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let mut sample_distances = |renorm_every| {
+            params.renorm_every = renorm_every;
+            let net = Arc::new(fresh_net(&vocab, &params));
+            let mut observer = NormObserver {
+                net: Arc::clone(&net),
+                distances: Vec::new(),
+            };
+            train_model(
+                &net,
+                &vocab,
+                &params,
+                &TrainigProgress::new(),
+                &mut observer,
+            )
+            .unwrap();
+            observer.distances
+        };
+        let renormalized = sample_distances(NonZeroU64::new(1000));
+        assert!(renormalized.len() >= 3, "{renormalized:?}");
+        assert!(renormalized.iter().all(|&d| d < 1e-3), "{renormalized:?}");
+        // without renormalization the norms move away from 1
+        let unnormalized = sample_distances(None);
+        assert!(unnormalized.iter().any(|&d| d > 0.5), "{unnormalized:?}");
+    }
+
+    #[test]
+    fn nonfinite_weights_are_detected() {
+        let mut net = NeuralNet::with_seed(4, 3, 1);
+        assert!(net.is_finite());
+        assert_eq!(net.count_nonfinite(), 0);
+
+        net.syn0[5] = f32::NAN;
+        assert!(!net.is_finite());
+        assert_eq!(net.count_nonfinite(), 1);
+        net.syn1neg[2] = f32::NEG_INFINITY;
+        assert_eq!(net.count_nonfinite(), 2);
+    }
+
+    #[test]
+    fn alpha_warms_up_then_decays() {
+        let file = write_two_topic_corpus("warmup.txt", 5000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.record_alpha = true;
+        params.warmup_words = vocab.train_words() * params.total_iter / 3;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = train(&net, &vocab, &params);
+        let trajectory = progress.alpha_trajectory.into_inner().unwrap();
+
+        let (warmup, decay): (Vec<_>, Vec<_>) = trajectory
+            .into_iter()
+            .partition(|&(word_count, _)| word_count < params.warmup_words);
+        assert!(warmup.len() > 2 && decay.len() > 2);
+        assert!(warmup.windows(2).all(|w| w[0].1 < w[1].1));
+        assert!(decay.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert!(warmup[0].1 < params.starting_alpha / 2.0);
+        assert!(decay[0].1 <= params.starting_alpha);
+    }
+
+    #[test]
+    fn over_training_saturates_the_dot_products() {
+        let file = write_two_topic_corpus("saturation.txt", 200);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.total_iter = 50;
+        params.starting_alpha = 0.5;
+        params.decay = false;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = train(&net, &vocab, &params);
+        assert!(progress.forward_passes.load(Ordering::Relaxed) > 0);
+        assert!(progress.saturation_rate() > 0.0);
+        assert!(progress.saturation_rate() <= 1.0);
+    }
+
+    #[test]
+    fn word_counts_survive_the_extended_binary_format() {
+        let vocab = vocab_of(&[("</s>", 0), ("apple", 30), ("pear", 20), ("car", 7)]);
+        let net = net_of(&[&[0.0, 0.5], &[1.0, 0.0], &[0.9, -0.1], &[0.0, 1.0]]);
+        let file = temp_path("with-counts.bin");
+        net.save_binary_with_counts(&vocab, &file).unwrap();
+        let (loaded_vocab, loaded) = NeuralNet::load_binary_with_counts(&file).unwrap();
+
+        assert_eq!(loaded_vocab.len(), vocab.len());
+        for idx in 0..vocab.len() {
+            assert_eq!(loaded_vocab.word(idx), vocab.word(idx));
+            assert_eq!(loaded_vocab.word_count(idx), vocab.word_count(idx));
+            assert_eq!(loaded.word_vector(idx), net.word_vector(idx));
+        }
+    }
+
+    #[test]
+    fn adam_lowers_the_loss_and_keeps_the_net_finite() {
+        let file = write_two_topic_corpus("adam.txt", 200);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.adam = true;
+        params.starting_alpha = 0.005;
+        let mut net = fresh_net(&vocab, &params);
+        net.enable_adam();
+        let net = Arc::new(net);
+        let fresh_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        train(&net, &vocab, &params);
+        let trained_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        assert!(trained_loss < fresh_loss, "{trained_loss} >= {fresh_loss}");
+        assert!(net.is_finite());
+    }
+
+    #[test]
+    fn hierarchical_softmax_alone_lowers_the_loss() {
+        let file = write_two_topic_corpus("hierarchical-softmax.txt", 500);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.hierarchical_softmax = true;
+        params.negative_samples = 0;
+        let mut net = fresh_net(&vocab, &params);
+        net.enable_hierarchical_softmax(&vocab);
+        let net = Arc::new(net);
+        let fresh_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        train(&net, &vocab, &params);
+        let trained_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        assert!(trained_loss < fresh_loss, "{trained_loss} >= {fresh_loss}");
+        assert!(net.is_finite());
+    }
+
+    #[test]
+    fn a_word_kept_with_probability_zero_is_never_trained() {
+        let file = write_two_topic_corpus("keep-probabilities.txt", 200);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let cat = vocab.search_word("cat") as usize;
+        let mut keep = vec![1.0; vocab.len()];
+        keep[cat] = 0.0;
+        params.keep_probabilities = Some(keep);
+        for architecture in [Architecture::Cbow, Architecture::SkipGram] {
+            params.architecture = architecture;
+            let fresh = fresh_net(&vocab, &params);
+            let net = Arc::new(fresh_net(&vocab, &params));
+            train(&net, &vocab, &params);
+            assert_eq!(net.word_vector(cat), fresh.word_vector(cat));
+            let dog = vocab.search_word("dog") as usize;
+            assert_ne!(net.word_vector(dog), fresh.word_vector(dog));
+        }
+    }
+
+    #[test]
+    fn subsampling_drops_occurrences_of_very_frequent_words_only() {
+        // "the" makes up 2/3 of the words, each other word occurs 10 times
+        let mut corpus = String::new();
+        for i in 0..1000 {
+            let (a, b, c) = (i % 300, (i + 100) % 300, (i + 200) % 300);
+            corpus.push_str(&format!("the the w{a} the the w{b} the the w{c} the the\n"));
+        }
+        let file = write_temp_file("subsampling.txt", &corpus);
+        let vocab = learn_vocab(&file);
+        let the = vocab.search_word("the") as usize;
+        assert!(subsample_keep_probability(&vocab, 1e-3, the) < 0.05);
+        for i in 0..300 {
+            let rare = vocab.search_word(&format!("w{i}")) as usize;
+            assert!(
+                subsample_keep_probability(&vocab, 1e-3, rare) >= 1.0,
+                "w{i}"
+            );
+        }
+
+        // most examples have "the" as center word, they are skipped
+        let mut params = training_params(&file);
+        params.total_iter = 1;
+        let mut forward_passes = |sample: f32| -> u64 {
+            params.sample = sample;
+            let net = Arc::new(fresh_net(&vocab, &params));
+            let progress = train(&net, &vocab, &params);
+            progress.forward_passes.load(Ordering::Relaxed)
+        };
+        let unsampled = forward_passes(0.0);
+        let sampled = forward_passes(1e-3);
+        assert!(sampled * 2 < unsampled, "{sampled} {unsampled}");
+    }
+
+    #[test]
+    fn every_half_survives_a_round_trip() {
+        for half in 0..=u16::MAX {
+            let value = f16_to_f32(half);
+            if value.is_nan() {
+                assert!(half & 0x7c00 == 0x7c00 && half & 0x3ff != 0, "{half:#06x}");
+                assert!(f16_to_f32(f32_to_f16(value)).is_nan());
+            } else {
+                assert_eq!(f32_to_f16(value), half, "{value}");
+            }
+        }
+    }
+
+    #[test]
+    fn floats_convert_to_the_expected_halves() {
+        let cases: [(f32, u16); 16] = [
+            (0.0, 0x0000),
+            (-0.0, 0x8000),
+            (1.0, 0x3c00),
+            (-2.0, 0xc000),
+            (0.5, 0x3800),
+            (0.1, 0x2e66),
+            (65504.0, 0x7bff),
+            // the largest finite half is 65504, 65520 is halfway to the next power of 2
+            (65519.0, 0x7bff),
+            (65520.0, 0x7c00),
+            (1e10, 0x7c00),
+            (f32::INFINITY, 0x7c00),
+            (f32::NEG_INFINITY, 0xfc00),
+            // the smallest normal and subnormal halves
+            (f32::powi(2.0, -14), 0x0400),
+            (f32::powi(2.0, -24), 0x0001),
+            (-f32::powi(2.0, -24), 0x8001),
+            (f32::powi(2.0, -26), 0x0000),
+        ];
+        for (value, half) in cases {
+            assert_eq!(f32_to_f16(value), half, "{value}");
+        }
+        assert_eq!(f32_to_f16(f32::NAN) & 0x7c00, 0x7c00);
+        assert_ne!(f32_to_f16(f32::NAN) & 0x3ff, 0);
+    }
+
+    #[test]
+    fn halfway_floats_round_to_even_halves() {
+        let ulp = f32::powi(2.0, -10);
+        // halfway between 1 and 1 + ulp, then between 1 + ulp and 1 + 2 ulp
+        assert_eq!(f32_to_f16(1.0 + ulp / 2.0), 0x3c00);
+        assert_eq!(f32_to_f16(1.0 + 1.5 * ulp), 0x3c02);
+        // just above halfway
+        assert_eq!(f32_to_f16(1.0 + ulp / 2.0 + f32::EPSILON), 0x3c01);
+        // halfway between subnormals, 2^-25 is between 0 and the smallest one
+        let sub = f32::powi(2.0, -24);
+        assert_eq!(f32_to_f16(sub / 2.0), 0x0000);
+        assert_eq!(f32_to_f16(1.5 * sub), 0x0002);
+        assert_eq!(f32_to_f16(2.5 * sub), 0x0002);
+        // the largest subnormal rounds up to the smallest normal half
+        assert_eq!(f32_to_f16(f32::powi(2.0, -14) - sub / 2.0), 0x0400);
+    }
+
+    #[test]
+    fn halves_are_within_the_precision_bound() {
+        // normal halves have a relative error of at most 2^-11, subnormal ones an
+        // absolute error of at most 2^-25
+        let mut bits = f32::powi(2.0, -30).to_bits();
+        while f32::from_bits(bits) < 65504.0 {
+            let value = f32::from_bits(bits);
+            let error = (f16_to_f32(f32_to_f16(value)) - value).abs();
+            if value >= f32::powi(2.0, -14) {
+                assert!(error <= value * f32::powi(2.0, -11), "{value}");
+            } else {
+                assert!(error <= f32::powi(2.0, -25), "{value}");
+            }
+            bits += 997;
+        }
+    }
+
+    #[test]
+    fn f16_vectors_survive_save_and_load() {
+        let vocab = vocab_of(&[("</s>", 0), ("apple", 3), ("pear", 2)]);
+        let net = net_of(&[&[0.0, -1.5], &[0.1, 1e-6], &[-123.456, 7.0]]);
+        let file = temp_path("vectors-f16.bin");
+        net.save_binary_f16(&vocab, &file).unwrap();
+        let (loaded_vocab, loaded) = NeuralNet::load_binary_f16(&file).unwrap();
+        assert_eq!(loaded_vocab.word(2), "pear");
+        for idx in 0..3 {
+            for (&a, &b) in loaded.word_vector(idx).iter().zip(net.word_vector(idx)) {
+                assert!((a - b).abs() <= b.abs() * f32::powi(2.0, -11) + f32::powi(2.0, -25));
+            }
+        }
+        // a 4 byte float model has the same header, but no magic line
+        net.save(&vocab, &file, true).unwrap();
+        let result = NeuralNet::load_binary_f16(&file);
+        assert!(matches!(result, Err(err) if err.kind() == ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn low_weight_tokens_move_their_vectors_less() {
+        let corpus =
+            "the cat faint:0.1 sat on the mat\nthe cat clear:1.0 sat on the mat\n".repeat(300);
+        let file = write_temp_file("token-weights.txt", &corpus);
+        let tokenizer = TokenizerParams {
+            token_weights: true,
+            ..Default::default()
+        };
+        let vocab_params = VocabParams {
+            tokenizer: tokenizer.clone(),
+            ..vocab_params()
+        };
+        let vocab = Vocabulary::learn_vocabulary_with_params(&file, &vocab_params).unwrap();
+        let mut params = training_params(&file);
+        params.tokenizer = tokenizer;
+        // short of convergence, where both norms would settle
+        params.total_iter = 1;
+        for architecture in [Architecture::Cbow, Architecture::SkipGram] {
+            params.architecture = architecture;
+            let fresh = fresh_net(&vocab, &params);
+            let net = Arc::new(fresh_net(&vocab, &params));
+            train(&net, &vocab, &params);
+            let norm_change = |word: &str| {
+                let idx = vocab.search_word(word) as usize;
+                let norm = |v: &[f32]| dot_product(v, v).sqrt();
+                (norm(net.word_vector(idx)) - norm(fresh.word_vector(idx))).abs()
+            };
+            let (faint, clear) = (norm_change("faint"), norm_change("clear"));
+            assert!(faint < clear, "{architecture:?}: {faint} {clear}");
+        }
+    }
+
+    #[test]
+    fn alpha_trajectory_decays_from_the_start_to_the_floor() {
+        let file = write_two_topic_corpus("alpha-trajectory.txt", 2000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.record_alpha = true;
+        // reached after half of the training
+        params.min_alpha = Some(params.starting_alpha / 2.0);
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = train(&net, &vocab, &params);
+        let trajectory = progress.alpha_trajectory.into_inner().unwrap();
+
+        assert_eq!(trajectory[0], (0, params.starting_alpha));
+        assert!(
+            trajectory
+                .windows(2)
+                .all(|w| w[0].0 <= w[1].0 && w[0].1 >= w[1].1)
+        );
+        assert_eq!(trajectory.last().unwrap().1, params.starting_alpha / 2.0);
+    }
+
+    #[test]
+    fn snapshots_hold_two_coordinates_of_the_top_words() {
+        let file = write_two_topic_corpus("snapshots.txt", 5000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let prefix = temp_path("snapshot-");
+        params.snapshot_prefix = Some(prefix.clone());
+        params.snapshot_every = 20_000;
+        params.snapshot_words = 5;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = train(&net, &vocab, &params);
+
+        // one snapshot for each multiple of snapshot_every words trained on
+        let total_words = progress.word_count_actual.load(Ordering::Relaxed);
+        let snapshots = (1..)
+            .take_while(|n| std::path::Path::new(&format!("{prefix}{n:05}.txt")).exists())
+            .count() as u64;
+        assert_eq!(snapshots, total_words / params.snapshot_every);
+        for n in 1..=snapshots {
+            let contents = std::fs::read_to_string(format!("{prefix}{n:05}.txt")).unwrap();
+            let lines: Vec<&str> = contents.lines().collect();
+            assert_eq!(lines.len(), 5);
+            for (line, idx) in lines.iter().zip(1..) {
+                let fields: Vec<&str> = line.split(' ').collect();
+                assert_eq!(fields[0], vocab.word(idx));
+                assert_eq!(fields.len(), 3);
+                assert!(
+                    fields[1..]
+                        .iter()
+                        .all(|f| f.parse::<f32>().unwrap().is_finite())
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn merged_rare_words_train_unk() {
+        let mut corpus = String::new();
+        for i in 0..300 {
+            corpus.push_str(&format!("the cat sat on rare{i} mat\n"));
+        }
+        let file = write_temp_file("train-unk.txt", &corpus);
+        let vocab_params = VocabParams {
+            min_count: 2,
+            merge_rare_words: true,
+            ..vocab_params()
+        };
+        let vocab = Vocabulary::learn_vocabulary_with_params(&file, &vocab_params).unwrap();
+        let unk = vocab.word_index_or_unknown("rare0") as usize;
+        assert_eq!(vocab.word(unk), "<UNK>");
+        assert_eq!(vocab.word_count(unk), 300);
+
+        let params = training_params(&file);
+        let fresh = fresh_net(&vocab, &params);
+        let net = Arc::new(fresh_net(&vocab, &params));
+        train(&net, &vocab, &params);
+        assert_ne!(net.word_vector(unk), fresh.word_vector(unk));
+    }
+
+    #[test]
+    fn training_without_threads_is_rejected() {
+        let file = write_temp_file("zero-threads.txt", "the quick fox\n");
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.num_threads = 0;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let result = train_model(
+            &net,
+            &vocab,
+            &params,
+            &TrainigProgress::new(),
+            &mut |_, _: &TrainigProgress| {},
+        );
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn windows_are_shrunk_like_the_original() {
+        // the draws of the original for a window of 5, next_random starting at 0
+        let mut rand_gen = LcRandomGen::new(0);
+        let windows: Vec<usize> = (0..8).map(|_| rand_gen.shrunk_window(5)).collect();
+        assert_eq!(windows, [4, 2, 2, 4, 5, 5, 5, 5]);
+
+        let mut seen = [false; 5];
+        let mut rand_gen = RandomStreams::new(7).window;
+        for _ in 0..1000 {
+            let window = rand_gen.shrunk_window(5);
+            assert!((1..=5).contains(&window));
+            seen[window - 1] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn lock_free_threads_lower_the_loss() {
+        let file = write_two_topic_corpus("lock-free.txt", 2000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.num_threads = 4;
+        params.lock_free = true;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let fresh_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        train(&net, &vocab, &params);
+        let trained_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        assert!(trained_loss < fresh_loss, "{trained_loss} >= {fresh_loss}");
+        assert!(net.is_finite());
+    }
+
+    #[test]
+    fn compute_loss_rejects_an_empty_window() {
+        let file = write_temp_file("compute-loss-window.txt", "the quick fox\n");
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.window = 0;
+        let err = fresh_net(&vocab, &params)
+            .compute_loss(&vocab, &file, &params, 10)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn training_without_an_output_objective_is_rejected() {
+        let file = write_temp_file("no-negative-samples.txt", "the quick fox\n");
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        let rejected = |params: &TrainigParams| {
+            let net = Arc::new(fresh_net(&vocab, params));
+            let result = train_model(
+                &net,
+                &vocab,
+                params,
+                &TrainigProgress::new(),
+                &mut |_, _: &TrainigProgress| {},
+            );
+            result.unwrap_err().kind()
+        };
+        params.negative_samples = -1;
+        assert_eq!(rejected(&params), ErrorKind::InvalidInput);
+        // no negative samples and no hierarchical softmax would only train the positive
+        // targets, pushing every output towards 1
+        params.negative_samples = 0;
+        assert_eq!(rejected(&params), ErrorKind::InvalidInput);
+        let err = fresh_net(&vocab, &params)
+            .compute_loss(&vocab, &file, &params, 10)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn adagrad_lowers_the_loss_and_keeps_the_net_finite() {
+        let file = write_two_topic_corpus("adagrad.txt", 200);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.adagrad = true;
+        params.starting_alpha = 0.1;
+        let mut net = fresh_net(&vocab, &params);
+        net.enable_adagrad();
+        let net = Arc::new(net);
+        let fresh_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        train(&net, &vocab, &params);
+        let trained_loss = net.compute_loss(&vocab, &file, &params, 100).unwrap();
+        assert!(trained_loss < fresh_loss, "{trained_loss} >= {fresh_loss}");
+        assert!(net.is_finite());
+        // the squared gradients of the trained rows were accumulated
+        let adagrad = net.adagrad.as_ref().unwrap();
+        assert!(
+            adagrad
+                .syn1neg_sums
+                .iter()
+                .any(|&sum| sum > ADAGRAD_INITIAL_ACCUMULATOR)
+        );
+    }
+
+    #[test]
+    fn warm_start_reuses_the_pretrained_vectors_of_shared_words() {
+        let pretrained_vocab = vocab_of(&[("</s>", 0), ("cat", 5), ("dog", 3), ("owl", 2)]);
+        let pretrained = net_of(&[&[0.0, 0.0], &[1.0, 2.0], &[3.0, 4.0], &[5.0, 6.0]]);
+        let vocab = vocab_of(&[("</s>", 9), ("dog", 7), ("car", 4), ("cat", 2)]);
+        let (net, reused) = NeuralNet::warm_start(&vocab, &pretrained_vocab, &pretrained, 3);
+        // "</s>", "dog" and "cat"
+        assert_eq!(reused, 3);
+        assert_eq!(net.layer1_size(), 2);
+        assert!(net.has_output_layer());
+        assert_eq!(net.word_vector(1), [3.0, 4.0]);
+        assert_eq!(net.word_vector(3), [1.0, 2.0]);
+        // the new word gets the random vector of a fresh net
+        let fresh = NeuralNet::with_seed(vocab.len(), 2, 3);
+        assert_eq!(net.word_vector(2), fresh.word_vector(2));
+        assert_ne!(net.word_vector(2), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn progress_is_reported_every_n_words() {
+        let file = write_two_topic_corpus("progress.txt", 5000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.progress_every = 5000;
+        let reports = progress_reports(&vocab, &params);
+        assert!(reports.len() >= 3, "{reports:?}");
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].word_count <= w[1].word_count)
+        );
+        let total_words = params.total_iter * vocab.train_words();
+        for report in &reports {
+            assert!(report.word_count >= params.progress_every);
+            assert_eq!(report.total_words, total_words);
+            assert!(report.words_per_sec.is_finite() && report.words_per_sec > 0.0);
+            assert!(report.eta.is_some());
+            assert!(report.alpha > 0.0 && report.alpha <= params.starting_alpha);
+        }
+        let (first, last) = (reports[0], reports[reports.len() - 1]);
+        assert!(last.eta.unwrap() < first.eta.unwrap(), "{first:?} {last:?}");
+        assert!(last.alpha < first.alpha, "{first:?} {last:?}");
+        assert!(last.loss.is_some());
+
+        params.progress_every = 0;
+        assert!(progress_reports(&vocab, &params).is_empty());
+    }
+
+    #[test]
+    fn smoothed_loss_decreases_over_the_batches() {
+        let file = write_two_topic_corpus("smoothed-loss.txt", 5000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.progress_every = 1;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let progress = TrainigProgress::new();
+        let mut recorder = ReportRecorder(Vec::new());
+        train_model(&net, &vocab, &params, &progress, &mut recorder).unwrap();
+        let first = recorder.0.iter().find_map(|report| report.loss).unwrap();
+        let last = progress.smoothed_loss.lock().unwrap().unwrap();
+        assert!(first.is_finite() && last.is_finite(), "{first} {last}");
+        assert!(last < first, "{first} {last}");
+
+        // no batch of about 10000 words is published
+        let file = write_temp_file("smoothed-loss-short.txt", &"the quick fox\n".repeat(200));
+        let vocab = learn_vocab(&file);
+        let params = training_params(&file);
+        let progress = train(&Arc::new(fresh_net(&vocab, &params)), &vocab, &params);
+        assert_eq!(*progress.smoothed_loss.lock().unwrap(), None);
+    }
+
+    #[test]
+    fn adagrad_steps_shrink_as_the_squared_gradients_add_up() {
+        let mut net = net_of(&[&[0.0, 0.0], &[0.0, 0.0]]);
+        net.enable_adagrad();
+        let grad = [1.0, -0.5];
+        let mut previous = net.word_vector(1).to_vec();
+        let mut steps = Vec::new();
+        for _ in 0..4 {
+            unsafe { net.adagrad_update(false, 1, &grad, 0.1) };
+            let current = net.word_vector(1).to_vec();
+            steps.push([current[0] - previous[0], current[1] - previous[1]]);
+            previous = current;
+        }
+        // the k-th step is alpha * g / sqrt(initial + k * g^2)
+        for (k, step) in steps.iter().enumerate() {
+            for (i, g) in grad.into_iter().enumerate() {
+                let expected =
+                    0.1 * g / (ADAGRAD_INITIAL_ACCUMULATOR + (k + 1) as f32 * g * g).sqrt();
+                assert!((step[i] - expected).abs() < 1e-6, "{steps:?}");
+            }
+        }
+        assert!(
+            steps
+                .windows(2)
+                .all(|w| w[1][0] < w[0][0] && w[1][1] > w[0][1])
+        );
+        // the rows not updated keep their state
+        assert_eq!(net.word_vector(0), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn clipping_keeps_a_huge_learning_rate_finite() {
+        let file = write_two_topic_corpus("max-grad.txt", 500);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.starting_alpha = 1e30;
+        params.output_bias = true;
+        params.max_grad = Some(0.1);
+        let mut net = fresh_net(&vocab, &params);
+        net.enable_output_bias();
+        let net = Arc::new(net);
+        train(&net, &vocab, &params);
+        assert!(net.is_finite());
+        // each bias step is bounded by max_grad
+        let updates =
+            params.total_iter * vocab.train_words() * (params.negative_samples as u64 + 1);
+        let bound = 0.1 * updates as f32;
+        assert!(net.output_bias().unwrap().iter().all(|b| b.abs() <= bound));
+
+        params.max_grad = None;
+        let mut net = fresh_net(&vocab, &params);
+        net.enable_output_bias();
+        let net = Arc::new(net);
+        train(&net, &vocab, &params);
+        assert!(!net.is_finite());
+    }
+
+    #[test]
+    fn early_stopping_keeps_the_best_vectors_across_a_resume() {
+        let file = write_two_topic_corpus("early-stopping.txt", 500);
+        // sentences mixing the two topics, which training makes less likely
+        let mixed: String = (0..50)
+            .map(|i| format!("{} {}\n", TOPIC_WORDS[0][i % 6], TOPIC_WORDS[1][i % 6]))
+            .collect();
+        let validation = write_temp_file("early-stopping-validation.txt", &mixed);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.total_iter = 10;
+        params.patience = 2;
+        params.validation_file = Some(validation);
+        let best_checkpoint = temp_path("early-stopping.checkpoint");
+        params.best_checkpoint_file = Some(best_checkpoint.clone());
+
+        // the input vectors after each epoch
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let mut epoch_vectors: Vec<Vec<f32>> = Vec::new();
+        let progress = TrainigProgress::new();
+        let mut observer = |_, _: &TrainigProgress| epoch_vectors.push(net.syn0.clone());
+        train_model(&net, &vocab, &params, &progress, &mut observer).unwrap();
+        let losses = progress.validation_losses.lock().unwrap().clone();
+        assert!(losses.windows(2).all(|w| w[1] > w[0]), "{losses:?}");
+        // the loss of the first epoch is the least, the next `patience` ones stop training
+        assert_eq!(losses.len(), 3);
+        assert_eq!(epoch_vectors.len(), 3);
+        assert_eq!(
+            *progress.best_validation.lock().unwrap(),
+            Some((losses[0], 0))
+        );
+        let (_, best, position) = NeuralNet::load_checkpoint(&best_checkpoint).unwrap();
+        assert_eq!(best.syn0, epoch_vectors[0]);
+        assert_eq!(position.epoch, 1);
+        assert_eq!(position.best_validation, Some((losses[0], 0)));
+
+        // resuming after the best epoch keeps its checkpoint and stops as early
+        let (vocab, resumed, position) = NeuralNet::load_checkpoint(&best_checkpoint).unwrap();
+        let resumed = Arc::new(resumed);
+        let progress = TrainigProgress::new();
+        train_model_from(
+            &resumed,
+            &vocab,
+            &params,
+            &progress,
+            &mut |_, _: &TrainigProgress| {},
+            Some(&position),
+        )
+        .unwrap();
+        assert_eq!(progress.validation_losses.lock().unwrap().len(), 2);
+        assert_eq!(
+            *progress.best_validation.lock().unwrap(),
+            Some((losses[0], 0))
+        );
+        let (_, best, _) = NeuralNet::load_checkpoint(&best_checkpoint).unwrap();
+        assert_eq!(best.syn0, epoch_vectors[0]);
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let mut corpus = String::new();
+        for i in 0..300 {
+            corpus.push_str(&format!("the cat sat on rare{i} mat\n"));
+        }
+        let file = write_temp_file("checkpoint-round-trip.txt", &corpus);
+        let vocab_params = VocabParams {
+            min_count: 2,
+            merge_rare_words: true,
+            ..vocab_params()
+        };
+        let vocab = Vocabulary::learn_vocabulary_with_params(&file, &vocab_params).unwrap();
+        let mut params = training_params(&file);
+        params.hierarchical_softmax = true;
+        params.output_bias = true;
+        params.total_iter = 1;
+        let mut net = fresh_net(&vocab, &params);
+        net.enable_hierarchical_softmax(&vocab);
+        net.enable_output_bias();
+        let net = Arc::new(net);
+        let progress = train(&net, &vocab, &params);
+
+        let position = TrainingPosition {
+            epoch: 1,
+            word_count_actual: progress.word_count_actual.load(Ordering::Relaxed),
+            threads: progress.thread_positions.lock().unwrap().clone(),
+            best_validation: Some((0.1 + 0.2, 0)),
+        };
+        let checkpoint = temp_path("round-trip.checkpoint");
+        net.save_checkpoint(&vocab, &position, &checkpoint).unwrap();
+        let (loaded_vocab, loaded, loaded_position) =
+            NeuralNet::load_checkpoint(&checkpoint).unwrap();
+        assert_eq!(loaded_position, position);
+        assert_eq!(loaded.syn0, net.syn0);
+        assert_eq!(loaded.syn1neg, net.syn1neg);
+        assert_eq!(loaded.syn1, net.syn1);
+        assert_eq!(loaded.bias, net.bias);
+        assert!(loaded.huffman_tree.is_some());
+        let words = |v: &Vocabulary| -> Vec<(String, u32)> {
+            (0..v.len())
+                .map(|i| (v.word(i).to_string(), v.word_count(i)))
+                .collect()
+        };
+        assert_eq!(words(&loaded_vocab), words(&vocab));
+        let unk = vocab.unknown_word_index().unwrap();
+        assert_eq!(loaded_vocab.unknown_word_index(), Some(unk));
+        assert_eq!(loaded_vocab.word_index_or_unknown("rare1000"), unk as i32);
+    }
+
+    #[test]
+    fn interrupted_training_resumes_from_its_checkpoint() {
+        let file = write_two_topic_corpus("resume.txt", 5000);
+        let vocab = learn_vocab(&file);
+        let mut params = training_params(&file);
+        params.total_iter = 2;
+        let checkpoint = temp_path("resume.checkpoint");
+        params.checkpoint_file = Some(checkpoint.clone());
+        params.checkpoint_every = 20_000;
+        let net = Arc::new(fresh_net(&vocab, &params));
+        let uninterrupted = train(&net, &vocab, &params);
+        let total = uninterrupted.word_count_actual.load(Ordering::Relaxed);
+
+        // the last checkpoint saved is where a run interrupted after it resumes
+        let (loaded_vocab, loaded, position) = NeuralNet::load_checkpoint(&checkpoint).unwrap();
+        assert!(position.epoch < params.total_iter);
+        assert!(position.word_count_actual >= 20_000 && position.word_count_actual <= total);
+        assert_eq!(position.threads.len(), 1);
+        params.checkpoint_file = None;
+        let loaded = Arc::new(loaded);
+        let resumed = TrainigProgress::new();
+        train_model_from(
+            &loaded,
+            &loaded_vocab,
+            &params,
+            &resumed,
+            &mut |_, _: &TrainigProgress| {},
+            Some(&position),
+        )
+        .unwrap();
+        // the words of the sentence the checkpoint was saved in are counted again
+        let resumed_total = resumed.word_count_actual.load(Ordering::Relaxed);
+        assert!(
+            resumed_total >= total && resumed_total <= total + 9,
+            "{resumed_total} words after resuming, {total} without interruption"
+        );
+        let fresh_loss = fresh_net(&vocab, &params)
+            .compute_loss(&vocab, &file, &params, 100)
+            .unwrap();
+        let resumed_loss = loaded.compute_loss(&vocab, &file, &params, 100).unwrap();
+        assert!(resumed_loss < fresh_loss, "{resumed_loss} >= {fresh_loss}");
+    }
+}
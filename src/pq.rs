@@ -0,0 +1,326 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+
+use crate::linalg::{kmeans, nearest_centroid, squared_distance};
+use crate::nnet::NeuralNet;
+use crate::vocab::Vocabulary;
+
+/// maximum number of k-means iterations when learning a codebook
+const PQ_KMEANS_ITERATIONS: usize = 25;
+
+/// Word vectors compressed by product quantization: each vector is split into
+/// `subspaces` parts of equal length, and each part is replaced by the 1 byte index
+/// of the closest vector of the codebook learned for its subspace.
+pub struct ProductQuantizer {
+    layer1_size: usize,
+    subspaces: usize,
+    /// number of vectors in each codebook, at most 256
+    codebook_size: usize,
+    /// the codebook vectors of the first subspace, then those of the second one, ...
+    codebooks: Vec<f32>,
+    /// `subspaces` codes for each word
+    codes: Vec<u8>,
+}
+
+impl NeuralNet {
+    /// Learn product quantization codebooks of 2^`bits` vectors for `m` subspaces with
+    /// k-means over the input vectors and encode every vector. A vector then takes
+    /// `m` bytes instead of 4 * layer1_size; the larger `m` and `bits`, the closer
+    /// the decoded vectors are to the originals, see `ProductQuantizer::distortion`.
+    /// Learning takes O(vocab_size * 2^bits * layer1_size) time per k-means iteration.
+    /// A net without words gives a quantizer without words and codebooks.
+    pub fn train_pq(&self, m: usize, bits: usize) -> ProductQuantizer {
+        assert!(
+            m > 0 && self.layer1_size().is_multiple_of(m),
+            "the vector size must be a multiple of the number of subspaces"
+        );
+        assert!((1..=8).contains(&bits), "the codes must have 1 to 8 bits");
+        let sub_dim = self.layer1_size() / m;
+        let codebook_size = (1usize << bits).min(self.vocab_size());
+        if codebook_size == 0 {
+            return ProductQuantizer {
+                layer1_size: self.layer1_size(),
+                subspaces: m,
+                codebook_size,
+                codebooks: Vec::new(),
+                codes: Vec::new(),
+            };
+        }
+
+        let mut codebooks: Vec<f32> = Vec::with_capacity(m * codebook_size * sub_dim);
+        let mut codes: Vec<u8> = vec![0; self.vocab_size() * m];
+        for subspace in 0..m {
+            let parts: Vec<f32> = (0..self.vocab_size())
+                .flat_map(|idx| {
+                    self.word_vector(idx)[subspace * sub_dim..(subspace + 1) * sub_dim].to_vec()
+                })
+                .collect();
+            let (centroids, assignments) =
+                kmeans(&parts, sub_dim, codebook_size, PQ_KMEANS_ITERATIONS);
+            codebooks.extend_from_slice(&centroids);
+            for (idx, code) in assignments.into_iter().enumerate() {
+                codes[idx * m + subspace] = code as u8;
+            }
+        }
+
+        ProductQuantizer {
+            layer1_size: self.layer1_size(),
+            subspaces: m,
+            codebook_size,
+            codebooks,
+            codes,
+        }
+    }
+}
+
+impl ProductQuantizer {
+    /// return the number of encoded words
+    pub fn vocab_size(&self) -> usize {
+        self.codes.len() / self.subspaces
+    }
+
+    /// @return the codebook vector for `code` in `subspace`
+    fn codebook_vector(&self, subspace: usize, code: u8) -> &[f32] {
+        let sub_dim = self.layer1_size / self.subspaces;
+        let start = (subspace * self.codebook_size + code as usize) * sub_dim;
+        &self.codebooks[start..start + sub_dim]
+    }
+
+    /// @return the approximation of the vector of the word with index `word_idx`
+    pub fn decode(&self, word_idx: usize) -> Vec<f32> {
+        let codes = &self.codes[word_idx * self.subspaces..(word_idx + 1) * self.subspaces];
+        codes
+            .iter()
+            .enumerate()
+            .flat_map(|(subspace, &code)| self.codebook_vector(subspace, code).to_vec())
+            .collect()
+    }
+
+    /// Encode all input vectors of `net` with the codebooks, e.g. to compress a model
+    /// trained further with codebooks learned before
+    pub fn encode(&mut self, net: &NeuralNet) {
+        assert!(net.layer1_size() == self.layer1_size);
+        let sub_dim = self.layer1_size / self.subspaces;
+        let codebook_len = self.codebook_size * sub_dim;
+        self.codes = (0..net.vocab_size())
+            .flat_map(|idx| {
+                let vec = net.word_vector(idx);
+                (0..self.subspaces)
+                    .map(|s| {
+                        let codebook = &self.codebooks[s * codebook_len..(s + 1) * codebook_len];
+                        nearest_centroid(&vec[s * sub_dim..(s + 1) * sub_dim], codebook) as u8
+                    })
+                    .collect::<Vec<u8>>()
+            })
+            .collect();
+    }
+
+    /// @return the mean squared euclidean distance between the input vectors of `net`
+    /// and their decoded approximations, the quantity minimized by the codebooks
+    pub fn distortion(&self, net: &NeuralNet) -> f64 {
+        let count = self.vocab_size().min(net.vocab_size());
+        if count == 0 {
+            return 0.0;
+        }
+        let total: f64 = (0..count)
+            .map(|idx| squared_distance(net.word_vector(idx), &self.decode(idx)) as f64)
+            .sum();
+        total / count as f64
+    }
+
+    /// @return a net holding the decoded vectors of all words, e.g. to run similarity
+    /// queries on a compressed model
+    pub fn to_net(&self) -> NeuralNet {
+        let syn0: Vec<f32> = (0..self.vocab_size())
+            .flat_map(|idx| self.decode(idx))
+            .collect();
+        NeuralNet::from_input_vectors(self.layer1_size, syn0)
+    }
+
+    /// Save the codebooks and codes: a "<vocab_size> <layer1_size> <subspaces>
+    /// <codebook_size>" header line, the codebooks as raw floats, then a
+    /// "<word> <codes>\n" record per word, `codes` being `subspaces` raw bytes
+    pub fn save_pq(&self, vocab: &Vocabulary, output_file_name: &str) -> std::io::Result<()> {
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(output_file_name)?);
+        writeln!(
+            buf_writer,
+            "{} {} {} {}",
+            self.vocab_size(),
+            self.layer1_size,
+            self.subspaces,
+            self.codebook_size
+        )?;
+        for f in &self.codebooks {
+            buf_writer.write_all(&f.to_ne_bytes())?;
+        }
+        for (idx, word) in vocab.into_iter().enumerate().take(self.vocab_size()) {
+            write!(buf_writer, "{word} ")?;
+            buf_writer.write_all(&self.codes[idx * self.subspaces..(idx + 1) * self.subspaces])?;
+            writeln!(buf_writer)?;
+        }
+        buf_writer.flush()
+    }
+
+    /// Load codebooks and codes saved by `save_pq`
+    pub fn load_pq(file_name: &str) -> std::io::Result<(Vocabulary, ProductQuantizer)> {
+        let mut reader = BufReader::new(File::open(file_name)?);
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let fields: Vec<usize> = header
+            .split_ascii_whitespace()
+            .map(|x| x.parse::<usize>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid header"))?;
+        let [vocab_size, layer1_size, subspaces, codebook_size] = fields[..] else {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid header"));
+        };
+        // only a quantizer without words has no codebooks
+        if subspaces == 0
+            || !layer1_size.is_multiple_of(subspaces)
+            || (codebook_size == 0 && vocab_size > 0)
+            || codebook_size > 256
+        {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid header"));
+        }
+
+        let mut data: Vec<u8> = vec![0; codebook_size * layer1_size * std::mem::size_of::<f32>()];
+        reader.read_exact(&mut data)?;
+        let codebooks: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|b| f32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        let mut words: Vec<String> = Vec::with_capacity(vocab_size);
+        let mut codes: Vec<u8> = vec![0; vocab_size * subspaces];
+        let mut word: Vec<u8> = vec![];
+        for word_codes in codes.chunks_exact_mut(subspaces) {
+            word.clear();
+            reader.read_until(b' ', &mut word)?;
+            // the previous codes are followed by a newline
+            let word = word.trim_ascii();
+            if word.is_empty() {
+                return Err(Error::new(ErrorKind::UnexpectedEof, "Missing word"));
+            }
+            words.push(String::from_utf8_lossy(word).into_owned());
+            reader.read_exact(word_codes)?;
+            if word_codes
+                .iter()
+                .any(|&code| code as usize >= codebook_size)
+            {
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid code"));
+            }
+        }
+
+        let vocab = Vocabulary::from_model_words(words)?;
+        Ok((
+            vocab,
+            ProductQuantizer {
+                layer1_size,
+                subspaces,
+                codebook_size,
+                codebooks,
+                codes,
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProductQuantizer;
+    use crate::nnet::NeuralNet;
+    use crate::test_util::{temp_path, vocab_of};
+    use crate::vocab::Vocabulary;
+
+    /// @return the indices of the `k` words closest to word `idx` by dot product
+    fn nearest(net: &NeuralNet, idx: usize, k: usize) -> Vec<usize> {
+        let dot = |other: usize| -> f32 {
+            net.word_vector(idx)
+                .iter()
+                .zip(net.word_vector(other))
+                .map(|(a, b)| a * b)
+                .sum()
+        };
+        let mut others: Vec<usize> = (0..net.vocab_size()).filter(|&o| o != idx).collect();
+        others.sort_by(|&a, &b| dot(b).total_cmp(&dot(a)));
+        others.truncate(k);
+        others
+    }
+
+    #[test]
+    fn decoded_vectors_approximate_the_originals() {
+        let net = NeuralNet::with_seed(200, 8, 1);
+        let pq = net.train_pq(4, 4);
+        assert_eq!(pq.vocab_size(), 200);
+
+        let mean_norm: f64 = (0..200)
+            .map(|idx| {
+                net.word_vector(idx)
+                    .iter()
+                    .map(|x| (x * x) as f64)
+                    .sum::<f64>()
+            })
+            .sum::<f64>()
+            / 200.0;
+        let distortion = pq.distortion(&net);
+        assert!(distortion < mean_norm / 4.0, "{distortion} {mean_norm}");
+
+        let decoded = pq.to_net();
+        let recall: usize = (0..200)
+            .map(|idx| {
+                let exact = nearest(&net, idx, 10);
+                nearest(&decoded, idx, 10)
+                    .iter()
+                    .filter(|n| exact.contains(n))
+                    .count()
+            })
+            .sum();
+        assert!(recall as f64 / 2000.0 > 0.5, "{recall}");
+    }
+
+    #[test]
+    fn codes_survive_save_and_load() {
+        let words: Vec<String> = (0..30).map(|i| format!("w{i}")).collect();
+        let counts: Vec<(&str, u32)> = words.iter().map(|w| (w.as_str(), 1)).collect();
+        let vocab = vocab_of(&counts);
+        let net = NeuralNet::with_seed(30, 4, 2);
+        let pq = net.train_pq(2, 3);
+        let file = temp_path("codes.pq");
+        pq.save_pq(&vocab, &file).unwrap();
+        let (loaded_vocab, loaded) = ProductQuantizer::load_pq(&file).unwrap();
+        assert_eq!(loaded_vocab.len(), 30);
+        assert_eq!(loaded_vocab.word(7), "w7");
+        for idx in 0..30 {
+            assert_eq!(loaded.decode(idx), pq.decode(idx));
+        }
+    }
+
+    #[test]
+    fn a_net_without_words_gives_an_empty_quantizer() {
+        let net = NeuralNet::from_input_vectors(4, Vec::new());
+        let pq = net.train_pq(2, 8);
+        assert_eq!(pq.vocab_size(), 0);
+        assert_eq!(pq.distortion(&net), 0.0);
+
+        let file = temp_path("empty.pq");
+        pq.save_pq(&Vocabulary::from_model_words(Vec::new()).unwrap(), &file)
+            .unwrap();
+        let (vocab, loaded) = ProductQuantizer::load_pq(&file).unwrap();
+        assert_eq!(vocab.len(), 0);
+        assert_eq!(loaded.vocab_size(), 0);
+    }
+}
@@ -0,0 +1,851 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use crate::linalg::squared_distance;
+use crate::nnet::NeuralNet;
+use crate::vocab::Vocabulary;
+
+/// maximum number of words whose silhouette is averaged by `silhouette_score`
+pub const SILHOUETTE_MAX_SAMPLES: usize = 2000;
+
+/// Question counts for a group of analogy questions
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AnalogyStats {
+    /// number of questions read
+    pub total: u64,
+    /// number of questions having all four words in the (restricted) vocabulary
+    pub answered: u64,
+    /// number of answered questions where the expected word ranked first
+    pub correct: u64,
+}
+
+impl AnalogyStats {
+    /// return the fraction of answered questions that were answered correctly
+    pub fn accuracy(&self) -> f64 {
+        if self.answered == 0 {
+            0.0
+        } else {
+            self.correct as f64 / self.answered as f64
+        }
+    }
+}
+
+/// Result of evaluating a model against a "questions-words" analogy file
+#[derive(Debug, Default, Clone)]
+pub struct AnalogyReport {
+    pub overall: AnalogyStats,
+    /// per section statistics, keyed by the section header without the leading ':'
+    pub sections: BTreeMap<String, AnalogyStats>,
+}
+
+/// How `NeuralNet::distance_matrix_flat` compares two vectors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// 1 - the cosine similarity, from 0 to 2
+    Cosine,
+    /// the euclidean (L2) distance
+    Euclidean,
+    /// the dot product, a similarity rather than a distance: larger means closer
+    Dot,
+}
+
+/// How the nearest neighbors of a word differ between two models, see
+/// `NeuralNet::neighbor_changes`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NeighborChanges {
+    /// neighbors in the other model only, best first
+    pub entered: Vec<String>,
+    /// neighbors in this model only, best first
+    pub left: Vec<String>,
+}
+
+/// @return the input vectors of the first `count` words, each scaled to unit length
+fn normalized_vectors(net: &NeuralNet, count: usize) -> Vec<f32> {
+    let mut rows: Vec<f32> = Vec::with_capacity(count * net.layer1_size());
+    for idx in 0..count {
+        let vec = net.word_vector(idx);
+        let len = vec.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if len > 0.0 {
+            rows.extend(vec.iter().map(|x| x / len));
+        } else {
+            rows.extend_from_slice(vec);
+        }
+    }
+    rows
+}
+
+/// Split a tagged token like "book|NOUN" into its word and tag at the last '|'
+/// @return (token, None) for tokens without a tag
+pub fn split_tag(token: &str) -> (&str, Option<&str>) {
+    match token.rsplit_once('|') {
+        Some((word, tag)) if !word.is_empty() => (word, Some(tag)),
+        _ => (token, None),
+    }
+}
+
+/// @return the cosine similarity of 2 f32 vectors, 0 if any of them is a zero vector
+fn cosine_similarity(vec1: &[f32], vec2: &[f32]) -> f32 {
+    let (mut dot, mut len1, mut len2) = (0.0f32, 0.0f32, 0.0f32);
+    for (a, b) in vec1.iter().zip(vec2) {
+        dot += a * b;
+        len1 += a * a;
+        len2 += b * b;
+    }
+    if len1 == 0.0 || len2 == 0.0 {
+        return 0.0;
+    }
+    dot / (len1.sqrt() * len2.sqrt())
+}
+
+impl NeuralNet {
+    /// @return up to `n` (word_index, similarity) pairs of the words whose input vectors
+    /// are most similar to `target`, best first; "</s>", the words in `exclude` and
+    /// those rejected by `accept` are skipped
+    fn nearest_words<F: Fn(usize) -> bool>(
+        &self,
+        target: &[f32],
+        n: usize,
+        exclude: &[usize],
+        accept: F,
+    ) -> Vec<(usize, f32)> {
+        self.nearest_rows(NeuralNet::word_vector, target, n, exclude, accept)
+    }
+
+    /// Same as `nearest_words`, comparing `target` with the vectors returned by `row`
+    fn nearest_rows<F: Fn(usize) -> bool>(
+        &self,
+        row: fn(&NeuralNet, usize) -> &[f32],
+        target: &[f32],
+        n: usize,
+        exclude: &[usize],
+        accept: F,
+    ) -> Vec<(usize, f32)> {
+        // kept sorted by decreasing similarity, like `bestd` in the C distance tool
+        let mut best: Vec<(usize, f32)> = Vec::with_capacity(n + 1);
+        if n == 0 {
+            return best;
+        }
+
+        for idx in 1..self.vocab_size() {
+            if exclude.contains(&idx) || !accept(idx) {
+                continue;
+            }
+            let sim = cosine_similarity(target, row(self, idx));
+            if best.len() == n && best[n - 1].1 >= sim {
+                continue;
+            }
+            let pos = best.partition_point(|&(_, s)| s >= sim);
+            best.insert(pos, (idx, sim));
+            best.truncate(n);
+        }
+        best
+    }
+
+    /// @return the `n` words most similar to `word` by cosine similarity, best first,
+    /// or an empty list if `word` is not in the vocabulary
+    pub fn most_similar(&self, vocab: &Vocabulary, word: &str, n: usize) -> Vec<(String, f32)> {
+        let idx = vocab.search_word(word);
+        if idx <= 0 || idx as usize >= self.vocab_size() {
+            return Vec::new();
+        }
+        let idx = idx as usize;
+        self.nearest_words(self.word_vector(idx), n, &[idx], |_| true)
+            .into_iter()
+            .map(|(i, sim)| (vocab.word(i).to_string(), sim))
+            .collect()
+    }
+
+    /// Same as `most_similar`, but ranking the output vectors (`syn1neg` rows) of the
+    /// words by their cosine similarity to the input vector of `word`. The dot product
+    /// of these vectors is what negative sampling trains to predict whether a word
+    /// occurs in the context of `word`, so the result lists likely context words
+    /// rather than words used in similar contexts. This is only meaningful for a net
+    /// trained in this process, model files don't store output vectors, an empty
+    /// list is returned for nets loaded from them.
+    pub fn most_similar_output(
+        &self,
+        vocab: &Vocabulary,
+        word: &str,
+        n: usize,
+    ) -> Vec<(String, f32)> {
+        let idx = vocab.search_word(word);
+        if idx <= 0 || idx as usize >= self.vocab_size() || !self.has_output_layer() {
+            return Vec::new();
+        }
+        let idx = idx as usize;
+        self.nearest_rows(
+            NeuralNet::output_vector,
+            self.word_vector(idx),
+            n,
+            &[idx],
+            |_| true,
+        )
+        .into_iter()
+        .map(|(i, sim)| (vocab.word(i).to_string(), sim))
+        .collect()
+    }
+
+    /// Compare the `k` nearest neighbors of `word` in this model with those in `other`,
+    /// e.g. a model trained on a later time slice of a corpus, to track how the
+    /// meaning of `word` drifts. Neighbors are matched by word, so the vocabularies
+    /// may differ.
+    /// @return the words that entered and left the neighborhood, None if `word` is
+    /// missing from any of the vocabularies
+    pub fn neighbor_changes(
+        &self,
+        vocab: &Vocabulary,
+        other: &NeuralNet,
+        other_vocab: &Vocabulary,
+        word: &str,
+        k: usize,
+    ) -> Option<NeighborChanges> {
+        if vocab.search_word(word) <= 0 || other_vocab.search_word(word) <= 0 {
+            return None;
+        }
+        let before: Vec<String> = self
+            .most_similar(vocab, word, k)
+            .into_iter()
+            .map(|(w, _)| w)
+            .collect();
+        let after: Vec<String> = other
+            .most_similar(other_vocab, word, k)
+            .into_iter()
+            .map(|(w, _)| w)
+            .collect();
+        Some(NeighborChanges {
+            entered: after
+                .iter()
+                .filter(|w| !before.contains(w))
+                .cloned()
+                .collect(),
+            left: before
+                .iter()
+                .filter(|w| !after.contains(w))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// Same as `most_similar`, but only words with a cosine similarity of at least
+    /// `min_similarity` are returned, so the result may hold fewer than `n` words
+    pub fn most_similar_above(
+        &self,
+        vocab: &Vocabulary,
+        word: &str,
+        n: usize,
+        min_similarity: f32,
+    ) -> Vec<(String, f32)> {
+        let mut result = self.most_similar(vocab, word, n);
+        // the list is sorted by decreasing similarity
+        let count = result.partition_point(|&(_, sim)| sim >= min_similarity);
+        result.truncate(count);
+        result
+    }
+
+    /// Same as `most_similar`, only returning words for which `predicate` returns
+    /// true, e.g. words of a given length or not in a blocklist. The words failing it
+    /// are skipped, so `n` words are returned as long as enough words pass.
+    pub fn most_similar_filtered<P: Fn(&str) -> bool>(
+        &self,
+        vocab: &Vocabulary,
+        word: &str,
+        n: usize,
+        predicate: P,
+    ) -> Vec<(String, f32)> {
+        let idx = vocab.search_word(word);
+        if idx <= 0 || idx as usize >= self.vocab_size() {
+            return Vec::new();
+        }
+        let idx = idx as usize;
+        self.nearest_words(self.word_vector(idx), n, &[idx], |i| {
+            predicate(vocab.word(i))
+        })
+        .into_iter()
+        .map(|(i, sim)| (vocab.word(i).to_string(), sim))
+        .collect()
+    }
+
+    /// Same as `most_similar`, for models trained on "word|TAG" tokens: only words
+    /// carrying the tag `tag` are returned. Use `split_tag` to strip the tag of the
+    /// results for display.
+    pub fn most_similar_with_tag(
+        &self,
+        vocab: &Vocabulary,
+        word: &str,
+        tag: &str,
+        n: usize,
+    ) -> Vec<(String, f32)> {
+        self.most_similar_filtered(vocab, word, n, |w| split_tag(w).1 == Some(tag))
+    }
+
+    /// Find the two distinct words with the most similar input vectors, e.g. to spot
+    /// duplicates. All pairs are compared, which costs O(vocab_size^2 * layer1_size);
+    /// the rows are processed in blocks that stay in the cache, so this remains
+    /// practical for vocabularies of up to a few 100k words.
+    /// @return (word_index1, word_index2, similarity) with word_index1 < word_index2,
+    /// or None if the net has less than 2 words besides "</s>"
+    pub fn closest_pair(&self) -> Option<(usize, usize, f32)> {
+        const BLOCK_ROWS: usize = 256;
+        let dim = self.layer1_size();
+        let rows = normalized_vectors(self, self.vocab_size());
+        let row = |idx: usize| &rows[idx * dim..(idx + 1) * dim];
+
+        let mut best: Option<(usize, usize, f32)> = None;
+        // skip word 0, the "</s>" sentence separator
+        for block_start in (1..self.vocab_size()).step_by(BLOCK_ROWS) {
+            let block_end = (block_start + BLOCK_ROWS).min(self.vocab_size());
+            for j in block_start + 1..self.vocab_size() {
+                for i in block_start..block_end.min(j) {
+                    let sim: f32 = row(i).iter().zip(row(j)).map(|(a, b)| a * b).sum();
+                    if best.is_none_or(|(_, _, s)| sim > s) {
+                        best = Some((i, j, sim));
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Measure how tight a group of words is, e.g. a cluster of a word classes run.
+    /// @return the mean cosine similarity of the input vectors over all pairs of
+    /// `word_indices`, or 0 if less than 2 indices are given
+    pub fn cluster_cohesion(&self, word_indices: &[usize]) -> f32 {
+        let mut sum: f64 = 0.0;
+        let mut pairs: u64 = 0;
+        for (i, &idx1) in word_indices.iter().enumerate() {
+            for &idx2 in &word_indices[i + 1..] {
+                sum += cosine_similarity(self.word_vector(idx1), self.word_vector(idx2)) as f64;
+                pairs += 1;
+            }
+        }
+        if pairs == 0 {
+            return 0.0;
+        }
+        (sum / pairs as f64) as f32
+    }
+
+    /// Compare the input vectors of all pairs of `word_indices` under `metric`, e.g. to
+    /// feed an external clustering library.
+    /// @return the N x N matrix for N indices as a flat row-major buffer: the entry
+    /// for `word_indices[i]` and `word_indices[j]` is at index i * N + j. The matrix is
+    /// symmetric, the diagonal compares each vector with itself.
+    pub fn distance_matrix_flat(&self, word_indices: &[usize], metric: Metric) -> Vec<f32> {
+        let n = word_indices.len();
+        let mut matrix: Vec<f32> = vec![0.0; n * n];
+        for (i, &idx1) in word_indices.iter().enumerate() {
+            let vec1 = self.word_vector(idx1);
+            for (j, &idx2) in word_indices.iter().enumerate().skip(i) {
+                let vec2 = self.word_vector(idx2);
+                let value = match metric {
+                    Metric::Cosine => 1.0 - cosine_similarity(vec1, vec2),
+                    Metric::Euclidean => squared_distance(vec1, vec2).sqrt(),
+                    Metric::Dot => vec1.iter().zip(vec2).map(|(a, b)| a * b).sum(),
+                };
+                matrix[i * n + j] = value;
+                matrix[j * n + i] = value;
+            }
+        }
+        matrix
+    }
+
+    /// Measure how well the words are clustered: word `i` belongs to cluster
+    /// `assignments[i]`, e.g. as given by a word classes run. The silhouette of a word
+    /// is (b - a) / max(a, b), where a is its mean cosine distance to the other words
+    /// of its cluster and b its mean distance to the words of the closest other
+    /// cluster, 0 for the only word of a cluster. For more than
+    /// `SILHOUETTE_MAX_SAMPLES` words the mean is taken over evenly spaced words, as
+    /// each one is compared to all others.
+    /// @return the mean silhouette between -1 and 1, 0 if less than 2 clusters have
+    /// words
+    pub fn silhouette_score(&self, assignments: &[usize]) -> f32 {
+        let count = assignments.len().min(self.vocab_size());
+        let num_clusters = assignments[..count].iter().max().map_or(0, |&c| c + 1);
+        let mut cluster_sizes: Vec<usize> = vec![0; num_clusters];
+        for &cluster in &assignments[..count] {
+            cluster_sizes[cluster] += 1;
+        }
+        if cluster_sizes.iter().filter(|&&size| size > 0).count() < 2 {
+            return 0.0;
+        }
+        let dim = self.layer1_size();
+        let rows = normalized_vectors(self, count);
+        let row = |idx: usize| &rows[idx * dim..(idx + 1) * dim];
+
+        let mut distance_sums: Vec<f64> = vec![0.0; num_clusters];
+        let mut total: f64 = 0.0;
+        let mut samples: usize = 0;
+        for i in (0..count).step_by(count.div_ceil(SILHOUETTE_MAX_SAMPLES)) {
+            distance_sums.fill(0.0);
+            for j in 0..count {
+                if j != i {
+                    let sim: f32 = row(i).iter().zip(row(j)).map(|(a, b)| a * b).sum();
+                    distance_sums[assignments[j]] += 1.0 - sim as f64;
+                }
+            }
+
+            samples += 1;
+            let own = assignments[i];
+            if cluster_sizes[own] < 2 {
+                continue;
+            }
+            let a = distance_sums[own] / (cluster_sizes[own] - 1) as f64;
+            let b = (0..num_clusters)
+                .filter(|&c| c != own && cluster_sizes[c] > 0)
+                .map(|c| distance_sums[c] / cluster_sizes[c] as f64)
+                .fold(f64::INFINITY, f64::min);
+            // b is finite as at least one other cluster isn't empty
+            if b.is_finite() && a.max(b) > 0.0 {
+                total += (b - a) / a.max(b);
+            }
+        }
+        (total / samples as f64) as f32
+    }
+
+    /// Estimate a vector for a word missing from the vocabulary by averaging the
+    /// input vectors of all vocabulary words sharing its first or its last
+    /// `affix_len` characters. A word found in the vocabulary gets its own vector.
+    /// @return None if `word` is shorter than `affix_len` or no word shares an affix
+    pub fn oov_vector(&self, vocab: &Vocabulary, word: &str, affix_len: usize) -> Option<Vec<f32>> {
+        let idx = vocab.search_word(word);
+        if idx > 0 {
+            return Some(self.word_vector(idx as usize).to_vec());
+        }
+
+        if affix_len == 0 || word.chars().count() < affix_len {
+            return None;
+        }
+        let prefix_end = word
+            .char_indices()
+            .nth(affix_len)
+            .map_or(word.len(), |(pos, _)| pos);
+        let suffix_start = word
+            .char_indices()
+            .rev()
+            .nth(affix_len - 1)
+            .map_or(0, |(pos, _)| pos);
+        let (prefix, suffix) = (&word[..prefix_end], &word[suffix_start..]);
+
+        let mut sum: Vec<f32> = vec![0.0; self.layer1_size()];
+        let mut count: usize = 0;
+        // skip word 0, the "</s>" sentence separator
+        for idx in 1..vocab.len().min(self.vocab_size()) {
+            let w = vocab.word(idx);
+            if w.starts_with(prefix) || w.ends_with(suffix) {
+                for (s, v) in sum.iter_mut().zip(self.word_vector(idx)) {
+                    *s += v;
+                }
+                count += 1;
+            }
+        }
+
+        if count == 0 {
+            return None;
+        }
+        sum.iter_mut().for_each(|s| *s /= count as f32);
+        Some(sum)
+    }
+
+    /// Answer an analogy question like "king - man + woman" by picking one of
+    /// `candidates`, e.g. the choices of a multiple-choice test. The unit length input
+    /// vectors of `positive` are added and those of `negative` are subtracted, then
+    /// the candidate most similar to the result by cosine similarity wins.
+    /// Candidates missing from the vocabulary are skipped.
+    /// @return the best candidate and its similarity, or None if a word of `positive`
+    /// or `negative` is missing from the vocabulary or no candidate is found
+    pub fn analogy_among(
+        &self,
+        vocab: &Vocabulary,
+        positive: &[&str],
+        negative: &[&str],
+        candidates: &[&str],
+    ) -> Option<(String, f32)> {
+        let lookup = |word: &str| {
+            let idx = vocab.search_word(word);
+            (idx > 0 && (idx as usize) < self.vocab_size()).then_some(idx as usize)
+        };
+
+        let mut target: Vec<f32> = vec![0.0; self.layer1_size()];
+        for (words, sign) in [(positive, 1.0f32), (negative, -1.0f32)] {
+            for word in words {
+                let vec = self.word_vector(lookup(word)?);
+                let len = vec.iter().map(|x| x * x).sum::<f32>().sqrt();
+                if len > 0.0 {
+                    for (t, v) in target.iter_mut().zip(vec) {
+                        *t += sign * v / len;
+                    }
+                }
+            }
+        }
+
+        let mut best: Option<(&str, f32)> = None;
+        for &candidate in candidates {
+            let Some(idx) = lookup(candidate) else {
+                continue;
+            };
+            let sim = cosine_similarity(&target, self.word_vector(idx));
+            if best.is_none_or(|(_, s)| sim > s) {
+                best = Some((candidate, sim));
+            }
+        }
+        best.map(|(word, sim)| (word.to_string(), sim))
+    }
+
+    /// Evaluate the model on a word analogy file in the format of Google's
+    /// `questions-words.txt`: lines starting with ':' begin a new section, every
+    /// other line holds a question "a b c d" meaning "a is to b as c is to d".
+    ///
+    /// As in the C `compute-accuracy` tool, words are compared case-insensitively
+    /// and only the `restrict_vocab` most frequent words are considered, both for
+    /// looking up question words and as candidate answers.
+    pub fn evaluate_analogies(
+        &self,
+        vocab: &Vocabulary,
+        questions_file: &str,
+        restrict_vocab: usize,
+    ) -> std::io::Result<AnalogyReport> {
+        let restrict_vocab = restrict_vocab.min(vocab.len()).min(self.vocab_size());
+        let dim = self.layer1_size();
+        let rows = normalized_vectors(self, restrict_vocab);
+
+        // lowercased word -> index, the most frequent spelling wins
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for idx in 1..restrict_vocab {
+            index.entry(vocab.word(idx).to_lowercase()).or_insert(idx);
+        }
+
+        let mut report = AnalogyReport::default();
+        let mut section = String::new();
+        let mut target: Vec<f32> = vec![0.0; dim];
+
+        for line in BufReader::new(File::open(questions_file)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if let Some(header) = line.strip_prefix(':') {
+                section = header.trim().to_string();
+                report.sections.entry(section.clone()).or_default();
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() != 4 {
+                continue;
+            }
+
+            let section_stats = report.sections.entry(section.clone()).or_default();
+            section_stats.total += 1;
+            report.overall.total += 1;
+
+            let ids: Vec<usize> = words
+                .iter()
+                .filter_map(|w| index.get(&w.to_lowercase()).copied())
+                .collect();
+            if ids.len() != 4 {
+                continue;
+            }
+            section_stats.answered += 1;
+            report.overall.answered += 1;
+
+            // target = b - a + c
+            let row = |idx: usize| &rows[idx * dim..(idx + 1) * dim];
+            for (i, t) in target.iter_mut().enumerate() {
+                *t = row(ids[1])[i] - row(ids[0])[i] + row(ids[2])[i];
+            }
+
+            let mut best: Option<(usize, f32)> = None;
+            for idx in 1..restrict_vocab {
+                if ids[..3].contains(&idx) {
+                    continue;
+                }
+                let sim: f32 = row(idx).iter().zip(&target).map(|(a, b)| a * b).sum();
+                if best.is_none_or(|(_, s)| sim > s) {
+                    best = Some((idx, sim));
+                }
+            }
+
+            if best.is_some_and(|(idx, _)| idx == ids[3]) {
+                section_stats.correct += 1;
+                report.overall.correct += 1;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::test_util::{
+        fresh_net, learn_vocab, net_of, train, training_params, vocab_of, write_temp_file,
+        write_two_topic_corpus,
+    };
+
+    /// @return 2D vectors of fruits around (1, 0) and vehicles around (0, 1)
+    fn fruits_and_vehicles() -> (Vocabulary, NeuralNet) {
+        let vocab = vocab_of(&[
+            ("</s>", 0),
+            ("apple", 5),
+            ("pear", 4),
+            ("banana", 3),
+            ("car", 2),
+            ("truck", 1),
+        ]);
+        let net = net_of(&[
+            &[0.0, 0.0],
+            &[1.0, 0.0],
+            &[1.0, 0.2],
+            &[0.8, 0.6],
+            &[0.0, 1.0],
+            &[-0.2, 1.0],
+        ]);
+        (vocab, net)
+    }
+
+    #[test]
+    fn analogy_sections_are_counted_separately() {
+        let vocab = vocab_of(&[
+            ("</s>", 0),
+            ("man", 5),
+            ("woman", 4),
+            ("king", 3),
+            ("queen", 2),
+            ("apple", 1),
+        ]);
+        let net = net_of(&[
+            &[0.0, 0.0, 0.0, 1.0],
+            &[1.0, 0.0, 0.0, 0.0],
+            &[0.0, 1.0, 0.0, 0.0],
+            &[1.0, 0.0, 1.0, 0.0],
+            &[0.0, 1.0, 1.0, 0.0],
+            &[0.0, 0.0, 0.0, 1.0],
+        ]);
+        let questions = write_temp_file(
+            "analogy-sections.txt",
+            ": family\nman woman king queen\n: capitals\nparis france berlin germany\n",
+        );
+
+        let report = net.evaluate_analogies(&vocab, &questions, 100).unwrap();
+        let family = &report.sections["family"];
+        assert_eq!((family.total, family.answered, family.correct), (1, 1, 1));
+        let capitals = &report.sections["capitals"];
+        assert_eq!(
+            (capitals.total, capitals.answered, capitals.correct),
+            (1, 0, 0)
+        );
+        let overall = &report.overall;
+        assert_eq!(
+            (overall.total, overall.answered, overall.correct),
+            (2, 1, 1)
+        );
+    }
+
+    #[test]
+    fn oov_vector_averages_words_sharing_an_affix() {
+        let vocab = vocab_of(&[("</s>", 0), ("running", 2), ("runner", 1), ("table", 1)]);
+        let net = net_of(&[&[0.0, 0.0], &[1.0, 0.0], &[0.0, 1.0], &[-1.0, -1.0]]);
+
+        // shares "run" with "running" and "runner"
+        let vector = net.oov_vector(&vocab, "runs", 3).unwrap();
+        assert_eq!(vector, vec![0.5, 0.5]);
+        assert!(vector.iter().any(|&x| x != 0.0));
+        assert_eq!(net.oov_vector(&vocab, "xyzzy", 3), None);
+    }
+
+    #[test]
+    fn most_similar_with_tag_only_returns_that_tag() {
+        let vocab = vocab_of(&[
+            ("</s>", 0),
+            ("book|NOUN", 4),
+            ("read|VERB", 3),
+            ("novel|NOUN", 2),
+            ("write|VERB", 1),
+        ]);
+        let net = net_of(&[
+            &[0.0, 0.0],
+            &[1.0, 0.0],
+            &[1.0, 0.1],
+            &[1.0, 0.3],
+            &[1.0, 0.5],
+        ]);
+
+        let nouns = net.most_similar_with_tag(&vocab, "book|NOUN", "NOUN", 5);
+        let words: Vec<&str> = nouns.iter().map(|(w, _)| split_tag(w).0).collect();
+        assert_eq!(words, vec!["novel"]);
+        let verbs = net.most_similar_with_tag(&vocab, "book|NOUN", "VERB", 5);
+        let words: Vec<&str> = verbs.iter().map(|(w, _)| w.as_str()).collect();
+        assert_eq!(words, vec!["read|VERB", "write|VERB"]);
+    }
+
+    #[test]
+    fn most_similar_above_drops_the_less_similar_neighbors() {
+        let (vocab, net) = fruits_and_vehicles();
+        let words = |min_similarity: f32| -> Vec<String> {
+            net.most_similar_above(&vocab, "apple", 4, min_similarity)
+                .into_iter()
+                .map(|(w, _)| w)
+                .collect()
+        };
+        assert!(words(0.99).is_empty());
+        assert_eq!(words(0.9), vec!["pear"]);
+        assert_eq!(words(-1.0), vec!["pear", "banana", "car", "truck"]);
+    }
+
+    #[test]
+    fn identical_rows_are_the_closest_pair() {
+        let net = net_of(&[
+            &[0.0, 0.0, 0.0],
+            &[1.0, 0.0, 0.0],
+            &[0.3, -0.5, 0.8],
+            &[0.9, 0.1, 0.0],
+            &[0.3, -0.5, 0.8],
+        ]);
+        let (i, j, similarity) = net.closest_pair().unwrap();
+        assert_eq!((i, j), (2, 4));
+        assert!((similarity - 1.0).abs() < 1e-6);
+        assert_eq!(net_of(&[&[0.0], &[1.0]]).closest_pair(), None);
+    }
+
+    #[test]
+    fn analogy_among_only_ranks_the_candidates() {
+        let (vocab, net) = fruits_and_vehicles();
+        let (positive, negative) = (["pear", "car"], ["apple"]);
+        let best = net.analogy_among(&vocab, &positive, &negative, &["apple", "banana"]);
+        let (word, similarity) = best.unwrap();
+        assert_eq!(word, "banana");
+        // "truck" scores higher but is not a candidate
+        let truck = net.analogy_among(&vocab, &positive, &negative, &["truck", "banana"]);
+        let (word, truck_similarity) = truck.unwrap();
+        assert_eq!(word, "truck");
+        assert!(truck_similarity > similarity);
+        assert_eq!(
+            net.analogy_among(&vocab, &["unknown"], &negative, &["banana"]),
+            None
+        );
+    }
+
+    #[test]
+    fn cohesion_of_tight_and_scattered_clusters() {
+        let (_, net) = fruits_and_vehicles();
+        let tight = net.cluster_cohesion(&[1, 2]);
+        assert!(tight > 0.95);
+        let scattered = net.cluster_cohesion(&[1, 3, 5]);
+        assert!(scattered < 0.5);
+        assert_eq!(net.cluster_cohesion(&[1]), 0.0);
+    }
+
+    #[test]
+    fn most_similar_output_ranks_the_output_vectors() {
+        let file = write_two_topic_corpus("most-similar-output.txt", 500);
+        let vocab = learn_vocab(&file);
+        let params = training_params(&file);
+        let net = Arc::new(fresh_net(&vocab, &params));
+        train(&net, &vocab, &params);
+
+        let output = net.most_similar_output(&vocab, "cat", 5);
+        assert_eq!(output.len(), 5);
+        assert!(output.iter().all(|(_, sim)| sim.is_finite()));
+        assert!(output.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_ne!(output, net.most_similar(&vocab, "cat", 5));
+    }
+
+    #[test]
+    fn silhouette_is_high_for_good_clusters_only() {
+        let (_, net) = fruits_and_vehicles();
+        let good = net.silhouette_score(&[0, 0, 0, 0, 1, 1]);
+        assert!(good > 0.5, "{good}");
+        let scrambled = net.silhouette_score(&[0, 1, 0, 1, 0, 1]);
+        assert!(scrambled < 0.1, "{scrambled}");
+        // a single non-empty cluster, whatever its index
+        assert_eq!(net.silhouette_score(&[1, 1, 1, 1, 1, 1]), 0.0);
+        assert_eq!(net.silhouette_score(&[]), 0.0);
+    }
+
+    #[test]
+    fn neighbor_changes_report_the_shifted_neighbors() {
+        let (vocab, before) = fruits_and_vehicles();
+        // banana moves next to the vehicles
+        let after = net_of(&[
+            &[0.0, 0.0],
+            &[1.0, 0.0],
+            &[1.0, 0.2],
+            &[-0.1, 1.0],
+            &[0.0, 1.0],
+            &[-0.2, 1.0],
+        ]);
+        let changes = before
+            .neighbor_changes(&vocab, &after, &vocab, "pear", 2)
+            .unwrap();
+        assert_eq!(changes.entered, ["car"]);
+        assert_eq!(changes.left, ["banana"]);
+
+        let unchanged = before
+            .neighbor_changes(&vocab, &before, &vocab, "pear", 2)
+            .unwrap();
+        assert_eq!(unchanged, NeighborChanges::default());
+        assert!(
+            before
+                .neighbor_changes(&vocab, &after, &vocab, "plum", 2)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn filtered_neighbors_skip_the_excluded_words() {
+        let (vocab, net) = fruits_and_vehicles();
+        let best = net.most_similar(&vocab, "apple", 2);
+        assert_eq!(best[0].0, "pear");
+
+        let filtered = net.most_similar_filtered(&vocab, "apple", 2, |w| w != "pear");
+        let words: Vec<&str> = filtered.iter().map(|(w, _)| w.as_str()).collect();
+        assert_eq!(words, ["banana", "car"]);
+        assert_eq!(filtered[0], best[1]);
+    }
+
+    #[test]
+    fn distance_matrix_matches_the_pairwise_distances() {
+        let net = net_of(&[&[0.0, 0.0], &[3.0, 0.0], &[0.0, 4.0], &[3.0, 4.0]]);
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-6);
+        let indices = [1, 2, 3];
+
+        let euclidean = net.distance_matrix_flat(&indices, Metric::Euclidean);
+        assert!(close(
+            &euclidean,
+            &[0.0, 5.0, 4.0, 5.0, 0.0, 3.0, 4.0, 3.0, 0.0]
+        ));
+        let dot = net.distance_matrix_flat(&indices, Metric::Dot);
+        assert!(close(
+            &dot,
+            &[9.0, 0.0, 9.0, 0.0, 16.0, 16.0, 9.0, 16.0, 25.0]
+        ));
+        let cosine = net.distance_matrix_flat(&indices, Metric::Cosine);
+        assert!(close(
+            &cosine,
+            &[0.0, 1.0, 0.4, 1.0, 0.0, 0.2, 0.4, 0.2, 0.0]
+        ));
+
+        for matrix in [euclidean, dot, cosine] {
+            for i in 0..3 {
+                for j in 0..3 {
+                    assert_eq!(matrix[i * 3 + j], matrix[j * 3 + i]);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+
+use crate::nnet::LcRandomGen;
+
+/// random number stream of the shuffle, distinct from the training streams
+const SHUFFLE_STREAM: u64 = 3;
+
+/// Approximately shuffles the items of `source` in constant memory, like the
+/// `shuffle(buffer_size)` of TensorFlow datasets: the buffer is filled with the first
+/// `buffer_size` items, then each step yields a random item of the buffer and replaces
+/// it with the next item of `source`. Every item is yielded exactly once, an item
+/// moves at most `buffer_size` positions towards the start, a buffer holding all
+/// items gives a uniform shuffle.
+pub struct ShuffleBuffer<I: Iterator> {
+    source: I,
+    buffer: Vec<I::Item>,
+    buffer_size: usize,
+    rand_gen: LcRandomGen,
+}
+
+impl<I: Iterator> ShuffleBuffer<I> {
+    /// Construct a ShuffleBuffer of `buffer_size` items, the same `seed` gives the same
+    /// order
+    pub fn new(source: I, buffer_size: usize, seed: u64) -> ShuffleBuffer<I> {
+        assert!(
+            buffer_size > 0,
+            "the shuffle buffer must hold at least one item"
+        );
+        ShuffleBuffer {
+            source,
+            buffer: Vec::with_capacity(buffer_size),
+            buffer_size,
+            rand_gen: LcRandomGen::for_stream(seed, SHUFFLE_STREAM),
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for ShuffleBuffer<I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.len() < self.buffer_size {
+            let Some(item) = self.source.next() else {
+                break;
+            };
+            self.buffer.push(item);
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+        // the low bits of the generator are poorly distributed
+        let idx = (self.rand_gen.next_rand() as u64 >> 16) as usize % self.buffer.len();
+        Some(self.buffer.swap_remove(idx))
+    }
+}
+
+/// Write the lines (sentences) of `input_file` to `output_file` in the order given by
+/// a `ShuffleBuffer` of `buffer_size` lines, for corpora too large to shuffle in
+/// memory. Every output line ends with `\n`, including the last one when the input
+/// doesn't end with a newline.
+pub fn shuffle_lines(
+    input_file: &str,
+    output_file: &str,
+    buffer_size: usize,
+    seed: u64,
+) -> std::io::Result<()> {
+    let reader = BufReader::new(File::open(input_file)?);
+    let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(output_file)?);
+    for line in ShuffleBuffer::new(reader.split(b'\n'), buffer_size, seed) {
+        buf_writer.write_all(&line?)?;
+        buf_writer.write_all(b"\n")?;
+    }
+    buf_writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{temp_path, write_temp_file};
+
+    #[test]
+    fn a_fixed_seed_gives_the_same_near_shuffle() {
+        let shuffled = |seed| ShuffleBuffer::new(0..100, 10, seed).collect::<Vec<_>>();
+        let first = shuffled(5);
+        assert_eq!(first, shuffled(5));
+        assert_ne!(first, shuffled(6));
+        assert_ne!(first, (0..100).collect::<Vec<_>>());
+
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..100).collect::<Vec<_>>());
+        // an item moves at most buffer_size positions towards the start
+        assert!(
+            first
+                .iter()
+                .enumerate()
+                .all(|(pos, &item)| pos + 10 >= item)
+        );
+    }
+
+    #[test]
+    fn every_shuffled_line_ends_with_a_newline() {
+        let input = write_temp_file("shuffle-input.txt", "one\ntwo\nthree\nfour");
+        let output = temp_path("shuffle-output.txt");
+        shuffle_lines(&input, &output, 2, 1).unwrap();
+        let shuffled = std::fs::read_to_string(&output).unwrap();
+        assert!(shuffled.ends_with('\n'));
+        let mut lines: Vec<&str> = shuffled.lines().collect();
+        lines.sort();
+        assert_eq!(lines, ["four", "one", "three", "two"]);
+    }
+}
@@ -0,0 +1,108 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+/// A count-min sketch: approximate counts of a stream of words in fixed memory. The
+/// estimate of a word is never below its true count and exceeds it by at most
+/// `error_bound()` with probability 1 - e^-depth.
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    /// `depth` rows of `width` counters
+    counters: Vec<u32>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    /// Construct a sketch of `depth` rows of `width` counters, taking
+    /// 4 * width * depth bytes
+    pub fn new(width: usize, depth: usize) -> CountMinSketch {
+        assert!(width > 0 && depth > 0);
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+            total: 0,
+        }
+    }
+
+    /// @return the index of the counter of `word` in row `row`
+    fn counter_index(&self, word: &str, row: usize) -> usize {
+        let mut hasher = DefaultHasher::new();
+        row.hash(&mut hasher);
+        word.hash(&mut hasher);
+        row * self.width + (hasher.finish() % self.width as u64) as usize
+    }
+
+    /// Count one occurrence of `word`
+    /// @return the new estimate of the count of `word`
+    pub fn add(&mut self, word: &str) -> u32 {
+        self.total += 1;
+        let mut estimate = u32::MAX;
+        for row in 0..self.depth {
+            let idx = self.counter_index(word, row);
+            self.counters[idx] = self.counters[idx].saturating_add(1);
+            estimate = estimate.min(self.counters[idx]);
+        }
+        estimate
+    }
+
+    /// @return the estimated count of `word`
+    pub fn estimate(&self, word: &str) -> u32 {
+        (0..self.depth)
+            .map(|row| self.counters[self.counter_index(word, row)])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// @return the number of words counted so far
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// @return e * total() / width, the overestimate that an estimate exceeds with a
+    /// probability of at most e^-depth
+    pub fn error_bound(&self) -> f64 {
+        std::f64::consts::E * self.total as f64 / self.width as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_stay_within_the_error_bound() {
+        let mut sketch = CountMinSketch::new(64, 5);
+        // word i occurs 1000 / (i + 1) times
+        let counts: Vec<(String, u32)> = (0..200)
+            .map(|i| (format!("w{i}"), 1000 / (i + 1)))
+            .collect();
+        for (word, count) in &counts {
+            for _ in 0..*count {
+                sketch.add(word);
+            }
+        }
+        assert_eq!(
+            sketch.total(),
+            counts.iter().map(|(_, c)| *c as u64).sum::<u64>()
+        );
+        for (word, count) in &counts {
+            let estimate = sketch.estimate(word);
+            assert!(estimate >= *count);
+            assert!((estimate - count) as f64 <= sketch.error_bound(), "{word}");
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//  Copyright 2026 Robert Zavalczki
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//      http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::sync::Arc;
+
+use crate::nnet::{
+    Architecture, LcRandomGen, NeuralNet, TrainigParams, TrainigProgress, train_model,
+};
+use crate::tokenizer::TokenizerParams;
+use crate::vocab::{VocabParams, Vocabulary};
+
+/// @return a path in the temporary directory that is unique to this test process
+pub fn temp_path(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("word2vec-rust-{}-{name}", std::process::id()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// write `contents` to `temp_path(name)`
+/// @return the path of the file
+pub fn write_temp_file(name: &str, contents: &str) -> String {
+    let path = temp_path(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+/// @return a vocabulary of the given (word, count) pairs, in that order
+pub fn vocab_of(words: &[(&str, u32)]) -> Vocabulary {
+    Vocabulary::from_word_counts(words.iter().map(|&(w, c)| (w.to_string(), c)).collect()).unwrap()
+}
+
+/// @return a net without output layer whose input vectors are `rows`
+pub fn net_of(rows: &[&[f32]]) -> NeuralNet {
+    NeuralNet::from_input_vectors(rows[0].len(), rows.concat())
+}
+
+/// the parameters for learning the vocabulary of a small test corpus
+pub fn vocab_params() -> VocabParams {
+    VocabParams {
+        min_count: 1,
+        estimated_words: Some(1000),
+        ..Default::default()
+    }
+}
+
+/// @return the vocabulary of `file` learned with `vocab_params`
+pub fn learn_vocab(file: &str) -> Vocabulary {
+    Vocabulary::learn_vocabulary_with_params(file, &vocab_params()).unwrap()
+}
+
+/// the words of the two topics of `write_two_topic_corpus`
+pub const TOPIC_WORDS: [[&str; 6]; 2] = [
+    ["cat", "dog", "pet", "fur", "paw", "tail"],
+    ["car", "road", "wheel", "drive", "engine", "fuel"],
+];
+
+/// Write `sentences` sentences of 8 words to `temp_path(name)`, alternating between
+/// the two topics of `TOPIC_WORDS`, the words of a sentence being drawn from its topic
+/// by a fixed random sequence
+/// @return the path of the file
+pub fn write_two_topic_corpus(name: &str, sentences: usize) -> String {
+    let mut rand_gen = LcRandomGen::new(1);
+    let mut contents = String::new();
+    for sentence in 0..sentences {
+        let topic = &TOPIC_WORDS[sentence % 2];
+        let words: Vec<&str> = (0..8)
+            .map(|_| topic[(rand_gen.next_rand() >> 16) as usize % topic.len()])
+            .collect();
+        contents.push_str(&words.join(" "));
+        contents.push('\n');
+    }
+    write_temp_file(name, &contents)
+}
+
+/// @return the parameters for training small nets on `file` with 1 thread
+pub fn training_params(file: &str) -> TrainigParams {
+    TrainigParams {
+        training_file: file.to_string(),
+        training_file_size: std::fs::metadata(file).unwrap().len(),
+        vocab_file: String::new(),
+        continue_from: None,
+        resume_from: None,
+        save_vocab_file: String::new(),
+        output_file: String::new(),
+        vectors_size: 10,
+        architecture: Architecture::Cbow,
+        window: 3,
+        total_iter: 3,
+        negative_samples: 5,
+        num_threads: 1,
+        starting_alpha: 0.05,
+        debug_mode: 0,
+        min_count: 1,
+        binary: false,
+        save_counts: false,
+        span_sentences: false,
+        skip_sentences: 0,
+        probe_words: Vec::new(),
+        probe_every: 0,
+        progress_every: 0,
+        tokenizer: TokenizerParams::default(),
+        seed: 0,
+        normalize_context: false,
+        gradient_histogram: false,
+        f64_gradients: false,
+        decay: true,
+        output_bias: false,
+        reverse_odd_epochs: false,
+        loss_log_path: None,
+        validation_file: None,
+        patience: 1,
+        best_checkpoint_file: None,
+        deterministic: false,
+        renorm_every: None,
+        warmup_words: 0,
+        min_alpha: None,
+        adam: false,
+        adagrad: false,
+        max_grad: None,
+        keep_probabilities: None,
+        sample: 0.0,
+        banded_negatives: false,
+        record_alpha: false,
+        snapshot_prefix: None,
+        snapshot_every: 0,
+        snapshot_words: 0,
+        checkpoint_file: None,
+        checkpoint_every: 0,
+        hierarchical_softmax: false,
+        lock_free: true,
+        check_singletons: false,
+    }
+}
+
+/// @return a fresh net for `vocab` with the vector size of `params`
+pub fn fresh_net(vocab: &Vocabulary, params: &TrainigParams) -> NeuralNet {
+    NeuralNet::with_seed(vocab.len(), params.vectors_size, params.seed)
+}
+
+/// train `net` on `vocab` with `params`
+/// @return the training progress
+pub fn train(net: &Arc<NeuralNet>, vocab: &Vocabulary, params: &TrainigParams) -> TrainigProgress {
+    let progress = TrainigProgress::new();
+    train_model(
+        net,
+        vocab,
+        params,
+        &progress,
+        &mut |_, _: &TrainigProgress| {},
+    )
+    .unwrap();
+    progress
+}
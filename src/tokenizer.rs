@@ -24,11 +24,148 @@ fn is_doc_separator(byte: &u8) -> bool {
     *byte == b'\n'
 }
 
+/// Options controlling how a file is split into sentences. By default each
+/// `\n`-terminated line is exactly one sentence, ended by a "</s>" token.
+#[derive(Clone, Debug, Default)]
+pub struct TokenizerParams {
+    /// also end a last line not terminated by `\n` with a "</s>" token
+    pub terminate_last_line: bool,
+    /// emit a single "</s>" for a run of line ends, so that blank lines don't make
+    /// empty sentences; this also drops the "</s>" of blank lines at the start
+    pub collapse_separators: bool,
+    /// emit no "</s>" for lines made only of spaces, tabs or carriage returns, like
+    /// malformed records in TSV files; lines without any character are kept
+    pub skip_whitespace_lines: bool,
+    /// lowercase the first token of each sentence, so that a capitalized word starting
+    /// a sentence maps to the same word as elsewhere, while capitalized words in the
+    /// middle of a sentence (like names) stay distinct
+    pub lowercase_sentence_start: bool,
+    /// tokens are "word:weight" pairs, e.g. "house:0.85" for a word read with 85%
+    /// confidence by OCR. The weight follows the last ':' and must be a non-negative
+    /// number, tokens without such a suffix keep the whole token as word and have
+    /// weight 1. The weights are returned by `FileTokenIterator::read_weighted_token`
+    /// and dropped by the other functions, which only return the words. Training
+    /// scales the updates of each example by the weight of its center word, and the
+    /// update of each context vector also by the weight of its context word.
+    pub token_weights: bool,
+}
+
+/// Decides which line ends become "</s>" tokens, as configured by `TokenizerParams`
+#[derive(Clone, Debug)]
+struct SentenceFilter {
+    params: TokenizerParams,
+    /// true while the current line has tokens not yet followed by a "</s>"
+    line_open: bool,
+    /// true if the current line has separators other than the line end
+    line_has_whitespace: bool,
+}
+
+impl SentenceFilter {
+    fn new(params: &TokenizerParams) -> SentenceFilter {
+        SentenceFilter {
+            params: params.clone(),
+            line_open: false,
+            line_has_whitespace: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.line_open = false;
+        self.line_has_whitespace = false;
+    }
+
+    /// a token (word) was read
+    /// @return true if the token should be lowercased
+    fn token(&mut self) -> bool {
+        let sentence_start = !self.line_open;
+        self.line_open = true;
+        sentence_start && self.params.lowercase_sentence_start
+    }
+
+    /// a separator other than the line end was read
+    fn whitespace(&mut self) {
+        self.line_has_whitespace = true;
+    }
+
+    /// a line end was read
+    /// @return true if a "</s>" should be emitted for it
+    fn line_end(&mut self) -> bool {
+        let whitespace_only = self.line_has_whitespace && !self.line_open;
+        self.line_has_whitespace = false;
+        if !self.line_open
+            && (self.params.collapse_separators
+                || (self.params.skip_whitespace_lines && whitespace_only))
+        {
+            return false;
+        }
+        self.line_open = false;
+        true
+    }
+
+    /// the end of the file was reached
+    /// @return true if a "</s>" should be emitted for an unterminated last line
+    fn file_end(&mut self) -> bool {
+        let result = self.params.terminate_last_line && self.line_open;
+        self.reset();
+        result
+    }
+}
+
+/// Split a "word:weight" token, see `TokenizerParams::token_weights`
+/// @return the word and its weight, None if the token has no valid weight suffix
+fn split_token_weight(token: &[u8]) -> Option<(&[u8], f32)> {
+    let pos = token
+        .iter()
+        .rposition(|&b| b == b':')
+        .filter(|&pos| pos > 0)?;
+    let weight: f32 = std::str::from_utf8(&token[pos + 1..]).ok()?.parse().ok()?;
+    if weight.is_finite() && weight >= 0.0 {
+        Some((&token[..pos], weight))
+    } else {
+        None
+    }
+}
+
+/// Invoke `token_callback` for `token`, without its weight suffix if `params` has
+/// `token_weights` set, lowercased if `lowercase` is true and the token is valid UTF-8
+fn emit_token<T: FnMut(&[u8])>(
+    token: &[u8],
+    lowercase: bool,
+    params: &TokenizerParams,
+    token_callback: &mut T,
+) {
+    let token = if params.token_weights
+        && let Some((word, _)) = split_token_weight(token)
+    {
+        word
+    } else {
+        token
+    };
+    if lowercase
+        && let Ok(word) = std::str::from_utf8(token)
+        && word.chars().any(char::is_uppercase)
+    {
+        token_callback(word.to_lowercase().as_bytes());
+    } else {
+        token_callback(token);
+    }
+}
+
 /// Read file and invoke `token_callback` for each token (word)
 pub fn read_file_by_tokens<T: FnMut(&[u8])>(
     file_name: &str,
+    token_callback: T,
+) -> std::io::Result<()> {
+    read_file_by_tokens_with_params(file_name, &TokenizerParams::default(), token_callback)
+}
+
+/// Same as `read_file_by_tokens`, splitting sentences as configured by `params`
+pub fn read_file_by_tokens_with_params<T: FnMut(&[u8])>(
+    file_name: &str,
+    params: &TokenizerParams,
     mut token_callback: T,
 ) -> std::io::Result<()> {
+    let mut filter = SentenceFilter::new(params);
     let mut buf_reader: BufReader<File> = BufReader::new(File::open(file_name)?);
     let mut rest: Vec<u8> = Vec::new();
 
@@ -36,7 +173,7 @@ pub fn read_file_by_tokens<T: FnMut(&[u8])>(
         let data: &[u8] = buf_reader.fill_buf()?;
         if data.is_empty() {
             if !rest.is_empty() {
-                token_callback(&rest);
+                emit_token(&rest, filter.token(), params, &mut token_callback);
             }
             break;
         }
@@ -44,16 +181,20 @@ pub fn read_file_by_tokens<T: FnMut(&[u8])>(
         let data_len = data.len();
         let mut skip_bytes: usize = 0;
 
-        if !rest.is_empty() {
-            if let Some(pos) = data.iter().position(is_token_separator) {
-                rest.extend_from_slice(&data[..pos]);
-                token_callback(&rest);
-                rest.clear();
-                if is_doc_separator(&data[pos]) {
-                    token_callback(b"</s>")
+        if !rest.is_empty()
+            && let Some(pos) = data.iter().position(is_token_separator)
+        {
+            rest.extend_from_slice(&data[..pos]);
+            emit_token(&rest, filter.token(), params, &mut token_callback);
+            rest.clear();
+            if is_doc_separator(&data[pos]) {
+                if filter.line_end() {
+                    token_callback(b"</s>");
                 }
-                skip_bytes = pos + 1;
+            } else {
+                filter.whitespace();
             }
+            skip_bytes = pos + 1;
         }
 
         let mut token_start: usize = skip_bytes;
@@ -62,13 +203,23 @@ pub fn read_file_by_tokens<T: FnMut(&[u8])>(
         for byte in &data[skip_bytes..] {
             if is_token_separator(byte) {
                 if token_end > token_start {
-                    token_callback(&data[token_start..token_end]);
+                    let lowercase = filter.token();
+                    emit_token(
+                        &data[token_start..token_end],
+                        lowercase,
+                        params,
+                        &mut token_callback,
+                    );
                 }
                 token_end += 1;
                 token_start = token_end;
 
                 if is_doc_separator(byte) {
-                    token_callback(b"</s>");
+                    if filter.line_end() {
+                        token_callback(b"</s>");
+                    }
+                } else {
+                    filter.whitespace();
                 }
             } else {
                 token_end += 1;
@@ -82,17 +233,54 @@ pub fn read_file_by_tokens<T: FnMut(&[u8])>(
         buf_reader.consume(data_len);
     }
 
+    if filter.file_end() {
+        token_callback(b"</s>");
+    }
+
     Ok(())
 }
 
+/// @return the byte offset in `file_name` where sentence number `sentences` (counting
+/// from 0) begins, i.e. the offset after the `sentences`-th sentence separator, or the
+/// file size if the file has fewer sentences
+pub fn sentence_offset(file_name: &str, sentences: u64) -> std::io::Result<u64> {
+    let mut buf_reader: BufReader<File> = BufReader::new(File::open(file_name)?);
+    let mut offset: u64 = 0;
+    let mut remaining = sentences;
+
+    while remaining > 0 {
+        let data: &[u8] = buf_reader.fill_buf()?;
+        if data.is_empty() {
+            break;
+        }
+        let mut consumed = data.len();
+        for (pos, byte) in data.iter().enumerate() {
+            if is_doc_separator(byte) {
+                remaining -= 1;
+                if remaining == 0 {
+                    consumed = pos + 1;
+                    break;
+                }
+            }
+        }
+        offset += consumed as u64;
+        buf_reader.consume(consumed);
+    }
+
+    Ok(offset)
+}
+
 /// Iterator over file tokens (words)
 pub struct FileTokenIterator {
     file: File,
+    /// the offset in the file of `read_buffer[0]`
+    buffer_offset: u64,
     start_pos: usize,
     end_pos: usize,
     read_buffer: Vec<u8>,
     rest: Vec<u8>,
     output_separator: bool,
+    filter: SentenceFilter,
 }
 
 impl Iterator for FileTokenIterator {
@@ -112,13 +300,24 @@ const MAX_TOKEN_LEN: usize = 64;
 impl FileTokenIterator {
     /// Construct a FileTokenIterator, iteration begins at byte `offset` in the file
     pub fn new(file_name: &str, offset: u64) -> std::io::Result<FileTokenIterator> {
+        Self::with_params(file_name, offset, &TokenizerParams::default())
+    }
+
+    /// Same as `new`, splitting sentences as configured by `params`
+    pub fn with_params(
+        file_name: &str,
+        offset: u64,
+        params: &TokenizerParams,
+    ) -> std::io::Result<FileTokenIterator> {
         let mut result = FileTokenIterator {
             file: File::open(file_name)?,
+            buffer_offset: offset,
             start_pos: 0,
             end_pos: 0,
             read_buffer: Vec::with_capacity(READ_BUFFER_SIZE),
             rest: Vec::new(),
             output_separator: false,
+            filter: SentenceFilter::new(params),
         };
         result.read_buffer.resize(READ_BUFFER_SIZE, 0);
         result.file.seek(SeekFrom::Start(offset))?;
@@ -129,15 +328,56 @@ impl FileTokenIterator {
     /// Re-start iteration from the given offset
     pub fn reset(&mut self, offset: u64) -> std::io::Result<()> {
         self.file.seek(SeekFrom::Start(offset))?;
+        self.buffer_offset = offset;
         self.start_pos = 0;
         self.end_pos = 0;
         self.rest.clear();
         self.output_separator = false;
+        self.filter.reset();
         Ok(())
     }
 
+    /// @return the byte offset in the file where the next token begins, or of the
+    /// data following the last token if a "</s>" ending it wasn't returned yet
+    pub fn position(&self) -> u64 {
+        self.buffer_offset + self.start_pos as u64 - self.rest.len() as u64
+    }
+
     /// Read and return the next token from the file
     pub fn read_token(&mut self) -> Option<String> {
+        self.read_weighted_token().map(|(token, _)| token)
+    }
+
+    /// Same as `read_token`, also returning the weight of the token, which is 1 unless
+    /// set by a suffix as described for `TokenizerParams::token_weights`
+    pub fn read_weighted_token(&mut self) -> Option<(String, f32)> {
+        loop {
+            let Some(mut token) = self.read_raw_token() else {
+                if self.filter.file_end() {
+                    return Some((String::from("</s>"), 1.0));
+                }
+                return None;
+            };
+
+            let mut weight: f32 = 1.0;
+            if token != "</s>" {
+                if self.filter.params.token_weights
+                    && let Some((word, w)) = split_token_weight(token.as_bytes())
+                {
+                    weight = w;
+                    token.truncate(word.len());
+                }
+                if self.filter.token() {
+                    token = token.to_lowercase();
+                }
+            } else if !self.filter.line_end() {
+                continue;
+            }
+            return Some((token, weight));
+        }
+    }
+
+    fn read_raw_token(&mut self) -> Option<String> {
         if self.output_separator {
             self.output_separator = false;
             return Some(String::from("</s>"));
@@ -148,6 +388,7 @@ impl FileTokenIterator {
         'readloop: loop {
             // Read data if read buffer is empty
             if self.start_pos == self.end_pos {
+                self.buffer_offset += self.end_pos as u64;
                 self.start_pos = 0;
                 self.end_pos = self.file.read(&mut self.read_buffer[..]).unwrap_or(0);
                 if self.end_pos == 0 {
@@ -161,23 +402,22 @@ impl FileTokenIterator {
 
             // Check if we have trailing data from a previous read. If we can form a
             // token together with the current read, return it
-            if !self.rest.is_empty() {
-                if let Some(pos) = self.read_buffer[self.start_pos..self.end_pos]
+            if !self.rest.is_empty()
+                && let Some(pos) = self.read_buffer[self.start_pos..self.end_pos]
                     .iter()
                     .position(is_token_separator)
-                {
-                    self.rest
-                        .extend_from_slice(&self.read_buffer[self.start_pos..self.start_pos + pos]);
-
-                    if is_doc_separator(&self.read_buffer[self.start_pos + pos]) {
-                        self.output_separator = true;
-                    }
+            {
+                self.rest
+                    .extend_from_slice(&self.read_buffer[self.start_pos..self.start_pos + pos]);
 
-                    self.start_pos += pos + 1;
-                    output = vec_to_string_opt(&self.rest);
-                    self.rest.clear();
-                    break 'readloop output;
+                if is_doc_separator(&self.read_buffer[self.start_pos + pos]) {
+                    self.output_separator = true;
                 }
+
+                self.start_pos += pos + 1;
+                output = vec_to_string_opt(&self.rest);
+                self.rest.clear();
+                break 'readloop output;
             }
 
             let mut token_start: usize = self.start_pos;
@@ -189,17 +429,22 @@ impl FileTokenIterator {
                     continue;
                 }
 
-                if is_doc_separator(byte) {
-                    self.output_separator = true;
-                }
-
                 if token_end == token_start {
-                    // empty token, skip it
+                    // empty token, skip it, but report each line end as a separator
                     token_end += 1;
                     token_start = token_end;
+                    if is_doc_separator(byte) {
+                        self.start_pos = token_end;
+                        break 'readloop Some(String::from("</s>"));
+                    }
+                    self.filter.whitespace();
                     continue;
                 }
 
+                if is_doc_separator(byte) {
+                    self.output_separator = true;
+                }
+
                 output = vec_to_string_opt(&self.read_buffer[token_start..token_end]);
                 self.start_pos = token_end + 1;
                 break 'readloop output;
@@ -224,3 +469,96 @@ impl FileTokenIterator {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::write_temp_file;
+
+    /// @return the tokens of `contents` read by `read_file_by_tokens_with_params`,
+    /// after checking that `FileTokenIterator` reads the same tokens
+    fn tokens(name: &str, contents: &str, params: &TokenizerParams) -> Vec<String> {
+        let file = write_temp_file(name, contents);
+        let mut tokens: Vec<String> = Vec::new();
+        read_file_by_tokens_with_params(&file, params, |t: &[u8]| {
+            tokens.push(String::from_utf8(t.to_vec()).unwrap())
+        })
+        .unwrap();
+        let iterated: Vec<String> = FileTokenIterator::with_params(&file, 0, params)
+            .unwrap()
+            .collect();
+        assert_eq!(iterated, tokens);
+        tokens
+    }
+
+    /// @return the number of "</s>" tokens of `tokens`
+    fn sentences(tokens: &[String]) -> usize {
+        tokens.iter().filter(|t| *t == "</s>").count()
+    }
+
+    #[test]
+    fn each_line_is_one_sentence() {
+        let params = TokenizerParams::default();
+        let contents = "a b\nc\nd e f\n";
+        let newlines = contents.matches('\n').count();
+        assert_eq!(sentences(&tokens("lines.txt", contents, &params)), newlines);
+
+        // an unterminated last line only gets a "</s>" if configured
+        let contents = "a b\nc";
+        assert_eq!(sentences(&tokens("last-line.txt", contents, &params)), 1);
+        let params = TokenizerParams {
+            terminate_last_line: true,
+            ..Default::default()
+        };
+        assert_eq!(sentences(&tokens("last-line.txt", contents, &params)), 2);
+    }
+
+    #[test]
+    fn collapse_separators_drops_empty_sentences() {
+        let contents = "a\n\n\nb\n";
+        let default = tokens("collapse.txt", contents, &TokenizerParams::default());
+        assert_eq!(default, ["a", "</s>", "</s>", "</s>", "b", "</s>"]);
+        let params = TokenizerParams {
+            collapse_separators: true,
+            ..Default::default()
+        };
+        let collapsed = tokens("collapse.txt", contents, &params);
+        assert_eq!(collapsed, ["a", "</s>", "b", "</s>"]);
+    }
+
+    #[test]
+    fn whitespace_only_lines_make_no_sentences() {
+        let contents = "a b\n\t\t\n   \nc\n \t\n";
+        let params = TokenizerParams {
+            skip_whitespace_lines: true,
+            ..Default::default()
+        };
+        let skipped = tokens("whitespace-lines.txt", contents, &params);
+        assert_eq!(skipped, ["a", "b", "</s>", "c", "</s>"]);
+        // empty lines are kept
+        let kept = tokens("empty-lines.txt", "a\n\nb\n", &params);
+        assert_eq!(kept, ["a", "</s>", "</s>", "b", "</s>"]);
+        let default = tokens(
+            "whitespace-lines.txt",
+            contents,
+            &TokenizerParams::default(),
+        );
+        assert_eq!(sentences(&default), 5);
+    }
+
+    #[test]
+    fn only_sentence_starts_are_lowercased() {
+        let params = TokenizerParams {
+            lowercase_sentence_start: true,
+            ..Default::default()
+        };
+        let contents = "Apple sells Apple phones\nThe Apple\n";
+        let folded = tokens("sentence-start.txt", contents, &params);
+        assert_eq!(
+            folded,
+            [
+                "apple", "sells", "Apple", "phones", "</s>", "the", "Apple", "</s>"
+            ]
+        );
+    }
+}
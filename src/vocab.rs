@@ -13,11 +13,14 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use super::tokenizer::read_file_by_tokens;
+use super::sketch::CountMinSketch;
+use super::tokenizer::{FileTokenIterator, TokenizerParams, read_file_by_tokens_with_params};
 use core::str;
+use std::collections::HashSet;
 use std::fs::File;
 use std::hash::{DefaultHasher, Hash, Hasher};
-use std::io::{BufRead, BufReader, BufWriter, Error, Write};
+use std::io::{BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Seek, Write};
+use std::sync::OnceLock;
 
 struct WordInfo {
     word: String,
@@ -25,12 +28,129 @@ struct WordInfo {
 }
 
 const VOCAB_HASH_TABLE_SIZE: i32 = 30_000_000;
+#[cfg(not(test))]
 const UNIGRAM_TABLE_SIZE: usize = 100_000_000;
+/// the word replacing rare words with `VocabParams::merge_rare_words`
+pub const UNKNOWN_WORD: &str = "<UNK>";
+/// the exponent of the word counts in the negative sampling distribution
+const WORD_POWER: f64 = 0.75;
+/// the first line of the files written by `Vocabulary::save_with_tables`
+const TABLES_FILE_MAGIC: &[u8] = b"word2vec-rust vocabulary with tables 1\n";
+/// the number of words `Vocabulary::load_with_tables` looks up to validate the file
+const TABLES_FILE_CHECKED_WORDS: usize = 1024;
+/// number of frequency bands of `Vocabulary::sample_word_in_band`
+pub const NEGATIVE_SAMPLING_BANDS: usize = 10;
+/// number of rows of the count-min sketch used with `VocabParams::sketch_width`
+pub const VOCAB_SKETCH_DEPTH: usize = 5;
+// keeps the memory used by the tests learning vocabularies low
+#[cfg(test)]
+const UNIGRAM_TABLE_SIZE: usize = 1_000_000;
 
-fn get_word_hash_index(word: &str) -> usize {
+fn get_word_hash_index(word: &str, table_size: usize) -> usize {
     let mut hasher = DefaultHasher::new();
     word.hash(&mut hasher);
-    (hasher.finish() % VOCAB_HASH_TABLE_SIZE as u64) as usize
+    (hasher.finish() % table_size as u64) as usize
+}
+
+/// Parameters for learning a vocabulary from a training file
+pub struct VocabParams {
+    /// words occurring less than `min_count` times are discarded
+    pub min_count: u32,
+    /// The maximum number of hash table slots probed when looking up a word. This
+    /// bounds the cost of `search_word` and `add_word` in a crowded table. When
+    /// `add_word` can't place a new word within the bound it drops infrequent words
+    /// (as if the table was full) and tries once more, skipping the word if that
+    /// fails, so a low bound may discard more of the rare words. A word ending up
+    /// further than the bound from its hash slot when the table is rebuilt is reported
+    /// as missing by `search_word`.
+    pub max_probes: usize,
+    /// how the training file is split into sentences, should match training
+    pub tokenizer: TokenizerParams,
+    /// drop infrequent words with the original in-place `unsafe` code instead of
+    /// `Vec::retain`, which reorders the surviving words
+    pub fast_reduce: bool,
+    /// the expected number of distinct words in the training file, used to size the
+    /// hash table instead of the default 30M slots. Once the vocabulary fills 70% of
+    /// the table infrequent words are dropped, so an underestimate loses rare words.
+    pub estimated_words: Option<usize>,
+    /// Count words approximately with a count-min sketch of `VOCAB_SKETCH_DEPTH` rows
+    /// of this many counters, for corpora having too many distinct words for the
+    /// exact hash table. Only words whose estimated count reaches `min_count` enter
+    /// the hash table, starting with their estimated count, so together with
+    /// `estimated_words` (the expected number of such words, including rare words the
+    /// sketch overestimates) memory stays bounded.
+    /// The counts may then exceed the exact ones by the sketch error
+    /// e * train_words / sketch_width, with probability 1 - e^-VOCAB_SKETCH_DEPTH.
+    pub sketch_width: Option<usize>,
+    /// A hard cap on the number of words (including "</s>") kept while learning, to
+    /// bound memory regardless of the hash table size. When a new word exceeds it,
+    /// the least frequent words are evicted, keeping 90% of the cap. Unlike the
+    /// reduction done when the hash table fills up, the count threshold is not raised.
+    pub max_words: Option<usize>,
+    /// Instead of discarding the words occurring less than `min_count` times, replace
+    /// them by a single `UNKNOWN_WORD` entry counting all their occurrences, ordered
+    /// among the other words by its count (and kept even below `min_count`). Training
+    /// then maps every word that is not in the vocabulary to it, see
+    /// `Vocabulary::word_index_or_unknown`.
+    pub merge_rare_words: bool,
+}
+
+impl Default for VocabParams {
+    fn default() -> Self {
+        VocabParams {
+            min_count: 5,
+            max_probes: VOCAB_HASH_TABLE_SIZE as usize,
+            tokenizer: TokenizerParams::default(),
+            fast_reduce: false,
+            estimated_words: None,
+            sketch_width: None,
+            max_words: None,
+            merge_rare_words: false,
+        }
+    }
+}
+
+/// How much two vocabularies overlap, see `Vocabulary::overlap`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlapStats {
+    /// number of words found in both vocabularies
+    pub shared_words: usize,
+    /// shared words / words found in any of the vocabularies
+    pub jaccard: f64,
+    /// the Jaccard index weighted by word counts: sum(min(count1, count2)) /
+    /// sum(max(count1, count2)) over all words, a missing word having count 0
+    pub weighted_jaccard: f64,
+}
+
+/// How many sentences of a training file have a single in-vocabulary word, see
+/// `Vocabulary::count_singleton_sentences`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SingletonSentences {
+    /// number of sentences with at least one in-vocabulary word
+    pub sentences: u64,
+    /// number of sentences with exactly one in-vocabulary word
+    pub singletons: u64,
+}
+
+/// the fraction of singleton sentences above which `SingletonSentences::is_suspicious`
+/// holds
+pub const SINGLETON_WARNING_FRACTION: f64 = 0.5;
+
+impl SingletonSentences {
+    /// @return singletons / sentences, 0 if there are no sentences
+    pub fn fraction(&self) -> f64 {
+        if self.sentences == 0 {
+            return 0.0;
+        }
+        self.singletons as f64 / self.sentences as f64
+    }
+
+    /// @return true if more than `SINGLETON_WARNING_FRACTION` of the sentences are
+    /// singletons, which usually means the file has one word per line instead of one
+    /// sentence per line
+    pub fn is_suspicious(&self) -> bool {
+        self.fraction() > SINGLETON_WARNING_FRACTION
+    }
 }
 
 pub struct Vocabulary {
@@ -38,7 +158,18 @@ pub struct Vocabulary {
     hash_table: Vec<i32>,
     train_words: u64,
     min_reduce: u32,
+    max_probes: usize,
+    fast_reduce: bool,
+    /// see `VocabParams::max_words`
+    max_words: usize,
+    /// the index of `UNKNOWN_WORD` if the rare words were merged into it, else -1
+    unknown_word: i32,
     unigram_table: Vec<i32>,
+    /// the cached `pow_sum`, reset when the words or their counts change
+    pow_sum: OnceLock<f64>,
+    /// number of documents containing each word, see `count_documents`
+    doc_counts: Vec<u32>,
+    documents: u64,
 }
 
 impl Vocabulary {
@@ -46,26 +177,79 @@ impl Vocabulary {
         file_name: &str,
         min_count: u32,
     ) -> std::io::Result<Vocabulary> {
-        let mut vocab = Vocabulary::new();
-        let mut word_callback = |word: &[u8]| {
-            let word_str =
-                String::from_utf8(Vec::from(word)).unwrap_or_else(|_| String::from("<INV>"));
-            let _ = vocab.add_word(word_str);
+        let params = VocabParams {
+            min_count,
+            ..Default::default()
         };
+        Vocabulary::learn_vocabulary_with_params(file_name, &params)
+    }
 
+    pub fn learn_vocabulary_with_params(
+        file_name: &str,
+        params: &VocabParams,
+    ) -> std::io::Result<Vocabulary> {
+        Vocabulary::learn_vocabulary_with_growth(file_name, params, 0, |_, _| {})
+    }
+
+    /// Same as `learn_vocabulary_with_params`, invoking `growth` every `report_every`
+    /// tokens (including "</s>" tokens) and once more at the end of the file with the
+    /// number of tokens read and the number of distinct words counted so far, e.g. to
+    /// fit Heaps' law. The count only drops when infrequent words are discarded to
+    /// make room in the hash table. Words below `min_count` are only removed after the
+    /// last report. A `report_every` of 0 disables the reports.
+    pub fn learn_vocabulary_with_growth<G: FnMut(u64, usize)>(
+        file_name: &str,
+        params: &VocabParams,
+        report_every: u64,
+        mut growth: G,
+    ) -> std::io::Result<Vocabulary> {
+        let mut vocab = match params.estimated_words {
+            Some(estimated_words) => Vocabulary::with_estimated_size(estimated_words),
+            None => Vocabulary::new(),
+        };
+        vocab.max_probes = params.max_probes.clamp(1, vocab.hash_table.len());
+        vocab.fast_reduce = params.fast_reduce;
+        // "</s>" is never dropped
+        vocab.max_words = params
+            .max_words
+            .map_or(usize::MAX, |max_words| max_words.max(1));
         // ensure the document/sentence/line separator represented by "</s>" has index 0, as
         // expected by other functions
-        word_callback(b"</s>");
-        read_file_by_tokens(file_name, word_callback)?;
-        vocab.sort_vocab(min_count);
+        vocab.add_word(String::from("</s>"));
+
+        let mut sketch = params
+            .sketch_width
+            .map(|width| CountMinSketch::new(width, VOCAB_SKETCH_DEPTH));
+        let mut tokens_seen: u64 = 0;
+        let word_callback = |word: &[u8]| {
+            let word_str =
+                String::from_utf8(Vec::from(word)).unwrap_or_else(|_| String::from("<INV>"));
+            vocab.count_token(word_str, sketch.as_mut(), params.min_count);
+            tokens_seen += 1;
+            if report_every > 0 && tokens_seen.is_multiple_of(report_every) {
+                growth(tokens_seen, vocab.len());
+            }
+        };
+        read_file_by_tokens_with_params(file_name, &params.tokenizer, word_callback)?;
+        if report_every > 0 && !tokens_seen.is_multiple_of(report_every) {
+            growth(tokens_seen, vocab.len());
+        }
+        vocab.sort_vocab(params.min_count, params.merge_rare_words);
         vocab.init_unigram_table();
         Ok(vocab)
     }
 
     pub fn save_to_file(&self, vocab_file: &str) -> std::io::Result<()> {
         let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(vocab_file)?);
+        self.save_to(&mut buf_writer)?;
+        buf_writer.flush()
+    }
+
+    /// Same as `save_to_file`, writing to `writer`, letting callers choose their own
+    /// buffering
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
         for w in self.words.iter() {
-            writeln!(buf_writer, "{} {}", w.word, w.count)?;
+            writeln!(writer, "{} {}", w.word, w.count)?;
         }
         Ok(())
     }
@@ -127,10 +311,180 @@ impl Vocabulary {
         Ok(vocab)
     }
 
+    /// Save the vocabulary together with its hash table and unigram table, so that
+    /// `load_with_tables` doesn't have to rebuild them. The file starts with a header
+    /// line, followed by the "word count" lines written by `save_to_file` and the
+    /// tables as little endian i32 values. The tables take 4 bytes per slot, so with the
+    /// default sizes the file is about 520MB whatever the number of words.
+    pub fn save_with_tables(&self, file_name: &str) -> std::io::Result<()> {
+        let mut buf_writer: BufWriter<File> = BufWriter::new(File::create(file_name)?);
+        buf_writer.write_all(TABLES_FILE_MAGIC)?;
+        writeln!(
+            buf_writer,
+            "{} {} {}",
+            self.words.len(),
+            self.hash_table.len(),
+            self.unigram_table.len()
+        )?;
+        self.save_to(&mut buf_writer)?;
+        for val in self.hash_table.iter().chain(&self.unigram_table) {
+            buf_writer.write_all(&val.to_le_bytes())?;
+        }
+        buf_writer.flush()
+    }
+
+    /// Load a vocabulary saved by `save_with_tables`. The hash table is only valid for
+    /// the word hash function it was built with, which may change with the Rust
+    /// version; an InvalidData error is returned if one of a sample of the words isn't
+    /// found through it.
+    pub fn load_with_tables(file_name: &str) -> std::io::Result<Vocabulary> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        let file = File::open(file_name)?;
+        let file_len = file.metadata()?.len();
+        let mut buf_reader = BufReader::new(file);
+        let mut line_buf: Vec<u8> = Vec::new();
+        buf_reader.read_until(b'\n', &mut line_buf)?;
+        if line_buf != TABLES_FILE_MAGIC {
+            return Err(invalid("Not a vocabulary file with tables"));
+        }
+
+        let mut line = String::new();
+        buf_reader.read_line(&mut line)?;
+        let sizes: Vec<usize> = line
+            .split_ascii_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid("Invalid header"))?;
+        let [words_len, hash_table_len, unigram_table_len] = sizes[..] else {
+            return Err(invalid("Invalid header"));
+        };
+        // each "word count" line takes at least 4 bytes
+        if words_len == 0
+            || hash_table_len <= words_len
+            || unigram_table_len == 0
+            || words_len as u64 > file_len / 4
+        {
+            return Err(invalid("Invalid header"));
+        }
+
+        let mut vocab = Vocabulary::with_table_size(0);
+        vocab.words.reserve_exact(words_len);
+        for _ in 0..words_len {
+            line_buf.clear();
+            buf_reader.read_until(b'\n', &mut line_buf)?;
+            let line =
+                str::from_utf8(&line_buf).map_err(|_| invalid("Encountered invalid line"))?;
+            let Some((word, count)) = line.trim_end().split_once(' ') else {
+                return Err(invalid("Encountered invalid line"));
+            };
+            let count: u32 = count
+                .parse()
+                .map_err(|_| invalid("Count is not a positive integer"))?;
+            vocab.train_words += count as u64;
+            vocab.words.push(WordInfo {
+                word: word.to_string(),
+                count,
+            });
+        }
+
+        // don't allocate tables larger than the rest of the file for a corrupted header
+        let tables_bytes = (hash_table_len as u64)
+            .checked_add(unigram_table_len as u64)
+            .and_then(|len| len.checked_mul(4));
+        let remaining_bytes = file_len.saturating_sub(buf_reader.stream_position()?);
+        if tables_bytes.is_none_or(|bytes| bytes > remaining_bytes) {
+            return Err(invalid("The tables don't fit in the file"));
+        }
+        vocab.hash_table = read_i32_table(&mut buf_reader, hash_table_len)?;
+        vocab.unigram_table = read_i32_table(&mut buf_reader, unigram_table_len)?;
+        vocab.max_probes = hash_table_len;
+        if vocab
+            .hash_table
+            .iter()
+            .chain(&vocab.unigram_table)
+            .any(|&idx| idx < -1 || idx >= words_len as i32)
+        {
+            return Err(invalid("Word index out of range"));
+        }
+        // looking up every word would cost as much as rebuilding the table, check that
+        // it holds each word once and look up a sample of the words
+        let mut seen: Vec<bool> = vec![false; words_len];
+        for &idx in vocab.hash_table.iter().filter(|&&idx| idx != -1) {
+            if std::mem::replace(&mut seen[idx as usize], true) {
+                return Err(invalid("The hash table doesn't match the words"));
+            }
+        }
+        let step = words_len.div_ceil(TABLES_FILE_CHECKED_WORDS);
+        if seen.contains(&false)
+            || (0..words_len)
+                .step_by(step)
+                .any(|idx| vocab.search_word(&vocab.words[idx].word) != idx as i32)
+        {
+            return Err(invalid("The hash table doesn't match the words"));
+        }
+        Ok(vocab)
+    }
+
+    /// Build a vocabulary from the words of a model file, in file order. Model files
+    /// don't store word counts so all counts are 0 and no unigram table is built,
+    /// such a vocabulary can be queried but not used for training.
+    pub(crate) fn from_model_words(words: Vec<String>) -> std::io::Result<Vocabulary> {
+        Vocabulary::from_word_counts(words.into_iter().map(|w| (w, 0)).collect())
+    }
+
+    /// Read `words` words saved by `save_to` from `reader`, e.g. from a training
+    /// checkpoint, keeping their order, and build the unigram table
+    pub(crate) fn read_from<R: BufRead>(
+        reader: &mut R,
+        words: usize,
+    ) -> std::io::Result<Vocabulary> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, msg.to_string());
+        let mut vocab = Vocabulary::with_estimated_size(words);
+        let mut line_buf: Vec<u8> = Vec::new();
+        for _ in 0..words {
+            line_buf.clear();
+            reader.read_until(b'\n', &mut line_buf)?;
+            let line =
+                str::from_utf8(&line_buf).map_err(|_| invalid("Encountered invalid line"))?;
+            let Some((word, count)) = line.trim_end().rsplit_once(' ') else {
+                return Err(invalid("Encountered invalid line"));
+            };
+            let count: u32 = count
+                .parse()
+                .map_err(|_| invalid("Count is not a positive integer"))?;
+            if word.is_empty() || vocab.search_word(word) != -1 {
+                return Err(invalid("Word is empty or duplicate"));
+            }
+            vocab.add_word_with_count(word.to_string(), count);
+        }
+        if vocab.is_empty() {
+            return Err(invalid("Empty vocabulary"));
+        }
+        vocab.init_unigram_table();
+        Ok(vocab)
+    }
+
+    /// Build a vocabulary from (word, count) pairs, keeping their order. No unigram
+    /// table is built, such a vocabulary can be queried but not used for training.
+    pub(crate) fn from_word_counts(words: Vec<(String, u32)>) -> std::io::Result<Vocabulary> {
+        let mut vocab = Vocabulary::with_estimated_size(words.len());
+        for (word, count) in words {
+            if vocab.search_word(&word) != -1 {
+                return Err(Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Duplicate word '{word}'"),
+                ));
+            }
+            vocab.add_word_with_count(word, count);
+        }
+        Ok(vocab)
+    }
+
     /// return word index (or word ID), -1 is returned if not found
     pub fn search_word(&self, word: &str) -> i32 {
-        let mut hidx = get_word_hash_index(word);
-        loop {
+        let table_size = self.hash_table.len();
+        let mut hidx = get_word_hash_index(word, table_size);
+        for _ in 0..self.max_probes {
             if self.hash_table[hidx] == -1 {
                 return -1;
             }
@@ -138,10 +492,53 @@ impl Vocabulary {
             if self.words[widx as usize].word == word {
                 return widx;
             }
-            hidx = (hidx + 1) % (VOCAB_HASH_TABLE_SIZE as usize);
+            hidx = (hidx + 1) % table_size;
+        }
+        -1
+    }
+
+    /// @return the index of `word`, or for words not in the vocabulary the index of
+    /// `UNKNOWN_WORD` if the rare words were merged into it by
+    /// `VocabParams::merge_rare_words`, else -1. Vocabularies read from files don't
+    /// keep that setting, their "<UNK>" entry is an ordinary word, except for the
+    /// vocabulary of a training checkpoint.
+    pub fn word_index_or_unknown(&self, word: &str) -> i32 {
+        match self.search_word(word) {
+            -1 => self.unknown_word,
+            idx => idx,
         }
     }
 
+    /// @return the index of `UNKNOWN_WORD` if the rare words were merged into it, see
+    /// `word_index_or_unknown`
+    pub fn unknown_word_index(&self) -> Option<usize> {
+        usize::try_from(self.unknown_word).ok()
+    }
+
+    /// map the words not in the vocabulary to the word with index `idx`, e.g. the
+    /// `UNKNOWN_WORD` of a vocabulary read from a checkpoint
+    pub(crate) fn set_unknown_word(&mut self, idx: usize) {
+        assert!(idx < self.words.len());
+        self.unknown_word = idx as i32;
+    }
+
+    /// return the longest probe sequence needed to find a word in the hash table, 1
+    /// meaning every word is stored at its hash position; long sequences signal a hash
+    /// table that is too small or a poor hash function
+    pub fn max_probe_length(&self) -> usize {
+        let table_size = self.hash_table.len();
+        self.hash_table
+            .iter()
+            .enumerate()
+            .filter(|&(_, &widx)| widx != -1)
+            .map(|(hidx, &widx)| {
+                let home = get_word_hash_index(&self.words[widx as usize].word, table_size);
+                (hidx + table_size - home) % table_size + 1
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
     pub fn len(&self) -> usize {
         self.words.len()
     }
@@ -151,13 +548,216 @@ impl Vocabulary {
         self.len() == 0
     }
 
+    /// return the word with index `word_idx`
+    pub fn word(&self, word_idx: usize) -> &str {
+        &self.words[word_idx].word
+    }
+
+    /// return the number of occurrences of the word with index `word_idx`
+    pub fn word_count(&self, word_idx: usize) -> u32 {
+        self.words[word_idx].count
+    }
+
     pub fn train_words(&self) -> u64 {
         self.train_words
     }
 
+    /// @return the smallest `min_count` (at least 1) that keeps at most `target` words
+    /// of this vocabulary besides "</s>", e.g. to learn the vocabulary again with a
+    /// `min_count` giving about `target` words
+    pub fn min_count_for_target_size(&self, target: usize) -> u32 {
+        let mut counts: Vec<u32> = self.words.iter().skip(1).map(|w| w.count).collect();
+        if counts.len() <= target {
+            return 1;
+        }
+        counts.sort_unstable_by(|a, b| b.cmp(a));
+        // all words counted more often than the first word not fitting in `target`
+        counts[target].saturating_add(1)
+    }
+
+    /// @return (word_index, word, count) of the word with the highest count, ignoring
+    /// "</s>", or None for a vocabulary without other words. The vocabulary is
+    /// searched, so this also works before the words are sorted by count.
+    pub fn most_frequent(&self) -> Option<(usize, &str, u32)> {
+        self.words
+            .iter()
+            .enumerate()
+            .skip(1)
+            // max_by_key returns the last maximum, so on ties the lowest index wins
+            .rev()
+            .max_by_key(|(_, info)| info.count)
+            .map(|(idx, info)| (idx, &info.word[..], info.count))
+    }
+
+    /// Count the number of documents containing each vocabulary word, as needed by
+    /// `idf`. Each sentence of `file_name` containing at least one token is a document.
+    pub fn count_documents(
+        &mut self,
+        file_name: &str,
+        tokenizer: &TokenizerParams,
+    ) -> std::io::Result<()> {
+        let mut doc_counts: Vec<u32> = vec![0; self.words.len()];
+        // the last document each word was counted in
+        let mut last_doc: Vec<u64> = vec![u64::MAX; self.words.len()];
+        let mut documents: u64 = 0;
+        let mut doc_open = false;
+
+        read_file_by_tokens_with_params(file_name, tokenizer, |token: &[u8]| {
+            if token == b"</s>" {
+                doc_open = false;
+                return;
+            }
+            if !doc_open {
+                doc_open = true;
+                documents += 1;
+            }
+            // invalid UTF-8 is counted as "<INV>", like when learning the vocabulary
+            let idx = self.search_word(str::from_utf8(token).unwrap_or("<INV>"));
+            if idx > 0 && last_doc[idx as usize] != documents {
+                last_doc[idx as usize] = documents;
+                doc_counts[idx as usize] += 1;
+            }
+        })?;
+
+        self.doc_counts = doc_counts;
+        self.documents = documents;
+        Ok(())
+    }
+
+    /// Count the sentences of `file_name` having a single in-vocabulary word, split as
+    /// configured by `tokenizer`. Such a sentence has no context words (unless
+    /// `TrainigParams::span_sentences` is set), so it adds no training signal.
+    pub fn count_singleton_sentences(
+        &self,
+        file_name: &str,
+        tokenizer: &TokenizerParams,
+    ) -> std::io::Result<SingletonSentences> {
+        let mut result = SingletonSentences {
+            sentences: 0,
+            singletons: 0,
+        };
+        // the number of in-vocabulary words of the current sentence
+        let mut words: u64 = 0;
+        let mut end_sentence = |words: &mut u64| {
+            if *words > 0 {
+                result.sentences += 1;
+            }
+            if *words == 1 {
+                result.singletons += 1;
+            }
+            *words = 0;
+        };
+
+        read_file_by_tokens_with_params(file_name, tokenizer, |token: &[u8]| {
+            if token == b"</s>" {
+                end_sentence(&mut words);
+            } else if self.search_word(str::from_utf8(token).unwrap_or("<INV>")) > 0 {
+                words += 1;
+            }
+        })?;
+        // an unterminated last line
+        end_sentence(&mut words);
+        Ok(result)
+    }
+
+    /// @return the inverse document frequency ln(documents / documents containing
+    /// `word`), None if `word` is not in the vocabulary, never occurred in a document
+    /// or `count_documents` wasn't called
+    pub fn idf(&self, word: &str) -> Option<f64> {
+        let idx = self.search_word(word);
+        if idx <= 0 {
+            return None;
+        }
+        match self.doc_counts.get(idx as usize) {
+            Some(&count) if count > 0 => Some((self.documents as f64 / count as f64).ln()),
+            _ => None,
+        }
+    }
+
+    /// Compare the words of this vocabulary with those of `other`, ignoring the "</s>"
+    /// sentence separator
+    pub fn overlap(&self, other: &Vocabulary) -> OverlapStats {
+        let mut shared_words: usize = 0;
+        let (mut min_sum, mut max_sum): (u64, u64) = (0, 0);
+        for info in self.words.iter().skip(1) {
+            let idx = other.search_word(&info.word);
+            let other_count = if idx > 0 {
+                shared_words += 1;
+                other.words[idx as usize].count
+            } else {
+                0
+            };
+            min_sum += info.count.min(other_count) as u64;
+            max_sum += info.count.max(other_count) as u64;
+        }
+        // words found only in `other`
+        for info in other.words.iter().skip(1) {
+            if self.search_word(&info.word) <= 0 {
+                max_sum += info.count as u64;
+            }
+        }
+
+        let all_words = self.len().saturating_sub(1) + other.len().saturating_sub(1) - shared_words;
+        let ratio = |num: u64, den: u64| {
+            if den == 0 {
+                0.0
+            } else {
+                num as f64 / den as f64
+            }
+        };
+        OverlapStats {
+            shared_words,
+            jaccard: ratio(shared_words as u64, all_words as u64),
+            weighted_jaccard: ratio(min_sum, max_sum),
+        }
+    }
+
+    /// @return the (word, count) pairs of the words of this vocabulary missing from
+    /// `other`, in vocabulary order and ignoring the "</s>" sentence separator; swap
+    /// the vocabularies for the words found only in `other`
+    pub fn words_missing_from(&self, other: &Vocabulary) -> Vec<(&str, u32)> {
+        self.words
+            .iter()
+            .skip(1)
+            .filter(|info| other.search_word(&info.word) <= 0)
+            .map(|info| (&info.word[..], info.count))
+            .collect()
+    }
+
+    /// Append the words of `other` missing from this vocabulary with a count of 0,
+    /// e.g. to keep the words of a pretrained model when continuing its training on a
+    /// new corpus, see `NeuralNet::warm_start`. Words of count 0 are never drawn as
+    /// negative samples, and are only trained on if they occur in the training file
+    /// despite the vocabulary's min count. The hash table grows as needed.
+    /// @return the number of words added
+    pub fn add_missing_words(&mut self, other: &Vocabulary) -> usize {
+        let missing: Vec<&str> = other
+            .into_iter()
+            .filter(|word| self.search_word(word) == -1)
+            .collect();
+        let min_size = ((self.words.len() + missing.len() + 1) as f64 / 0.7).ceil() as usize;
+        if min_size > self.hash_table.len() {
+            // a probe limit of the table size means no limit
+            let unlimited = self.max_probes >= self.hash_table.len();
+            self.hash_table = vec![-1; min_size.next_power_of_two()];
+            if unlimited {
+                self.max_probes = self.hash_table.len();
+            }
+            self.rebuild_hashtable();
+        }
+        for &word in &missing {
+            self.add_word_with_count(word.to_string(), 0);
+        }
+        missing.len()
+    }
+
     // Pick a random word to use as a 'negative sample'; do this using
     // the unigram table.d.bin"
     pub fn sample_random_word(&self, rand_seed: i64) -> i32 {
+        assert!(
+            !self.unigram_table.is_empty(),
+            "vocabularies loaded from model files have no unigram table"
+        );
         let idx = (rand_seed as usize >> 16) % self.unigram_table.len();
         let mut target = self.unigram_table[idx];
         // If the target is the special end of sentence token, then just
@@ -168,16 +768,70 @@ impl Vocabulary {
         target
     }
 
+    /// @return the sum of count^0.75 over all words, the normalization of the negative
+    /// sampling distribution, computed once and cached until the counts change
+    pub fn pow_sum(&self) -> f64 {
+        *self.pow_sum.get_or_init(|| {
+            self.words.iter().fold(0.0f64, |acc, word| {
+                acc + f64::powf(word.count as f64, WORD_POWER)
+            })
+        })
+    }
+
+    /// Pick a random word of the frequency band of the word with index `word_idx`, as
+    /// negative sample for `TrainigParams::banded_negatives`: assuming the vocabulary
+    /// is sorted by decreasing count, the words besides "</s>" are split by their rank
+    /// into `NEGATIVE_SAMPLING_BANDS` bands of (nearly) equal size. The word is drawn
+    /// uniformly from the band, not following the unigram distribution.
+    pub fn sample_word_in_band(&self, word_idx: usize, rand_seed: i64) -> i32 {
+        let words = self.words.len() - 1;
+        let rank = word_idx.saturating_sub(1).min(words.saturating_sub(1));
+        let band = rank * NEGATIVE_SAMPLING_BANDS / words.max(1);
+        // the ranks r with band * words <= r * NEGATIVE_SAMPLING_BANDS < (band + 1) * words
+        let start = (band * words).div_ceil(NEGATIVE_SAMPLING_BANDS);
+        let end = ((band + 1) * words).div_ceil(NEGATIVE_SAMPLING_BANDS);
+        (start + (rand_seed as usize >> 16) % (end - start).max(1) + 1) as i32
+    }
+
     fn new() -> Self {
-        let mut vocab = Vocabulary {
+        Vocabulary::with_table_size(VOCAB_HASH_TABLE_SIZE as usize)
+    }
+
+    /// Construct an empty vocabulary with a hash table sized for `estimated_words`
+    /// words: the next power of two above `estimated_words / 0.7`, as the vocabulary
+    /// is reduced once it fills 70% of the table, but at most the default 30M slots.
+    /// Words can be added with `add_missing_words`, the vocabulary learned from a
+    /// training file is sized like this by `VocabParams::estimated_words`.
+    pub fn with_estimated_size(estimated_words: usize) -> Self {
+        // one more for "</s>"
+        let min_size = ((estimated_words + 1) as f64 / 0.7).ceil() as usize + 1;
+        Vocabulary::with_table_size(
+            min_size
+                .next_power_of_two()
+                .min(VOCAB_HASH_TABLE_SIZE as usize),
+        )
+    }
+
+    fn with_table_size(table_size: usize) -> Self {
+        Vocabulary {
             words: Vec::new(),
-            hash_table: Vec::with_capacity(VOCAB_HASH_TABLE_SIZE as usize),
+            hash_table: vec![-1; table_size],
             train_words: 0,
             min_reduce: 1,
+            max_probes: table_size,
+            fast_reduce: false,
+            max_words: usize::MAX,
+            unknown_word: -1,
             unigram_table: Vec::new(),
-        };
-        vocab.hash_table.resize(VOCAB_HASH_TABLE_SIZE as usize, -1);
-        vocab
+            pow_sum: OnceLock::new(),
+            doc_counts: Vec::new(),
+            documents: 0,
+        }
+    }
+
+    /// return the number of slots in the word hash table
+    pub fn hash_table_size(&self) -> usize {
+        self.hash_table.len()
     }
 
     /// return a pair: (hashtable_index, word_array_index), where
@@ -185,47 +839,81 @@ impl Vocabulary {
     /// insertion index for a new word
     /// word_array_index the index in the word array for an existing word or -1 for
     /// a new word
-    fn get_word_indices(&self, word: &str) -> (usize, i32) {
+    /// None is returned if neither was found within `max_probes` probes
+    fn get_word_indices(&self, word: &str) -> Option<(usize, i32)> {
         // index in the hashtable
-        let mut hash_idx = get_word_hash_index(word);
-        // index in the words array
-        let mut word_idx: i32 = -1;
-        loop {
-            if self.hash_table[hash_idx] == -1 {
-                break;
-            }
+        let table_size = self.hash_table.len();
+        let mut hash_idx = get_word_hash_index(word, table_size);
+        for _ in 0..self.max_probes {
             let wi = self.hash_table[hash_idx];
-            if self.words[wi as usize].word == word {
-                word_idx = wi;
-                break;
+            if wi == -1 || self.words[wi as usize].word == word {
+                return Some((hash_idx, wi));
             }
-            hash_idx = (hash_idx + 1) % (VOCAB_HASH_TABLE_SIZE as usize);
+            hash_idx = (hash_idx + 1) % table_size;
         }
-        (hash_idx, word_idx)
+        None
     }
 
+    /// Count one occurrence of `word`. If the word can't be placed within
+    /// `max_probes` probes even after dropping infrequent words once, it is not
+    /// counted and -1 is returned.
     fn add_word(&mut self, word: String) -> i32 {
-        let (hash_idx, mut word_idx) = self.get_word_indices(&word);
+        self.add_word_occurrences(word, 1)
+    }
+
+    /// Count an occurrence of `word` read from a training file. With a `sketch` the
+    /// word only enters the hash table once its estimated count reaches `min_count`.
+    fn count_token(&mut self, word: String, sketch: Option<&mut CountMinSketch>, min_count: u32) {
+        let Some(sketch) = sketch else {
+            let _ = self.add_word(word);
+            return;
+        };
+
+        let estimate = sketch.add(&word);
+        if self.search_word(&word) != -1 {
+            let _ = self.add_word(word);
+        } else if estimate >= min_count {
+            let _ = self.add_word_occurrences(word, estimate);
+        }
+    }
+
+    /// Same as `add_word`, counting `count` occurrences of `word`
+    fn add_word_occurrences(&mut self, word: String, count: u32) -> i32 {
+        let indices = self.get_word_indices(&word).or_else(|| {
+            // the probe sequence is too long, make room by dropping infrequent words
+            self.reduce_vocab();
+            self.get_word_indices(&word)
+        });
+        let Some((hash_idx, mut word_idx)) = indices else {
+            return -1;
+        };
 
         if word_idx == -1 {
             word_idx = self.words.len() as i32;
-            self.words.push(WordInfo { word, count: 1 });
+            self.words.push(WordInfo { word, count });
             self.hash_table[hash_idx] = word_idx;
         } else {
-            self.words[word_idx as usize].count += 1;
+            self.words[word_idx as usize].count += count;
         }
-        self.train_words += 1;
+        self.train_words += count as u64;
+        self.pow_sum.take();
 
-        if self.words.len() as f64 > (0.7 * VOCAB_HASH_TABLE_SIZE as f64) {
+        if self.words.len() as f64 > (0.7 * self.hash_table.len() as f64) {
             self.reduce_vocab();
             // word_idx is no longer valid at this point, set it to -1
             word_idx = -1;
         }
+        if self.words.len() > self.max_words {
+            self.evict_infrequent_words();
+            word_idx = -1;
+        }
         word_idx
     }
 
     fn add_word_with_count(&mut self, word: String, count: u32) -> i32 {
-        let (hash_idx, mut word_idx) = self.get_word_indices(&word);
+        let (hash_idx, mut word_idx) = self
+            .get_word_indices(&word)
+            .expect("vocabulary hash table is full");
         assert!(word_idx == -1);
 
         if word_idx == -1 {
@@ -236,24 +924,57 @@ impl Vocabulary {
             self.words[word_idx as usize].count += count;
         }
         self.train_words += count as u64;
+        self.pow_sum.take();
         word_idx
     }
 
     fn rebuild_hashtable(&mut self) {
         self.hash_table.fill(-1);
         self.train_words = 0;
+        // the words were removed or reordered
+        self.pow_sum.take();
 
+        let table_size = self.hash_table.len();
         for (widx, w) in self.words.iter().enumerate() {
-            let mut hidx = get_word_hash_index(&w.word);
+            let mut hidx = get_word_hash_index(&w.word, table_size);
             while self.hash_table[hidx] != -1 {
-                hidx = (hidx + 1) % (VOCAB_HASH_TABLE_SIZE as usize);
+                hidx = (hidx + 1) % table_size;
             }
             self.hash_table[hidx] = widx as i32;
             self.train_words += w.count as u64;
         }
     }
 
+    /// drop the words occurring at most `min_reduce` times, keeping "</s>" at index 0,
+    /// and increment `min_reduce`
     fn reduce_vocab(&mut self) {
+        if self.fast_reduce {
+            self.reduce_vocab_in_place();
+        } else {
+            let min_reduce = self.min_reduce;
+            let mut is_first = true;
+            self.words
+                .retain(|w| std::mem::take(&mut is_first) || w.count > min_reduce);
+        }
+        self.min_reduce += 1;
+        self.rebuild_hashtable();
+    }
+
+    /// Keep only the most frequent 90% of `max_words` words, to make room for new words
+    /// without evicting one word each time a new one is added
+    fn evict_infrequent_words(&mut self) {
+        let keep = (self.max_words - self.max_words / 10).max(1);
+        if self.words.len() > keep {
+            // word 0 is "</s>", it is always kept
+            self.words[1..].select_nth_unstable_by_key(keep - 1, |w| u32::MAX - w.count);
+            self.words.truncate(keep);
+            self.rebuild_hashtable();
+        }
+    }
+
+    /// the word removal step of `reduce_vocab`, moving the last surviving words into
+    /// the slots of the removed ones
+    fn reduce_vocab_in_place(&mut self) {
         let mut idx: usize = 1;
         loop {
             if idx >= self.words.len() {
@@ -273,29 +994,53 @@ impl Vocabulary {
                 }
             }
         }
-        self.min_reduce += 1;
-        self.rebuild_hashtable();
     }
 
-    fn sort_vocab(&mut self, min_count: u32) {
-        let f = |x: &WordInfo| u32::MAX - x.count;
-        // self.words[1..].sort_unstable_by_key(f);
-        self.words[1..].sort_by_key(f);
+    /// sort the words and drop those occurring less than `min_count` times, or merge
+    /// them into `UNKNOWN_WORD` if `merge_rare` is set
+    fn sort_vocab(&mut self, min_count: u32, merge_rare: bool) {
+        // an "<UNK>" token of the training file is merged with the rare words
+        let mut unknown_count: u64 = 0;
+        if merge_rare && let Some(pos) = self.words[1..].iter().position(|w| w.word == UNKNOWN_WORD)
+        {
+            unknown_count = self.words.remove(pos + 1).count as u64;
+        }
+
+        // by decreasing count, words of equal count alphabetically, so that the order
+        // doesn't depend on the order the words were added in; as the words are
+        // distinct an unstable sort gives the same result
+        let order =
+            |x: &WordInfo, y: &WordInfo| y.count.cmp(&x.count).then_with(|| x.word.cmp(&y.word));
+        self.words[1..].sort_unstable_by(order);
         let idx = self
             .words
             .partition_point(|x: &WordInfo| x.count >= min_count);
-        self.words.truncate(idx);
-        self.rebuild_hashtable();
+
+        if merge_rare {
+            unknown_count += self.words[idx..]
+                .iter()
+                .map(|w| w.count as u64)
+                .sum::<u64>();
+            self.words.truncate(idx);
+            let unknown = WordInfo {
+                word: String::from(UNKNOWN_WORD),
+                count: unknown_count.min(u32::MAX as u64) as u32,
+            };
+            let pos = 1 + self.words[1..].partition_point(|w| order(w, &unknown).is_lt());
+            self.words.insert(pos, unknown);
+            self.rebuild_hashtable();
+            self.unknown_word = pos as i32;
+        } else {
+            self.words.truncate(idx);
+            self.rebuild_hashtable();
+        }
     }
 
     fn init_unigram_table(&mut self) {
         assert!(!self.words.is_empty());
 
         // initialize the unigram table according to the word count distribution
-        const WORD_POWER: f64 = 0.75;
-        let train_words_pow: f64 = self.words.iter().fold(0.0f64, |acc, word| {
-            acc + f64::powf(word.count as f64, WORD_POWER)
-        });
+        let train_words_pow: f64 = self.pow_sum();
 
         let mut frac: f64 = f64::powf(self.words[0].count as f64, WORD_POWER) / train_words_pow;
         self.unigram_table.resize(UNIGRAM_TABLE_SIZE, -1);
@@ -311,6 +1056,65 @@ impl Vocabulary {
     }
 }
 
+/// read `len` little endian i32 values from `reader`
+fn read_i32_table<R: Read>(reader: &mut R, len: usize) -> std::io::Result<Vec<i32>> {
+    let mut table: Vec<i32> = Vec::with_capacity(len);
+    let mut buf: Vec<u8> = vec![0; 1 << 16];
+    while table.len() < len {
+        let bytes = ((len - table.len()) * 4).min(buf.len());
+        reader.read_exact(&mut buf[..bytes])?;
+        table.extend(
+            buf[..bytes]
+                .chunks_exact(4)
+                .map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        );
+    }
+    Ok(table)
+}
+
+/// Estimate the number of distinct tokens (including "</s>") of `file_name` from its
+/// first `sample_bytes` bytes, e.g. to set `VocabParams::estimated_words`, splitting
+/// it as configured by `tokenizer`, which should be `VocabParams::tokenizer`.
+/// Following Heaps' law, the number of distinct words grows like n^b after n tokens,
+/// b being estimated from the growth between the first half of the sample and the
+/// whole sample. The result is exact if the file is not larger than the sample; it
+/// doesn't account for `min_count`.
+pub fn estimate_vocab_size(
+    file_name: &str,
+    sample_bytes: u64,
+    tokenizer: &TokenizerParams,
+) -> std::io::Result<usize> {
+    let file_size = std::fs::metadata(file_name)?.len();
+    let mut distinct: HashSet<String> = HashSet::new();
+    let mut bytes_read: u64 = 0;
+    // (tokens, distinct tokens) after half of the sample
+    let mut half_sample: (u64, usize) = (0, 0);
+    let mut tokens: u64 = 0;
+
+    let mut fi = FileTokenIterator::with_params(file_name, 0, tokenizer)?;
+    while bytes_read < sample_bytes {
+        let Some(token) = fi.read_token() else {
+            break;
+        };
+        // the token with its weight suffix and the separators following it
+        bytes_read = fi.position();
+        tokens += 1;
+        distinct.insert(token);
+        if half_sample.0 == 0 && bytes_read >= sample_bytes / 2 {
+            half_sample = (tokens, distinct.len());
+        }
+    }
+
+    if bytes_read >= file_size || half_sample.0 == 0 || half_sample.0 == tokens {
+        return Ok(distinct.len());
+    }
+    let exponent = ((distinct.len() as f64 / half_sample.1 as f64).ln()
+        / (tokens as f64 / half_sample.0 as f64).ln())
+    .clamp(0.0, 1.0);
+    let total_tokens = tokens as f64 * file_size as f64 / bytes_read as f64;
+    Ok((distinct.len() as f64 * (total_tokens / tokens as f64).powf(exponent)).round() as usize)
+}
+
 pub struct VocabularyIter<'a> {
     vocab: &'a Vocabulary,
     i: usize,
@@ -335,3 +1139,525 @@ impl<'a> IntoIterator for &'a Vocabulary {
         VocabularyIter { vocab: self, i: 0 }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nnet::{LcRandomGen, NeuralNet};
+    use crate::test_util::{
+        learn_vocab, net_of, temp_path, vocab_of, vocab_params, write_temp_file,
+    };
+
+    #[test]
+    fn a_blocked_probe_sequence_drops_the_word() {
+        let mut vocab = Vocabulary::with_table_size(16);
+        vocab.max_probes = 1;
+        vocab.add_word(String::from("</s>"));
+        // a word hashing to the slot of "</s>", which is never dropped
+        let home = get_word_hash_index("</s>", 16);
+        let word = (0..)
+            .map(|i| format!("w{i}"))
+            .find(|w| get_word_hash_index(w, 16) == home)
+            .unwrap();
+
+        assert_eq!(vocab.add_word(word.clone()), -1);
+        assert_eq!(vocab.len(), 1);
+        assert_eq!(vocab.search_word(&word), -1);
+        assert_eq!(vocab.search_word("</s>"), 0);
+        assert_eq!(vocab.max_probe_length(), 1);
+    }
+
+    #[test]
+    fn max_probe_length_counts_colliding_words() {
+        let mut vocab = Vocabulary::with_table_size(16);
+        assert_eq!(vocab.max_probe_length(), 0);
+        let colliding: Vec<String> = (0..)
+            .map(|i| format!("w{i}"))
+            .filter(|w| get_word_hash_index(w, 16) == 5)
+            .take(3)
+            .collect();
+        for word in &colliding {
+            vocab.add_word_with_count(word.clone(), 1);
+        }
+        assert_eq!(vocab.max_probe_length(), 3);
+        for (idx, word) in colliding.iter().enumerate() {
+            assert_eq!(vocab.search_word(word), idx as i32);
+        }
+    }
+
+    #[test]
+    fn overlap_of_partly_shared_vocabularies() {
+        let first = vocab_of(&[("</s>", 9), ("a", 4), ("b", 2), ("c", 1)]);
+        let second = vocab_of(&[("</s>", 7), ("d", 5), ("b", 3), ("c", 1)]);
+        let stats = first.overlap(&second);
+        assert_eq!(stats.shared_words, 2);
+        assert_eq!(stats.jaccard, 0.5);
+        // min(2, 3) + min(1, 1) over 4 + 3 + 1 + 5
+        assert!((stats.weighted_jaccard - 3.0 / 13.0).abs() < 1e-12);
+        assert_eq!(second.overlap(&first), stats);
+    }
+
+    #[test]
+    fn both_reductions_keep_the_same_words() {
+        let surviving = |fast_reduce: bool| -> Vec<String> {
+            let mut vocab = Vocabulary::with_table_size(256);
+            vocab.fast_reduce = fast_reduce;
+            vocab.add_word_with_count(String::from("</s>"), 1);
+            for i in 0..100u32 {
+                vocab.add_word_with_count(format!("w{i}"), i * 7 % 5 + 1);
+            }
+            vocab.reduce_vocab();
+            vocab.reduce_vocab();
+            assert_eq!(vocab.word(0), "</s>");
+            for (idx, word) in vocab.into_iter().enumerate() {
+                assert_eq!(vocab.search_word(word), idx as i32);
+            }
+            let mut words: Vec<String> = vocab.into_iter().map(String::from).collect();
+            words.sort();
+            words
+        };
+        let words = surviving(false);
+        // the words counted 3 times or more
+        assert_eq!(words.len(), 61);
+        assert_eq!(surviving(true), words);
+    }
+
+    #[test]
+    fn idf_of_common_and_rare_words() {
+        let file = write_temp_file(
+            "idf.txt",
+            "the cat sat\nthe dog ran\nthe cat ran\nthe bird\n\nzebra the\n",
+        );
+        let mut vocab = learn_vocab(&file);
+        assert_eq!(vocab.idf("the"), None);
+        vocab
+            .count_documents(&file, &TokenizerParams::default())
+            .unwrap();
+        assert!(vocab.idf("the").unwrap().abs() < 1e-12);
+        assert!((vocab.idf("cat").unwrap() - (5.0f64 / 2.0).ln()).abs() < 1e-12);
+        assert!((vocab.idf("zebra").unwrap() - 5.0f64.ln()).abs() < 1e-12);
+        assert_eq!(vocab.idf("unicorn"), None);
+    }
+
+    #[test]
+    fn most_frequent_of_sorted_and_unsorted_vocabularies() {
+        let sorted = vocab_of(&[("</s>", 50), ("the", 9), ("a", 4)]);
+        assert_eq!(sorted.most_frequent(), Some((1, "the", 9)));
+        let unsorted = vocab_of(&[("</s>", 50), ("a", 4), ("the", 9), ("of", 9)]);
+        assert_eq!(unsorted.most_frequent(), Some((2, "the", 9)));
+        assert_eq!(vocab_of(&[("</s>", 50)]).most_frequent(), None);
+    }
+
+    #[test]
+    fn estimated_size_gives_a_small_working_table() {
+        let mut vocab = Vocabulary::with_estimated_size(1000);
+        // the next power of two above 1001 / 0.7
+        assert_eq!(vocab.hash_table_size(), 2048);
+
+        let words: String = (0..1000).map(|i| format!("w{i} ")).collect();
+        let file = write_temp_file("estimated-size.txt", &words);
+        let params = VocabParams {
+            min_count: 1,
+            estimated_words: Some(1000),
+            ..Default::default()
+        };
+        let learned = Vocabulary::learn_vocabulary_with_params(&file, &params).unwrap();
+        assert_eq!(learned.hash_table_size(), 2048);
+        assert_eq!(learned.len(), 1001);
+
+        assert_eq!(vocab.add_missing_words(&learned), 1001);
+        assert_eq!(vocab.hash_table_size(), 2048);
+        for v in [&learned, &vocab] {
+            for i in 0..1000 {
+                let word = format!("w{i}");
+                assert_eq!(v.word(v.search_word(&word) as usize), word);
+            }
+        }
+    }
+
+    #[test]
+    fn words_missing_from_gives_both_differences() {
+        let first = vocab_of(&[("</s>", 9), ("a", 4), ("b", 2), ("c", 1)]);
+        let second = vocab_of(&[("</s>", 7), ("d", 5), ("b", 3), ("e", 2)]);
+        assert_eq!(first.words_missing_from(&second), vec![("a", 4), ("c", 1)]);
+        assert_eq!(second.words_missing_from(&first), vec![("d", 5), ("e", 2)]);
+        assert!(first.words_missing_from(&first).is_empty());
+    }
+
+    #[test]
+    fn min_count_for_target_size_keeps_at_most_the_target() {
+        let vocab = vocab_of(&[
+            ("</s>", 20),
+            ("a", 10),
+            ("b", 8),
+            ("c", 8),
+            ("d", 5),
+            ("e", 3),
+            ("f", 1),
+        ]);
+        let kept = |min_count: u32| {
+            (1..vocab.len())
+                .filter(|&i| vocab.word_count(i) >= min_count)
+                .count()
+        };
+        assert_eq!(vocab.min_count_for_target_size(3), 6);
+        assert_eq!(kept(6), 3);
+        // the tie of "b" and "c" can't be split
+        assert_eq!(vocab.min_count_for_target_size(2), 9);
+        assert_eq!(kept(9), 1);
+        assert_eq!(vocab.min_count_for_target_size(6), 1);
+        for target in 0..8 {
+            assert!(kept(vocab.min_count_for_target_size(target)) <= target);
+        }
+    }
+
+    #[test]
+    fn sketched_counts_of_frequent_words_are_close() {
+        let mut contents = String::new();
+        for i in 0..100 {
+            for _ in 0..500 / (i + 1) {
+                contents.push_str(&format!("w{i} "));
+            }
+            contents.push('\n');
+        }
+        let file = write_temp_file("sketched-counts.txt", &contents);
+        let exact = learn_vocab(&file);
+        let params = VocabParams {
+            sketch_width: Some(64),
+            ..vocab_params()
+        };
+        let sketched = Vocabulary::learn_vocabulary_with_params(&file, &params).unwrap();
+        let error_bound = std::f64::consts::E * exact.train_words() as f64 / 64.0;
+        for i in 0..10 {
+            let word = format!("w{i}");
+            let exact_count = exact.word_count(exact.search_word(&word) as usize);
+            let sketched_count = sketched.word_count(sketched.search_word(&word) as usize);
+            assert!(sketched_count >= exact_count);
+            assert!((sketched_count - exact_count) as f64 <= error_bound);
+        }
+    }
+
+    #[test]
+    fn growth_never_decreases_and_ends_at_the_vocabulary_size() {
+        let file = write_temp_file(
+            "growth.txt",
+            "a a b c a d b e f a g h\ni a j b k c l\nm n o a b c\n",
+        );
+        let mut reports = Vec::new();
+        let vocab =
+            Vocabulary::learn_vocabulary_with_growth(&file, &vocab_params(), 4, |tokens, words| {
+                reports.push((tokens, words))
+            })
+            .unwrap();
+        // 25 words and 3 "</s>" tokens, reported every 4 tokens
+        assert_eq!(reports.len(), 7);
+        assert!(
+            reports
+                .windows(2)
+                .all(|w| w[0].0 < w[1].0 && w[0].1 <= w[1].1)
+        );
+        assert_eq!(*reports.last().unwrap(), (28, vocab.len()));
+    }
+
+    #[test]
+    fn loaded_models_have_no_training_tables() {
+        let vocab = vocab_of(&[("</s>", 0), ("apple", 3), ("pear", 2), ("car", 1)]);
+        let net = net_of(&[&[0.0, 0.0], &[1.0, 0.0], &[0.9, 0.1], &[0.0, 1.0]]);
+        for binary in [true, false] {
+            let file = temp_path(&format!("loaded-model-{binary}.bin"));
+            net.save(&vocab, &file, binary).unwrap();
+            let (loaded_vocab, loaded) = if binary {
+                NeuralNet::load_binary(&file).unwrap()
+            } else {
+                NeuralNet::load_text(&file).unwrap()
+            };
+            assert!(loaded_vocab.unigram_table.is_empty());
+            assert!(loaded_vocab.hash_table_size() < 100);
+            let neighbors = loaded.most_similar(&loaded_vocab, "apple", 1);
+            assert_eq!(neighbors[0].0, "pear");
+        }
+    }
+
+    #[test]
+    fn the_word_cap_keeps_the_most_frequent_words() {
+        // 20 frequent words and 480 words occurring once
+        let corpus: String = (0..480)
+            .map(|i| format!("f{} f{} r{i}\n", i % 20, (i + 1) % 20))
+            .collect();
+        let file = write_temp_file("max-words.txt", &corpus);
+        let params = VocabParams {
+            max_words: Some(100),
+            ..vocab_params()
+        };
+        let mut max_len: usize = 0;
+        let vocab = Vocabulary::learn_vocabulary_with_growth(&file, &params, 1, |_, words| {
+            max_len = max_len.max(words)
+        })
+        .unwrap();
+        assert!(max_len <= 100, "{max_len}");
+        assert!(vocab.len() <= 100);
+        for i in 0..20 {
+            assert!(vocab.search_word(&format!("f{i}")) > 0, "f{i}");
+        }
+    }
+
+    #[test]
+    fn vocabulary_size_is_estimated_from_a_sample() {
+        // word ids drawn log-uniformly, a roughly Zipfian distribution
+        let mut rand_gen = LcRandomGen::new(3);
+        let mut corpus = String::new();
+        let mut ids: HashSet<u32> = HashSet::new();
+        for token in 1..=50_000 {
+            let r = ((rand_gen.next_rand() >> 16) % 65536) as f64 / 65536.0;
+            let id = (r * 20_000f64.ln()).exp() as u32;
+            ids.insert(id);
+            corpus.push_str(&format!("w{id}"));
+            corpus.push(if token % 20 == 0 { '\n' } else { ' ' });
+        }
+        let file = write_temp_file("estimate-vocab.txt", &corpus);
+        // and "</s>"
+        let actual = ids.len() + 1;
+
+        let tokenizer = TokenizerParams::default();
+        let estimate = estimate_vocab_size(&file, corpus.len() as u64 / 10, &tokenizer).unwrap();
+        assert!(
+            estimate * 2 > actual && estimate < actual * 2,
+            "{estimate} {actual}"
+        );
+        assert_eq!(
+            estimate_vocab_size(&file, corpus.len() as u64, &tokenizer).unwrap(),
+            actual
+        );
+    }
+
+    #[test]
+    fn vocabulary_size_estimate_splits_tokens_like_the_tokenizer() {
+        // each word gets one of 4 weights, and is capitalized when it starts a line
+        let mut rand_gen = LcRandomGen::new(5);
+        let mut corpus = String::new();
+        for line in 0..2000 {
+            for token in 0..10 {
+                let id = (rand_gen.next_rand() >> 16) as u64 % 500;
+                let weight =
+                    ["0.25", "0.5", "0.75", "1"][(rand_gen.next_rand() >> 16) as usize % 4];
+                let word = if token == 0 { "W" } else { "w" };
+                corpus.push_str(&format!("{word}{id}:{weight} "));
+            }
+            corpus.push_str(if line % 2 == 0 { "\n" } else { "\n\n" });
+        }
+        let file = write_temp_file("estimate-vocab-tokenizer.txt", &corpus);
+        let tokenizer = TokenizerParams {
+            token_weights: true,
+            lowercase_sentence_start: true,
+            collapse_separators: true,
+            ..Default::default()
+        };
+        let vocab = Vocabulary::learn_vocabulary_with_params(
+            &file,
+            &VocabParams {
+                tokenizer: tokenizer.clone(),
+                ..vocab_params()
+            },
+        )
+        .unwrap();
+        let actual = vocab.len();
+        assert!(actual <= 501, "{actual}");
+
+        assert_eq!(
+            estimate_vocab_size(&file, corpus.len() as u64, &tokenizer).unwrap(),
+            actual
+        );
+        let estimate = estimate_vocab_size(&file, corpus.len() as u64 / 10, &tokenizer).unwrap();
+        assert!(
+            estimate * 2 > actual && estimate < actual * 2,
+            "{estimate} {actual}"
+        );
+        // the default splitting counts each weight and capitalization separately
+        let default = TokenizerParams::default();
+        assert!(estimate_vocab_size(&file, corpus.len() as u64, &default).unwrap() > actual * 4);
+    }
+
+    #[test]
+    fn words_with_equal_counts_are_sorted_alphabetically() {
+        let file = write_temp_file(
+            "equal-counts.txt",
+            "pear kiwi fig apple kiwi pear fig apple plum\n",
+        );
+        let vocab = learn_vocab(&file);
+        let words: Vec<&str> = vocab.into_iter().collect();
+        assert_eq!(words, ["</s>", "apple", "fig", "kiwi", "pear", "plum"]);
+    }
+
+    #[test]
+    fn saved_tables_behave_like_rebuilt_ones() {
+        let file = write_temp_file(
+            "tables.txt",
+            "the cat sat on the mat\nthe dog sat on the log\n",
+        );
+        let vocab = learn_vocab(&file);
+        let tables_file = temp_path("tables.vocab");
+        vocab.save_with_tables(&tables_file).unwrap();
+        let loaded = Vocabulary::load_with_tables(&tables_file).unwrap();
+        let vocab_file = temp_path("tables-rebuilt.vocab");
+        vocab.save_to_file(&vocab_file).unwrap();
+        let rebuilt = Vocabulary::load_from_file(&vocab_file).unwrap();
+
+        assert_eq!(loaded.hash_table, vocab.hash_table);
+        assert_eq!(loaded.unigram_table, vocab.unigram_table);
+        for word in ["</s>", "the", "cat", "log", "bird", ""] {
+            assert_eq!(loaded.search_word(word), vocab.search_word(word), "{word}");
+            assert_eq!(
+                loaded.search_word(word),
+                rebuilt.search_word(word),
+                "{word}"
+            );
+        }
+    }
+
+    #[test]
+    fn corrupted_table_sizes_are_rejected() {
+        let file = write_temp_file("corrupted-tables.txt", "the cat sat on the mat\n");
+        let vocab = learn_vocab(&file);
+        let tables_file = temp_path("corrupted-tables.vocab");
+        vocab.save_with_tables(&tables_file).unwrap();
+        let saved = std::fs::read(&tables_file).unwrap();
+        let header_len = TABLES_FILE_MAGIC.len();
+        let sizes_len = saved[header_len..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .unwrap();
+        let words_len = vocab.len();
+        let hash_table_len = vocab.hash_table.len();
+        let unigram_table_len = vocab.unigram_table.len();
+
+        let rejected = |sizes: String| {
+            let mut corrupted = saved[..header_len].to_vec();
+            corrupted.extend_from_slice(sizes.as_bytes());
+            corrupted.extend_from_slice(&saved[header_len + sizes_len..]);
+            std::fs::write(&tables_file, corrupted).unwrap();
+            let result = Vocabulary::load_with_tables(&tables_file);
+            matches!(result, Err(err) if err.kind() == ErrorKind::InvalidData)
+        };
+        assert!(rejected(format!(
+            "{words_len} {} {unigram_table_len}",
+            1u64 << 60
+        )));
+        assert!(rejected(format!(
+            "{words_len} {hash_table_len} {}",
+            usize::MAX
+        )));
+        assert!(rejected(format!("{words_len} {hash_table_len} 0")));
+        assert!(rejected(format!(
+            "{} {hash_table_len} {unigram_table_len}",
+            1u64 << 40
+        )));
+        assert!(!rejected(format!(
+            "{words_len} {hash_table_len} {unigram_table_len}"
+        )));
+    }
+
+    #[test]
+    fn mostly_single_word_lines_are_suspicious() {
+        let file = write_temp_file(
+            "singletons.txt",
+            "apple\npear\n\nkiwi rare\napple pear kiwi\nfig\napple\n",
+        );
+        let tokenizer = TokenizerParams::default();
+        let singletons = learn_vocab(&file)
+            .count_singleton_sentences(&file, &tokenizer)
+            .unwrap();
+        // empty lines aren't sentences
+        assert_eq!(
+            singletons,
+            SingletonSentences {
+                sentences: 6,
+                singletons: 4
+            }
+        );
+        assert!(singletons.is_suspicious());
+
+        // out-of-vocabulary words don't count
+        let vocab = vocab_of(&[
+            ("</s>", 0),
+            ("apple", 3),
+            ("pear", 2),
+            ("kiwi", 2),
+            ("fig", 1),
+        ]);
+        let singletons = vocab.count_singleton_sentences(&file, &tokenizer).unwrap();
+        assert_eq!(singletons.singletons, 5);
+
+        let prose = write_temp_file("no-singletons.txt", "the cat sat\nthe dog ran\nfig\n");
+        let singletons = learn_vocab(&prose)
+            .count_singleton_sentences(&prose, &tokenizer)
+            .unwrap();
+        assert_eq!(singletons.fraction(), 1.0 / 3.0);
+        assert!(!singletons.is_suspicious());
+    }
+
+    #[test]
+    fn banded_negatives_of_a_frequent_word_are_frequent() {
+        let words: Vec<String> = (1..=100).map(|i| format!("w{i}")).collect();
+        let mut counts: Vec<(&str, u32)> = vec![("</s>", 0)];
+        counts.extend(
+            words
+                .iter()
+                .enumerate()
+                .map(|(i, w)| (w.as_str(), 1000 - 10 * i as u32)),
+        );
+        let vocab = vocab_of(&counts);
+        let band_size = 100 / NEGATIVE_SAMPLING_BANDS;
+
+        let mut rand_gen = LcRandomGen::new(1);
+        let mut drawn: HashSet<i32> = HashSet::new();
+        for _ in 0..1000 {
+            let negative = vocab.sample_word_in_band(1, rand_gen.next_rand());
+            assert!((1..=band_size as i32).contains(&negative), "{negative}");
+            drawn.insert(negative);
+        }
+        // the whole band is used
+        assert_eq!(drawn.len(), band_size);
+        // and the rarest words get the rarest band
+        let rare = vocab.sample_word_in_band(100, rand_gen.next_rand());
+        assert!(rare > (100 - band_size) as i32, "{rare}");
+    }
+
+    #[test]
+    fn pow_sum_is_recomputed_after_the_counts_change() {
+        let fresh_sum = |vocab: &Vocabulary| -> f64 {
+            (0..vocab.len())
+                .map(|idx| (vocab.word_count(idx) as f64).powf(0.75))
+                .sum()
+        };
+        let mut vocab = vocab_of(&[("</s>", 0), ("apple", 16), ("pear", 81)]);
+        assert!(vocab.pow_sum.get().is_none());
+        assert!((vocab.pow_sum() - 35.0).abs() < 1e-9);
+        assert!(vocab.pow_sum.get().is_some());
+
+        vocab.add_missing_words(&vocab_of(&[("</s>", 0), ("kiwi", 1)]));
+        assert!(vocab.pow_sum.get().is_none());
+        vocab.add_word_occurrences(String::from("kiwi"), 15);
+        assert!((vocab.pow_sum() - fresh_sum(&vocab)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rare_words_are_merged_into_unk() {
+        let file = write_temp_file("merge-rare.txt", "a b a b a c\nd a b e c f\n");
+        let params = VocabParams {
+            min_count: 2,
+            merge_rare_words: true,
+            ..vocab_params()
+        };
+        let vocab = Vocabulary::learn_vocabulary_with_params(&file, &params).unwrap();
+        let words: Vec<(&str, u32)> = (1..vocab.len())
+            .map(|idx| (vocab.word(idx), vocab.word_count(idx)))
+            .collect();
+        // d, e and f occur once
+        assert_eq!(words, [("a", 4), ("<UNK>", 3), ("b", 3), ("c", 2)]);
+        for word in ["d", "e", "f", "unseen"] {
+            assert_eq!(vocab.search_word(word), -1);
+            assert_eq!(vocab.word_index_or_unknown(word), 2);
+        }
+        assert_eq!(vocab.word_index_or_unknown("b"), 3);
+    }
+}